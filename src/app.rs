@@ -1,20 +1,42 @@
 pub mod api;
+pub mod behaviors;
+pub mod cargo;
+pub mod catalogue;
+pub mod config;
+pub mod contract_runner;
+pub mod contracts;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod control_api;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod database;
+pub mod events;
+pub mod exploration;
+pub mod fleet_ops;
+pub mod fleet_table;
+pub mod groups;
+pub mod history;
+pub mod inspector;
+pub mod ledger;
+pub mod maintenance;
+pub mod map;
+pub mod mounts;
+pub mod navigation;
+pub mod notifications;
+pub mod persistence;
+pub mod route_runner;
+pub mod scripting;
+pub mod swarms;
 
-use std::env;
 #[allow(unused)]
 use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Mutex};
 
+use crossbeam_queue::SegQueue;
 use pollster::FutureExt as _;
-#[allow(unused)]
-use tokio::io::{AsyncWriteExt};
-#[allow(unused)]
-use tokio::net::TcpListener; // provides Future.block_on()
 
 use spacedust::apis::agents_api::*;
-#[allow(unused)]
-use spacedust::apis::contracts_api::*;
 use spacedust::apis::fleet_api::*;
+#[allow(unused)]
 use spacedust::apis::systems_api::*;
 
 use spacedust::apis::configuration::Configuration;
@@ -25,9 +47,42 @@ use spacedust::models::register_request::{Faction, RegisterRequest};
 
 use spacedust::models::*;
 
+use crate::message_handler::Message;
 use crate::spacetraders::ShipyardShipWithWaypoint;
 
-use self::api::spacetraders::{Render, RenderWithWaypoints, ShipWithNav, SpaceTraders};
+use self::behaviors::Behaviors;
+use self::cargo::{co_located, CargoTransfers, Transfer};
+use self::catalogue::{SystemCatalogue, CATALOGUE_PATH};
+use self::config::Config;
+use self::contract_runner::{estimate_profit, ContractRunner};
+use self::contracts::is_complete;
+#[cfg(not(target_arch = "wasm32"))]
+use self::database::{Database, DatabaseView};
+use self::events::{Event, EventLog};
+use self::exploration::{has_sensors, Exploration};
+use self::fleet_ops::ORDERS_AVAILABLE;
+use self::fleet_table::{automated_ships, fleet_rows, Automation, FleetTable};
+use self::groups::FleetGroups;
+use self::history::{estimate_fleet_value, CreditHistory};
+use self::inspector::render_ship_details;
+use self::ledger::{Ledger, LedgerEntry, TransactionKind};
+use self::maintenance::{Maintenance, MaintenanceAction};
+use self::map::MapView;
+use self::mounts::{check_at_shipyard, render_mounts, MountAction};
+use self::navigation::{
+    can_warp_to, plan_route, target_system, warp_arrival, warp_estimate, warp_targets,
+    JumpGateInfo, RouteStep,
+};
+use self::notifications::Notifications;
+use self::persistence::stale_label;
+use self::route_runner::Routes;
+use self::scripting::ScriptRunner;
+use self::swarms::Swarms;
+
+use self::api::spacetraders::{
+    FleetSummary, Render, RenderWithFleet, RenderWithWaypoints, ShipAction, ShipWithNav,
+    SpaceTraders,
+};
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
@@ -42,29 +97,87 @@ pub struct AppData {
     value: f32,
     #[serde(skip)]
     conf: Configuration,
+    #[serde(skip)]
+    config: Config,
+    agent: Option<Agent>,
+    history: CreditHistory,
+    ledger: Ledger,
     contracts: Vec<Contract>,
+    contract_runner: ContractRunner,
     ships: Vec<ShipWithNav>,
     waypoints: Vec<Waypoint>,
+    markets: Vec<Market>,
+    jump_gates: Vec<JumpGateInfo>,
+    /// Kept in its own file, see `catalogue::CATALOGUE_PATH`
+    #[serde(skip)]
+    catalogue: SystemCatalogue,
+    #[serde(skip)]
+    catalogue_import_path: String,
+    map: MapView,
+    routes: Routes,
+    exploration: Exploration,
+    scripts: ScriptRunner,
+    behaviors: Behaviors,
+    groups: FleetGroups,
+    fleet_table: FleetTable,
+    maintenance: Maintenance,
+    #[serde(skip)]
+    transfers: CargoTransfers,
+    swarms: Swarms,
+    notifications: Notifications,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    db: Option<Database>,
+    #[cfg(not(target_arch = "wasm32"))]
+    database_view: DatabaseView,
     shipyard_waypoint: Option<Waypoint>,
     shipyard_ships: Option<Vec<ShipyardShipWithWaypoint>>,
     log: Vec<String>,
+    #[serde(skip)]
+    events: EventLog,
+    /// Data restored from the last session that hasn't been fetched again yet, by the
+    /// message that refreshes it
+    #[serde(skip)]
+    stale: Vec<Message>,
+    /// Set once the last session has been restored, or there was none. Messages wait
+    /// for it so a restore can't overwrite what they fetch
+    #[serde(skip)]
+    loaded: bool,
+    /// Messages to handle ahead of the schedule, shared with the `Scheduler`
+    #[serde(skip)]
+    requested: Arc<SegQueue<Message>>,
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
 pub struct AppState {
     data: Arc<Mutex<AppData>>,
 }
 
 impl AppState {
-    pub fn new(_cc: &eframe::CreationContext<'_>, data: Arc<Mutex<AppData>>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, data: Arc<Mutex<AppData>>) -> Self {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        //if let Some(storage) = cc.storage {
-        //return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
-        //}
+        if let Some(storage) = cc.storage {
+            let mut current = data.lock().unwrap();
+            match persistence::load(storage) {
+                Ok(Some(saved)) => current.restore(saved),
+                Ok(None) => {}
+                Err(e) => current
+                    .log
+                    .push(format!("Could not restore last session: {}", e)),
+            }
+            #[cfg(target_arch = "wasm32")]
+            match persistence::load_config(storage) {
+                Ok(Some(config)) => current.set_config(config),
+                Ok(None) => {}
+                Err(e) => current
+                    .log
+                    .push(format!("Could not restore settings: {}", e)),
+            }
+        }
+        data.lock().unwrap().loaded = true;
         Self { data }
     }
 }
@@ -76,32 +189,200 @@ impl Default for AppData {
             test: SpaceTraders {},
             value: 2.7,
             conf: Configuration::new(),
+            config: Config::default(),
+            agent: None,
+            history: CreditHistory::default(),
+            ledger: Ledger::default(),
             contracts: vec![],
+            contract_runner: ContractRunner::default(),
             ships: vec![],
             waypoints: vec![],
+            markets: vec![],
+            jump_gates: vec![],
+            catalogue: SystemCatalogue::default(),
+            catalogue_import_path: "systems.json".to_owned(),
+            map: MapView::default(),
+            routes: Routes::default(),
+            exploration: Exploration::default(),
+            scripts: ScriptRunner::default(),
+            behaviors: Behaviors::default(),
+            groups: FleetGroups::default(),
+            fleet_table: FleetTable::default(),
+            maintenance: Maintenance::default(),
+            transfers: CargoTransfers::default(),
+            swarms: Swarms::default(),
+            notifications: Notifications::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            db: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            database_view: DatabaseView::default(),
             shipyard_waypoint: None,
             shipyard_ships: None,
             log: vec![],
+            events: EventLog::default(),
+            stale: vec![],
+            loaded: false,
+            requested: Arc::new(SegQueue::new()),
         }
     }
 }
 
 impl AppData {
     /// Called once before the first frame.
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
 
         let mut state = AppData::default();
-        state.conf.bearer_access_token = Some(
-            env::var("SPACETRADERS_TOKEN")
-                .expect("SPACETRADERS_TOKEN environment variable must be set"),
-        );
+        state.catalogue = SystemCatalogue::load(CATALOGUE_PATH);
+        #[cfg(not(target_arch = "wasm32"))]
+        match Database::open(config.database_path()) {
+            Ok(db) => state.db = Some(db),
+            Err(e) => state.log.push(format!("Failed to open database: {}", e)),
+        }
+        config.apply(&mut state.conf);
+        if state.conf.bearer_access_token.is_none() {
+            state
+                .log
+                .push("No token configured, enter one in the Connect window".to_owned());
+        }
+        state.config = config;
         state
     }
+
+    /// Takes the agent from any response that carries one, so the credit history sees
+    /// every change and not just the ones the agent poll happens to catch
+    pub fn update_agent(&mut self, agent: Agent) {
+        let fleet_value = estimate_fleet_value(&self.ships, &self.shipyard_ships);
+        self.history
+            .record_credits(&agent.symbol, agent.credits as i64, fleet_value);
+        self.agent = Some(agent);
+    }
+
+    /// Adds a transaction to the ledger and, for costs, the spending history
+    pub fn record_transaction(&mut self, entry: LedgerEntry) {
+        if let Some(category) = entry.kind.spending_category() {
+            self.history.record_spending(category, -entry.amount);
+        }
+        if entry.kind == TransactionKind::Sale {
+            self.emit(Event::CargoSold {
+                ship: entry.ship_symbol.clone(),
+                trade_symbol: entry.trade_symbol.clone(),
+                units: entry.units,
+                credits: entry.amount,
+            });
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.store(|db, _| db.store_transaction(&entry));
+        self.ledger.record(entry);
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Logs a failure and passes it on to anything watching the event stream
+    pub fn report_error(&mut self, message: String) {
+        self.log.push(message.clone());
+        // Raised once where it happened, not again at every level it's passed up through
+        if !self.events.repeats_last_error(&message) {
+            self.emit(Event::Error { message });
+        }
+    }
+
+    /// Takes over everything from a saved session, keeping the API configuration,
+    /// database and anything else that only lives for this run. Fetched data is marked
+    /// stale until the message handler refreshes it
+    pub fn restore(&mut self, mut saved: AppData) {
+        saved.conf = std::mem::take(&mut self.conf);
+        saved.config = std::mem::take(&mut self.config);
+        saved.catalogue = std::mem::take(&mut self.catalogue);
+        saved.catalogue_import_path = std::mem::take(&mut self.catalogue_import_path);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            saved.db = self.db.take();
+        }
+        saved.log.append(&mut self.log);
+        saved.events = std::mem::take(&mut self.events);
+        saved.requested = self.requested.clone();
+        saved.stale = vec![
+            Message::GetAgent,
+            Message::GetFleet,
+            Message::GetWaypoints,
+            Message::GetContracts,
+            Message::GetMarkets,
+        ];
+        saved.log.push("Restored last session".to_owned());
+        *self = saved;
+    }
+
+    /// Takes a ship out of every automation, e.g. once it's been scrapped
+    pub fn forget_ship(&mut self, ship_symbol: &str) {
+        self.contract_runner
+            .jobs
+            .retain(|j| j.ship_symbol != ship_symbol);
+        self.routes.cancel(ship_symbol);
+        self.exploration.set_exploring(ship_symbol, false);
+        self.behaviors
+            .assignments
+            .retain(|a| a.ship_symbol != ship_symbol);
+        self.scripts.assign(ship_symbol, None);
+        self.groups.set_group(ship_symbol, None);
+        self.groups.set_role(ship_symbol, None);
+        self.swarms.drop_ship(ship_symbol);
+    }
+
+    /// Queue for messages to handle next, for the `Scheduler` to share
+    pub fn requests(&self) -> Arc<SegQueue<Message>> {
+        self.requested.clone()
+    }
+
+    /// Asks for a message to be handled next, ahead of the schedule
+    pub fn request(&self, message: Message) {
+        self.requested.push(message);
+    }
+
+    /// Whether the last session has been restored, so messages can be handled
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Switches to new settings, pointing the API client at them
+    pub fn set_config(&mut self, config: Config) {
+        config.apply(&mut self.conf);
+        self.config = config;
+    }
+
+    pub fn is_stale(&self, message: Message) -> bool {
+        self.stale.contains(&message)
+    }
+
+    /// Called once a message has fetched fresh data
+    pub fn mark_fresh(&mut self, message: Message) {
+        self.stale.retain(|m| *m != message);
+    }
+
+    /// Writes to the database, if one is open, logging any failure
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn store(&mut self, write: impl FnOnce(&Database, &AppData) -> rusqlite::Result<()>) {
+        let result = match &self.db {
+            Some(db) => write(db, self),
+            None => return,
+        };
+        if let Err(e) = result {
+            self.log.push(format!("Database write failed: {}", e));
+        }
+    }
 }
 
 impl eframe::App for AppState {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, self);
+        let mut data = self.data.lock().unwrap();
+        if let Err(e) = persistence::save(storage, &data) {
+            data.log.push(format!("Failed to save: {}", e));
+        }
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
@@ -133,56 +414,363 @@ impl eframe::App for AppState {
                 *&mut data.value += 1.0;
             }
 
-            if ui.button("Get info").clicked() {
-                match get_my_agent(&data.conf).block_on() {
-                    Ok(res) => {
-                        println!("{:#?}", res);
-                        match get_waypoint(
-                            &data.conf,
-                            res.data.headquarters.rsplit_once("-").unwrap().0,
-                            &res.data.headquarters,
-                        )
-                        .block_on()
-                        {
-                            Ok(w) => data.log.push(format!("{:?}", w)),
-                            Err(_) => data.log.push("Failed to get waypoint info".to_owned()),
-                        }
+            egui::Window::new("Agent").show(ctx, |ui| {
+                stale_label(ui, data.is_stale(Message::GetAgent));
+                match &mut data.agent {
+                    Some(agent) => agent.render(ui, &data.conf),
+                    None => {
+                        ui.label("No agent info fetched yet");
                     }
-                    Err(err_res) => {
-                        panic!("{:#?}", err_res);
+                }
+                ui.separator();
+                FleetSummary::from_ships(&data.ships).render(ui, &data.conf);
+                let ships: Vec<Ship> = data.ships.iter().map(|s| s.ship.clone()).collect();
+                data.maintenance.render_alert(ui, &ships);
+
+                orders_only_on_desktop(ui);
+                if ui.button("Refresh").clicked() {
+                    match get_my_agent(&data.conf).block_on() {
+                        Ok(res) => data.update_agent(*res.data),
+                        Err(_) => data.log.push("Failed to get agent info".to_owned()),
                     }
                 }
-                println!("\n");
-            }
+            });
+
+            egui::Window::new("Credits")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    data.history.render(ui);
+                });
 
             egui::Window::new("Contracts")
                 .vscroll(true)
                 .show(ctx, |ui| {
+                    stale_label(ui, data.is_stale(Message::GetContracts));
+                    orders_only_on_desktop(ui);
                     if data.contracts.len() == 0 {
                         ui.label("No contracts available or accepted");
                     }
+                    let mut accept: Option<String> = None;
+                    let mut fulfil: Option<String> = None;
                     for contract in &mut data.contracts {
-                        contract.render(ui, &data.conf);
+                        contract.render_with_fleet(
+                            ui,
+                            &data.conf,
+                            &mut data.ships,
+                            &data.waypoints,
+                        );
+
+                        if !contract.accepted {
+                            if ui.button("Accept!").clicked() {
+                                accept = Some(contract.id.clone());
+                            }
+                        } else if !contract.fulfilled && is_complete(contract) {
+                            if ui.button("Fulfil").clicked() {
+                                fulfil = Some(contract.id.clone());
+                            }
+                        }
+
+                        if !contract.fulfilled {
+                            estimate_profit(contract, &data.ships, &data.markets, &data.waypoints)
+                                .render(ui);
+
+                            let mut automated = data.contract_runner.is_automated(&contract.id);
+                            if ui.checkbox(&mut automated, "Automate").changed() {
+                                data.contract_runner.set_automated(&contract.id, automated);
+                            }
+                            for job in &data.contract_runner.jobs {
+                                if job.contract_id == contract.id {
+                                    ui.label(format!(
+                                        "\t{} sourcing {} from {:?}",
+                                        job.ship_symbol, job.trade_symbol, job.source
+                                    ));
+                                }
+                            }
+                        }
+                        ui.separator();
+                    }
+                    if let Some(id) = accept {
+                        let _ = fleet_ops::accept(data, &id);
+                    }
+                    if let Some(id) = fulfil {
+                        let _ = fleet_ops::fulfill(data, &id);
+                    }
+
+                    let headquarters = data.agent.as_ref().map(|a| a.headquarters.clone());
+                    let negotiators: Vec<String> = data
+                        .ships
+                        .iter()
+                        .filter(|s| {
+                            matches!(s.ship.nav.status, ShipNavStatus::Docked)
+                                && Some(&s.ship.nav.waypoint_symbol) == headquarters.as_ref()
+                        })
+                        .map(|s| s.ship.symbol.clone())
+                        .collect();
+
+                    let mut negotiate: Option<String> = None;
+                    for ship_symbol in negotiators {
+                        if ui
+                            .button(format!("Negotiate contract with {}", ship_symbol))
+                            .clicked()
+                        {
+                            negotiate = Some(ship_symbol);
+                        }
+                    }
+                    if let Some(ship_symbol) = negotiate {
+                        let _ = fleet_ops::negotiate(data, &ship_symbol);
+                    }
+
+                    if ui.button("Fetch").clicked() {
+                        data.request(Message::GetContracts);
                     }
-                    if ui.button("Fetch").clicked() {}
                 });
 
             {
                 egui::Window::new("Fleet (simple)")
                     .vscroll(true)
                     .show(ctx, |ui| {
+                        stale_label(ui, data.is_stale(Message::GetFleet));
+                        orders_only_on_desktop(ui);
                         if data.ships.len() == 0 {
                             ui.label("No ships found in fleet");
                         }
-                        for ship in &mut data.ships {
-                            ship.render_with_waypoints(ui, &data.conf, &data.waypoints);
+                        data.groups.render_new_group(ui);
+
+                        // One section per group, then whatever isn't in one
+                        let mut sections: Vec<Option<String>> =
+                            data.groups.groups.iter().cloned().map(Some).collect();
+                        sections.push(None);
+                        let busy_exploring = automated_ships(data, Some(Automation::Exploration));
+                        let busy_travelling = automated_ships(data, Some(Automation::Route));
+
+                        for section in sections {
+                            let members: Vec<usize> = data
+                                .ships
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, s)| {
+                                    data.groups.group_of(&s.ship.symbol) == section.as_deref()
+                                })
+                                .map(|(i, _)| i)
+                                .collect();
+                            let title = match &section {
+                                Some(g) => format!("{} ({})", g, members.len()),
+                                None => format!("Ungrouped ({})", members.len()),
+                            };
+
+                            egui::CollapsingHeader::new(title)
+                                .id_source(format!("group_section_{:?}", section))
+                                .default_open(true)
+                                .show(ui, |ui| {
+                                    if let Some(group) = &section {
+                                        data.groups.render_commands(ui, group);
+                                    }
+                                    for i in members {
+                                        let ship = &mut data.ships[i];
+                                        data.groups.render_ship(ui, &ship.ship.symbol);
+                                        ship.render_with_waypoints(ui, &data.conf, &data.waypoints);
+                                        data.ledger.render_ship(ui, &ship.ship.symbol);
+
+                                        let mut exploring = data.exploration.is_exploring(&ship.ship.symbol);
+                                        let label = if has_sensors(ship) {
+                                            "Explore (chart and scan)"
+                                        } else {
+                                            "Explore (chart only, no sensors)"
+                                        };
+                                        let busy = busy_exploring.get(&ship.ship.symbol);
+                                        if ui
+                                            .add_enabled(busy.is_none(), egui::Checkbox::new(&mut exploring, label))
+                                            .on_disabled_hover_text(format!("Busy: {}", busy.cloned().unwrap_or_default()))
+                                            .changed()
+                                        {
+                                            data.exploration.set_exploring(&ship.ship.symbol, exploring);
+                                        }
+
+                                        egui::CollapsingHeader::new("Travel")
+                                            .id_source(format!("travel_{}", ship.ship.symbol))
+                                            .show(ui, |ui| {
+                                                if let Some(route) = data.routes.route_for(&ship.ship.symbol) {
+                                                    route.render(ui);
+                                                    if ui.button("Cancel route").clicked() {
+                                                        data.routes.cancel(&ship.ship.symbol);
+                                                    }
+                                                    return;
+                                                }
+                                                if let Some(automation) = busy_travelling.get(&ship.ship.symbol) {
+                                                    ui.label(format!("Busy: {}", automation));
+                                                    return;
+                                                }
+
+                                                // Anything outside the current system: other systems'
+                                                // waypoints and every system a known gate leads to
+                                                let mut targets: Vec<String> = data
+                                                    .waypoints
+                                                    .iter()
+                                                    .filter(|w| w.system_symbol != ship.ship.nav.system_symbol)
+                                                    .map(|w| w.symbol.clone())
+                                                    .collect();
+                                                for gate in &data.jump_gates {
+                                                    for system in &gate.gate.connected_systems {
+                                                        targets.push(system.symbol.clone());
+                                                    }
+                                                }
+                                                targets.extend(warp_targets(ship, &data.catalogue));
+                                                targets.sort();
+                                                targets.dedup();
+
+                                                egui::ComboBox::from_label("Travel to")
+                                                    .selected_text(&ship.route_target)
+                                                    .width(170.0)
+                                                    .show_ui(ui, |ui| {
+                                                        for t in targets {
+                                                            ui.selectable_value(
+                                                                &mut ship.route_target,
+                                                                t.clone(),
+                                                                t,
+                                                            );
+                                                        }
+                                                    });
+
+                                                if ship.route_target.len() > 0
+                                                    && ui.button("Start route").clicked()
+                                                {
+                                                    match plan_route(
+                                                        ship,
+                                                        &ship.route_target,
+                                                        &data.waypoints,
+                                                        &data.jump_gates,
+                                                        &data.catalogue,
+                                                    ) {
+                                                        Some(steps) => data.routes.start(
+                                                            &ship.ship.symbol,
+                                                            &ship.route_target,
+                                                            steps,
+                                                        ),
+                                                        None => data.log.push(format!(
+                                                            "No known jump gate connection or warp route to {}",
+                                                            ship.route_target
+                                                        )),
+                                                    }
+                                                }
+
+                                                let target_system = target_system(&ship.route_target, &data.waypoints);
+                                                let from = data.catalogue.get(&ship.ship.nav.system_symbol);
+                                                let to = data.catalogue.get(target_system);
+                                                if let (Some(from), Some(to)) = (from, to) {
+                                                    let estimate = warp_estimate(ship, from, to);
+                                                    ui.label(format!(
+                                                        "Warp: distance {:.0}, fuel {}, about {}s",
+                                                        estimate.distance, estimate.fuel, estimate.seconds
+                                                    ));
+
+                                                    if can_warp_to(ship, target_system, &data.catalogue)
+                                                        && ui.button("Warp").clicked()
+                                                    {
+                                                        let arrival = if target_system == ship.route_target {
+                                                            warp_arrival(to)
+                                                        } else {
+                                                            Some(ship.route_target.clone())
+                                                        };
+                                                        if let Some(arrival) = arrival {
+                                                            data.routes.start(
+                                                                &ship.ship.symbol,
+                                                                &ship.route_target,
+                                                                vec![RouteStep::Warp(arrival)],
+                                                            );
+                                                        }
+                                                    }
+                                                }
+                                            });
+                                    }
+                                });
                         }
                         if ui.button("Fetch").clicked() {}
                     });
 
+                egui::Window::new("Fleet")
+                    .vscroll(true)
+                    .hscroll(true)
+                    .show(ctx, |ui| {
+                        stale_label(ui, data.is_stale(Message::GetFleet));
+                        let rows = fleet_rows(data);
+                        data.fleet_table.render(ui, rows);
+                    });
+
+                if let Some(selected) = data.fleet_table.selected.clone() {
+                    let mut open = true;
+                    let mut mount_action: Option<MountAction> = None;
+                    let mut maintenance_action: Option<MaintenanceAction> = None;
+                    let mut transfer: Option<Transfer> = None;
+                    egui::Window::new(format!("Ship {}", selected))
+                        .id(egui::Id::new("ship_detail"))
+                        .open(&mut open)
+                        .vscroll(true)
+                        .show(ctx, |ui| {
+                            let ship = match data.ships.iter().find(|s| s.ship.symbol == selected)
+                            {
+                                Some(s) => s.ship.clone(),
+                                None => {
+                                    ui.label("Ship no longer in fleet");
+                                    return;
+                                }
+                            };
+                            render_ship_details(ui, &ship);
+                            #[cfg(not(target_arch = "wasm32"))]
+                            data.database_view.render_ship(ui, data.db.as_ref(), &ship);
+                            orders_only_on_desktop(ui);
+                            mount_action = render_mounts(ui, data, &ship);
+                            let at_shipyard = check_at_shipyard(data, &ship);
+                            maintenance_action =
+                                data.maintenance.render_ship(ui, &ship, at_shipyard);
+                            let partners = co_located(&data.ships, &ship);
+                            transfer = data.transfers.render(ui, &ship, &partners);
+                            ui.separator();
+
+                            if let Some(ship) =
+                                data.ships.iter_mut().find(|s| s.ship.symbol == selected)
+                            {
+                                ship.render_with_waypoints(ui, &data.conf, &data.waypoints);
+                                data.ledger.render_ship(ui, &selected);
+                            }
+                        });
+                    if !open {
+                        data.fleet_table.selected = None;
+                    }
+
+                    let _ = match mount_action {
+                        Some(MountAction::Refresh(ship)) => fleet_ops::refresh_mounts(data, &ship),
+                        Some(MountAction::Install(ship, mount)) => {
+                            fleet_ops::install(data, &ship, &mount)
+                        }
+                        Some(MountAction::Remove(ship, mount)) => {
+                            fleet_ops::uninstall(data, &ship, &mount)
+                        }
+                        None => Ok(()),
+                    };
+                    match maintenance_action {
+                        Some(MaintenanceAction::Estimate(ship)) => maintenance::estimate(data, &ship),
+                        Some(MaintenanceAction::Perform(ship, service)) => {
+                            let _ = maintenance::perform(data, &ship, service);
+                        }
+                        None => {}
+                    }
+                    if let Some(t) = transfer {
+                        let _ = fleet_ops::transfer(data, &t.from, &t.to, &t.trade_symbol, t.units);
+                    }
+                }
+
+                egui::Window::new("Profit & loss")
+                    .vscroll(true)
+                    .default_open(false)
+                    .show(ctx, |ui| {
+                        data.ledger.render(ui);
+                    });
+
                 egui::Window::new("Waypoints")
                     .vscroll(true)
                     .show(ctx, |ui| {
+                        stale_label(ui, data.is_stale(Message::GetWaypoints));
                         if data.waypoints.len() == 0 {
                             ui.label("No waypoints found");
                         }
@@ -199,10 +787,130 @@ impl eframe::App for AppState {
                     });
             }
 
+            egui::Window::new("Systems")
+                .default_open(false)
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "{} systems cataloged ({})",
+                        data.catalogue.len(),
+                        if data.catalogue.complete {
+                            "complete"
+                        } else {
+                            "fetching"
+                        }
+                    ));
+                    ui.label(format!("Reset: {}", data.catalogue.reset_date));
+
+                    if ui.button("Download systems.json").clicked() {
+                        data.catalogue.download_requested = true;
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut data.catalogue_import_path);
+                        if ui.button("Import").clicked() {
+                            let imported = std::fs::read_to_string(&data.catalogue_import_path)
+                                .map_err(|e| e.to_string())
+                                .and_then(|json| data.catalogue.import_json(&json))
+                                .and_then(|count| {
+                                    data.catalogue.save(CATALOGUE_PATH).map(|_| count)
+                                });
+                            match imported {
+                                Ok(count) => data.log.push(format!("Imported {} systems", count)),
+                                Err(e) => data.log.push(format!("Failed to import systems: {}", e)),
+                            }
+                        }
+                    });
+                });
+
+            egui::Window::new("Map")
+                .default_open(false)
+                .show(ctx, |ui| {
+                    data.map.render(
+                        ui,
+                        &data.catalogue,
+                        &data.waypoints,
+                        &data.ships,
+                        &data.exploration,
+                    );
+                });
+
+            egui::Window::new("Scripts")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    orders_only_on_desktop(ui);
+                    let ship_symbols: Vec<String> =
+                        data.ships.iter().map(|s| s.ship.symbol.clone()).collect();
+                    let busy = automated_ships(data, Some(Automation::Script));
+                    data.scripts.render(ui, &ship_symbols, &busy);
+                });
+
+            egui::Window::new("Behaviors")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    orders_only_on_desktop(ui);
+                    let ships: Vec<Ship> = data.ships.iter().map(|s| s.ship.clone()).collect();
+                    let busy = automated_ships(data, Some(Automation::Behavior));
+                    data.behaviors.render(ui, &ships, &busy);
+                });
+
+            egui::Window::new("Settings")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    if data.config.render(ui) {
+                        data.config.apply(&mut data.conf);
+                    }
+                });
+
+            if data.conf.bearer_access_token.is_none() {
+                egui::Window::new("Connect").show(ctx, |ui| {
+                    if data.config.render_token_prompt(ui) {
+                        data.config.apply(&mut data.conf);
+                    }
+                });
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            egui::Window::new("Database")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    data.database_view.render(ui, data.db.as_ref());
+                });
+
+            egui::Window::new("Notifications")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    data.notifications.render(ui);
+                });
+            data.notifications.render_toasts(ctx);
+
+            egui::Window::new("Mining swarms")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    orders_only_on_desktop(ui);
+                    let busy = automated_ships(data, Some(Automation::Swarm));
+                    data.swarms.render(ui, &data.ships, &data.waypoints, &busy);
+                });
+
+            egui::Window::new("Exploration")
+                .vscroll(true)
+                .default_open(false)
+                .show(ctx, |ui| {
+                    orders_only_on_desktop(ui);
+                    data.exploration.render(ui);
+                });
+
+            let mut purchased: Option<(LedgerEntry, Agent)> = None;
             egui::Window::new("Shipyard")
                 .constrain(true)
                 .vscroll(true)
                 .show(ctx, |ui| {
+                    orders_only_on_desktop(ui);
                     if let Some(s) = &data.shipyard_ships {
                         for ship in s.iter() {
                             ui.label(format!("Ship: {:?}", ship.ship.description));
@@ -232,13 +940,44 @@ impl eframe::App for AppState {
                                     ship_type: ship.ship.r#type.unwrap(),
                                     waypoint_symbol: ship.waypoint.clone(),
                                 };
-                                purchase_ship(&data.conf, Some(req)).block_on();
+                                match purchase_ship(&data.conf, Some(req)).block_on() {
+                                    Ok(r) => {
+                                        purchased = Some((
+                                            LedgerEntry::new(
+                                                &r.data.transaction.ship_symbol,
+                                                TransactionKind::ShipPurchase,
+                                                &ship.ship.name,
+                                                1,
+                                                -(r.data.transaction.price as i64),
+                                            ),
+                                            *r.data.agent,
+                                        ));
+                                    }
+                                    Err(_) => data.log.push("Failed to purchase ship".to_owned()),
+                                }
                             }
 
                             ui.separator();
                         }
                     }
                 });
+
+            if let Some((p, agent)) = purchased {
+                data.update_agent(agent);
+                data.record_transaction(p);
+            }
+
+            let actions: Vec<(String, ShipAction)> = data
+                .ships
+                .iter_mut()
+                .flat_map(|s| {
+                    let symbol = s.ship.symbol.clone();
+                    s.actions.drain(..).map(move |a| (symbol.clone(), a))
+                })
+                .collect();
+            for (ship_symbol, action) in actions {
+                let _ = fleet_ops::perform(data, &ship_symbol, action);
+            }
         });
 
         egui::TopBottomPanel::bottom("")
@@ -258,3 +997,15 @@ impl eframe::App for AppState {
             });
     }
 }
+
+/// Says the rest of a window only works in the desktop app and disables it, when in the
+/// browser. See `fleet_ops::ORDERS_AVAILABLE`
+fn orders_only_on_desktop(ui: &mut egui::Ui) {
+    if !ORDERS_AVAILABLE {
+        ui.colored_label(
+            egui::Color32::GRAY,
+            "Orders and automation only work in the desktop app",
+        );
+        ui.set_enabled(false);
+    }
+}
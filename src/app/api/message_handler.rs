@@ -1,16 +1,15 @@
 use std::ops::DerefMut;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use pollster::FutureExt;
-#[allow(unused)]
+use crossbeam_queue::SegQueue;
 use spacedust::apis::configuration::Configuration;
-use strum_macros::EnumIter;
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, EnumString};
 
-#[allow(unused)]
 use spacedust::apis::agents_api::*;
 #[allow(unused)]
 use spacedust::apis::contracts_api::*;
-#[allow(unused)]
 use spacedust::apis::default_api::*;
 use spacedust::apis::fleet_api::*;
 use spacedust::apis::systems_api::*;
@@ -20,116 +19,451 @@ use spacedust::models::*;
 #[allow(unused)]
 use spacedust::apis::default_api::register;
 
+use crate::behaviors;
+use crate::catalogue::CATALOGUE_PATH;
+use crate::config::Config;
+use crate::contract_runner;
+use crate::events::Event;
+use crate::exploration;
+use crate::fleet_ops;
+use crate::groups;
+use crate::history::now;
+use crate::notifications;
+use crate::route_runner;
+use crate::scripting;
 use crate::spacetraders::{ShipWithNav, ShipyardShipWithWaypoint};
+use crate::swarms;
 use crate::AppData;
 
-#[derive(Debug, EnumIter, Clone, Copy)]
+const SYSTEMS_PAGE_SIZE: i32 = 20;
+/// Shortest wait when no message is due, so the loop doesn't spin with no delay set
+pub const IDLE_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, EnumIter, EnumString, Clone, Copy, PartialEq)]
 pub enum Message {
+    GetAgent,
     GetFleet,
     GetWaypoints,
     GetContracts,
     GetShipyards,
+    GetMarkets,
+    GetJumpGates,
+    GetSystems,
+    RunContracts,
+    RunRoutes,
+    RunExploration,
+    RunScripts,
+    RunBehaviors,
+    RunGroupCommands,
+    RunSwarms,
+    RunNotifications,
+}
+
+impl Message {
+    /// Messages that go through the blocking calls in fleet_ops. A browser can't wait
+    /// on a request without returning to its event loop, so these only run natively
+    pub fn is_blocking(&self) -> bool {
+        !matches!(
+            self,
+            Message::GetAgent
+                | Message::GetFleet
+                | Message::GetWaypoints
+                | Message::GetContracts
+                | Message::GetShipyards
+                | Message::GetMarkets
+                | Message::GetSystems
+                | Message::RunNotifications
+        )
+    }
+
+    /// Messages that run the automation, giving orders to ships
+    pub fn is_automation(&self) -> bool {
+        matches!(
+            self,
+            Message::RunContracts
+                | Message::RunRoutes
+                | Message::RunExploration
+                | Message::RunScripts
+                | Message::RunBehaviors
+                | Message::RunGroupCommands
+                | Message::RunSwarms
+        )
+    }
+}
+
+/// Picks the next message to handle, going round every message whose refresh interval
+/// has passed since it last ran
+pub struct Scheduler {
+    last_run: Vec<(Message, Option<i64>)>,
+    next: usize,
+    /// Messages asked for out of turn, which are handled before anything scheduled
+    pub requested: Arc<SegQueue<Message>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            last_run: Message::iter()
+                .filter(|m| !cfg!(target_arch = "wasm32") || !m.is_blocking())
+                .map(|m| (m, None))
+                .collect(),
+            next: 0,
+            requested: Arc::new(SegQueue::new()),
+        }
+    }
+}
+
+impl Scheduler {
+    /// The next message that's due, or None if they're all waiting on their interval
+    pub fn next(&mut self, config: &Config) -> Option<Message> {
+        let time = now();
+        if let Some(message) = self.requested.pop() {
+            if let Some((_, last_run)) = self.last_run.iter_mut().find(|(m, _)| *m == message) {
+                *last_run = Some(time);
+            }
+            return Some(message);
+        }
+
+        let count = self.last_run.len();
+        for _ in 0..count {
+            let (message, last_run) = &mut self.last_run[self.next];
+            self.next = (self.next + 1) % count;
+            let interval = config.refresh_interval(*message).as_secs() as i64;
+            if last_run.map_or(true, |t| time - t >= interval) {
+                *last_run = Some(time);
+                return Some(*message);
+            }
+        }
+        None
+    }
 }
 
 pub struct MessageHandler;
 
+// Fetches are awaited with the state unlocked, so the UI keeps drawing while they're
+// in flight. Blocking messages (GetJumpGates and the Run* ticks) hold the lock for
+// their whole run instead, since fleet_ops waits on each request in turn, so the UI
+// stalls until they finish. That's why they only run natively: in the browser the UI
+// and the message loop share one thread and a held lock would never be released
+
 impl MessageHandler {
     pub async fn handle_message(&self, m: &Message, state: Arc<Mutex<AppData>>) {
-        let mut state_guard = state.lock().unwrap();
-        let data = state_guard.deref_mut();
-        data.log.push(format!("Handling message: {:?}", &m));
+        let conf = {
+            let mut data = state.lock().unwrap();
+            // Automation waits for the fleet and contracts restored from last session to
+            // be fetched again, rather than giving orders based on where things were
+            if m.is_automation()
+                && (data.is_stale(Message::GetFleet) || data.is_stale(Message::GetContracts))
+            {
+                return;
+            }
+            if cfg!(target_arch = "wasm32") && m.is_blocking() {
+                data.log
+                    .push(format!("{:?} only runs in the desktop app", m));
+                return;
+            }
+            data.log.push(format!("Handling message: {:?}", &m));
+            data.conf.clone()
+        };
         match m {
-            Message::GetFleet => match get_my_ships(&data.conf, None, None).block_on() {
-                Ok(f) => {
-                    data.log.push("Fetching fleet".into());
-                    data.ships.clear();
-
-                    for ship in f.data {
-                        let destination = ship.nav.waypoint_symbol.clone();
-                        let ship_with_nav = ShipWithNav {
-                            ship,
-                            destination: destination,
-                        };
-                        data.ships.push(ship_with_nav);
+            Message::GetAgent => {
+                let result = get_my_agent(&conf).await;
+                let mut data = state.lock().unwrap();
+                match result {
+                    Ok(a) => {
+                        data.log.push("Fetching agent".into());
+                        data.update_agent(*a.data);
+                        data.mark_fresh(Message::GetAgent);
+                        #[cfg(not(target_arch = "wasm32"))]
+                        data.store(|db, data| match &data.agent {
+                            Some(agent) => db.store_agent(agent),
+                            None => Ok(()),
+                        });
                     }
+                    Err(_) => data.report_error("Failed to get agent".to_owned()),
                 }
-                Err(_) => {}
-            },
+            }
+            Message::GetFleet => {
+                let result = get_my_ships(&conf, None, None).await;
+                let mut state_guard = state.lock().unwrap();
+                let data = state_guard.deref_mut();
+                match result {
+                    Ok(f) => {
+                        data.log.push("Fetching fleet".into());
+                        let previous = std::mem::take(&mut data.ships);
+
+                        for ship in f.data {
+                            let was_in_transit = previous.iter().any(|s| {
+                                s.ship.symbol == ship.symbol
+                                    && matches!(s.ship.nav.status, ShipNavStatus::InTransit)
+                            });
+                            if was_in_transit
+                                && !matches!(ship.nav.status, ShipNavStatus::InTransit)
+                            {
+                                data.emit(Event::ShipArrived {
+                                    ship: ship.symbol.clone(),
+                                    waypoint: ship.nav.waypoint_symbol.clone(),
+                                });
+                            }
+                            let destination = ship.nav.waypoint_symbol.clone();
+                            // Keep what the user picked in the UI across refreshes
+                            let route_target = previous
+                                .iter()
+                                .find(|s| s.ship.symbol == ship.symbol)
+                                .map(|s| s.route_target.clone())
+                                .unwrap_or_default();
+                            let ship_with_nav = ShipWithNav {
+                                ship,
+                                destination: destination,
+                                route_target,
+                                actions: vec![],
+                            };
+                            data.ships.push(ship_with_nav);
+                        }
+                        data.mark_fresh(Message::GetFleet);
+                        #[cfg(not(target_arch = "wasm32"))]
+                        data.store(|db, data| db.store_ships(data.ships.iter().map(|s| &s.ship)));
+                    }
+                    Err(_) => data.report_error("Failed to get fleet".to_owned()),
+                }
+            }
             Message::GetWaypoints => {
-                let visible_systems = &self.get_visible_systems(data);
+                let visible_systems = self.get_visible_systems(&state.lock().unwrap());
 
-                data.waypoints.clear();
-                for system in visible_systems {
-                    match get_system_waypoints(&data.conf, &system, None, None).block_on() {
+                let mut waypoints: Vec<Waypoint> = vec![];
+                for system in &visible_systems {
+                    let result = get_system_waypoints(&conf, system, None, None).await;
+                    let mut data = state.lock().unwrap();
+                    match result {
                         Ok(w) => {
                             data.log
                                 .push(format!("Fetched waypoints for system: {}", system).into());
 
                             for waypoint in w.data {
-                                data.waypoints.push(waypoint);
+                                waypoints.push(waypoint);
                             }
+                            data.mark_fresh(Message::GetWaypoints);
                         }
-                        Err(_) => data.log.push(
-                            format!("Failed to fetch waypoints for system: {}", system).to_owned(),
-                        ),
+                        Err(_) => data.report_error(format!(
+                            "Failed to fetch waypoints for system: {}",
+                            system
+                        )),
                     }
                 }
+                let mut data = state.lock().unwrap();
+                data.waypoints = waypoints;
+                #[cfg(not(target_arch = "wasm32"))]
+                data.store(|db, data| db.store_waypoints(&data.waypoints));
             }
-            Message::GetContracts => match get_contracts(&data.conf, None, None).block_on() {
-                Ok(c) => {
-                    data.log.push("Fetching contracts".into());
-                    data.contracts.clear();
+            Message::GetContracts => {
+                let result = get_contracts(&conf, None, None).await;
+                let mut data = state.lock().unwrap();
+                match result {
+                    Ok(c) => {
+                        data.log.push("Fetching contracts".into());
+                        let previous = std::mem::take(&mut data.contracts);
 
-                    for contract in c.data {
-                        data.contracts.push(contract);
+                        for contract in c.data {
+                            if !previous.contains(&contract) {
+                                data.emit(Event::ContractUpdated {
+                                    contract: contract.clone(),
+                                });
+                            }
+                            data.contracts.push(contract);
+                        }
+                        data.mark_fresh(Message::GetContracts);
+                        #[cfg(not(target_arch = "wasm32"))]
+                        data.store(|db, data| db.store_contracts(&data.contracts));
                     }
+                    Err(_) => data.report_error("Failed to get contracts".to_owned()),
                 }
-                Err(_) => data.log.push("Failed to get contracts".to_owned()),
-            },
+            }
             Message::GetShipyards => {
-                data.shipyard_ships = None;
+                let shipyards =
+                    self.waypoints_with(&state.lock().unwrap(), waypoint_trait::Symbol::Shipyard);
 
                 let mut ships: Vec<ShipyardShipWithWaypoint> = vec![];
-                for w in &data.waypoints {
-                    if w.traits
-                        .iter()
-                        .any(|f| f.symbol == waypoint_trait::Symbol::Shipyard)
-                    {
-                        match get_shipyard(&data.conf, &w.system_symbol, &w.symbol).block_on() {
-                            Ok(r) => {
-                                if let Some(s) = r.data.ships {
-                                    for ship in s {
-                                        ships.push(ShipyardShipWithWaypoint {ship: ship, waypoint: w.symbol.clone()});
-                                    }
+                let mut failed: Vec<String> = vec![];
+                for (system, waypoint) in shipyards {
+                    match get_shipyard(&conf, &system, &waypoint).await {
+                        Ok(r) => {
+                            if let Some(s) = r.data.ships {
+                                for ship in s {
+                                    ships.push(ShipyardShipWithWaypoint {
+                                        ship: ship,
+                                        waypoint: waypoint.clone(),
+                                    });
                                 }
                             }
-                            Err(_) => {}
+                        }
+                        Err(e) => {
+                            let mut data = state.lock().unwrap();
+                            data.report_error(format!(
+                                "Failed to fetch shipyard at {}: {}",
+                                waypoint, e
+                            ));
+                            failed.push(waypoint);
                         }
                     }
+                }
 
-                    if ships.len() == 0 {
-                        data.shipyard_ships = None;
-                    }
+                let mut data = state.lock().unwrap();
+                // Keep what we had for shipyards that couldn't be fetched this time
+                if let Some(previous) = data.shipyard_ships.take() {
+                    ships.extend(
+                        previous
+                            .into_iter()
+                            .filter(|s| failed.contains(&s.waypoint)),
+                    );
+                }
+                data.shipyard_ships = if ships.len() == 0 { None } else { Some(ships) };
+            }
+            Message::GetMarkets => {
+                let marketplaces = self
+                    .waypoints_with(&state.lock().unwrap(), waypoint_trait::Symbol::Marketplace);
 
-                    if let Some(s) = &mut data.shipyard_ships {
-                        s.clear();
+                let mut markets: Vec<Market> = vec![];
+                let mut failed: Vec<String> = vec![];
+                for (system, waypoint) in marketplaces {
+                    match get_market(&conf, &system, &waypoint).await {
+                        Ok(m) => markets.push(*m.data),
+                        Err(e) => {
+                            let mut data = state.lock().unwrap();
+                            data.report_error(format!(
+                                "Failed to fetch market at {}: {}",
+                                waypoint, e
+                            ));
+                            failed.push(waypoint);
+                        }
                     }
+                }
 
-                    for i in 0..ships.len() {
-                        if let None = data.shipyard_ships {
-                            data.shipyard_ships = Some(vec![]);
-                        }
+                let mut data = state.lock().unwrap();
+                // Markets that couldn't be fetched keep their last known (maybe restored)
+                // data, and stay marked stale until a refresh gets all of them
+                let previous = std::mem::take(&mut data.markets);
+                markets.extend(previous.into_iter().filter(|m| failed.contains(&m.symbol)));
+                data.markets = markets;
+                if failed.len() == 0 {
+                    data.mark_fresh(Message::GetMarkets);
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                data.store(|db, data| db.store_markets(&data.markets));
+            }
+            Message::GetJumpGates => fleet_ops::fetch_jump_gates(&mut state.lock().unwrap()),
+            Message::GetSystems => self.get_systems(&conf, &state).await,
+            Message::RunContracts => contract_runner::tick(&mut state.lock().unwrap()),
+            Message::RunRoutes => route_runner::tick(&mut state.lock().unwrap()),
+            Message::RunExploration => exploration::tick(&mut state.lock().unwrap()),
+            Message::RunScripts => scripting::tick(&mut state.lock().unwrap()),
+            Message::RunBehaviors => behaviors::tick(&mut state.lock().unwrap()),
+            Message::RunGroupCommands => groups::tick(&mut state.lock().unwrap()),
+            Message::RunSwarms => swarms::tick(&mut state.lock().unwrap()),
+            Message::RunNotifications => notifications::tick(&mut state.lock().unwrap()),
+        }
+        // Check the rules against what this message changed straight away, rather than
+        // when RunNotifications next comes round
+        notifications::tick(&mut state.lock().unwrap());
+    }
 
-                        let tmp = data.shipyard_ships.as_mut();
+    /// Checks for a server reset, then fills the catalogue a page at a time or from the
+    /// bulk download if the user asked for it
+    async fn get_systems(&self, conf: &Configuration, state: &Arc<Mutex<AppData>>) {
+        let checked = state.lock().unwrap().catalogue.checked;
+        if !checked {
+            let result = get_status(conf).await;
+            let mut data = state.lock().unwrap();
+            match result {
+                Ok(status) => {
+                    if status.reset_date != data.catalogue.reset_date {
+                        data.log.push(format!(
+                            "Server reset on {}, rebuilding system catalogue",
+                            status.reset_date
+                        ));
+                        data.catalogue.clear(&status.reset_date);
+                        #[cfg(not(target_arch = "wasm32"))]
+                        data.store(|db, _| db.replace_systems(&[]));
+                    }
+                    data.catalogue.checked = true;
+                }
+                Err(_) => data.report_error("Failed to get server status".to_owned()),
+            }
+        }
 
-                        if let Some(s) = tmp {
-                            s.push(ships.get(i).unwrap().clone());
-                        }
+        let (download, page) = {
+            let mut data = state.lock().unwrap();
+            let download = data.catalogue.download_requested;
+            data.catalogue.download_requested = false;
+            let paging = data.catalogue.checked && !data.catalogue.complete;
+            // There are thousands of systems, so pull in one page per round until done
+            let page = data.catalogue.len() as i32 / SYSTEMS_PAGE_SIZE + 1;
+            (download, if paging { Some(page) } else { None })
+        };
+
+        if download {
+            self.download_systems(conf, state).await;
+        } else if let Some(page) = page {
+            let result = get_systems(conf, Some(page), Some(SYSTEMS_PAGE_SIZE)).await;
+            let mut state_guard = state.lock().unwrap();
+            let data = state_guard.deref_mut();
+            match result {
+                Ok(s) => {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    data.store(|db, _| db.store_systems(&s.data));
+                    data.catalogue.add(s.data);
+                    if data.catalogue.len() as i32 >= s.meta.total {
+                        data.catalogue.complete = true;
+                    }
+                    // Saved as we go so a restart picks up from the last page
+                    if let Err(e) = data.catalogue.save(CATALOGUE_PATH) {
+                        data.log.push(format!("Failed to save systems: {}", e));
                     }
                 }
+                Err(_) => data.report_error("Failed to fetch systems".to_owned()),
             }
         }
     }
+
+    /// Fills the catalogue from the bulk systems.json dump in one request
+    async fn download_systems(&self, conf: &Configuration, state: &Arc<Mutex<AppData>>) {
+        let url = format!("{}/systems.json", conf.base_path);
+        let mut request = conf.client.get(url);
+        if let Some(token) = &conf.bearer_access_token {
+            request = request.bearer_auth(token);
+        }
+
+        let json = match request.send().await {
+            Ok(r) => r.text().await,
+            Err(e) => Err(e),
+        };
+
+        let mut state_guard = state.lock().unwrap();
+        let data = state_guard.deref_mut();
+        match json
+            .map_err(|e| e.to_string())
+            .and_then(|j| data.catalogue.import_json(&j))
+        {
+            Ok(count) => {
+                data.log.push(format!("Imported {} systems", count));
+                #[cfg(not(target_arch = "wasm32"))]
+                data.store(|db, data| db.replace_systems(data.catalogue.systems()));
+                if let Err(e) = data.catalogue.save(CATALOGUE_PATH) {
+                    data.log.push(format!("Failed to save systems: {}", e));
+                }
+            }
+            Err(e) => data.report_error(format!("Failed to download systems: {}", e)),
+        }
+    }
+
+    /// System and symbol of every known waypoint with a trait
+    fn waypoints_with(&self, data: &AppData, t: waypoint_trait::Symbol) -> Vec<(String, String)> {
+        data.waypoints
+            .iter()
+            .filter(|w| w.traits.iter().any(|f| f.symbol == t))
+            .map(|w| (w.system_symbol.clone(), w.symbol.clone()))
+            .collect()
+    }
+
     fn get_visible_systems(&self, data: &AppData) -> Vec<String> {
         let mut visible_systems: Vec<String>;
         visible_systems = data
@@ -137,6 +471,8 @@ impl MessageHandler {
             .iter()
             .map(|ship| ship.ship.nav.system_symbol.clone())
             .collect();
+        visible_systems.append(&mut data.routes.target_systems());
+        visible_systems.sort();
         visible_systems.dedup();
         visible_systems
     }
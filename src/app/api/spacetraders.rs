@@ -1,10 +1,7 @@
 use egui::Ui;
-use pollster::FutureExt;
 use spacedust::apis::agents_api::*;
-use spacedust::apis::contracts_api::*;
 #[allow(unused)]
 use spacedust::apis::default_api::*;
-use spacedust::apis::fleet_api::*;
 #[allow(unused)]
 use spacedust::apis::systems_api::*;
 
@@ -14,13 +11,46 @@ use spacedust::apis::configuration::Configuration;
 use spacedust::apis::default_api::register;
 use spacedust::models::register_request::{Faction, RegisterRequest};
 
+use crate::contracts::{
+    estimate_contract_seconds, format_countdown, seconds_until, units_in_cargo,
+};
+
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct SpaceTraders {}
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct ShipWithNav {
     pub ship: Ship,
     pub destination: String,
+    /// Waypoint or system picked for a multi-step route
+    #[serde(skip)]
+    pub route_target: String,
+    /// Actions picked in the UI since the last frame, carried out through fleet_ops
+    #[serde(skip)]
+    pub actions: Vec<ShipAction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShipAction {
+    Sell(String, i32),
+    Navigate(String),
+    Dock,
+    Refuel,
+    Extract,
+    Survey,
+    /// Contract id, trade symbol and units
+    Deliver(String, String, i32),
+}
+
+pub fn has_surveyor(ship: &Ship) -> bool {
+    ship.mounts.iter().any(|m| {
+        matches!(
+            m.symbol,
+            ship_mount::Symbol::MountSurveyorI
+                | ship_mount::Symbol::MountSurveyorIi
+                | ship_mount::Symbol::MountSurveyorIii
+        )
+    })
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -29,6 +59,53 @@ pub struct ShipyardShipWithWaypoint {
     pub waypoint: String,
 }
 
+/// Aggregated view of the fleet shown on the agent dashboard
+#[derive(Debug, Default)]
+pub struct FleetSummary {
+    pub ship_count: usize,
+    pub docked: usize,
+    pub in_orbit: usize,
+    pub in_transit: usize,
+    pub cargo_units: i32,
+    pub cargo_capacity: i32,
+    pub fuel_current: i32,
+    pub fuel_capacity: i32,
+    pub low_fuel: Vec<String>,
+}
+
+impl FleetSummary {
+    /// Ships below this fraction of their fuel capacity are listed as low on fuel
+    const LOW_FUEL_RATIO: f32 = 0.25;
+
+    pub fn from_ships(ships: &[ShipWithNav]) -> Self {
+        let mut summary = FleetSummary {
+            ship_count: ships.len(),
+            ..Default::default()
+        };
+
+        for s in ships {
+            match s.ship.nav.status {
+                ShipNavStatus::Docked => summary.docked += 1,
+                ShipNavStatus::InOrbit => summary.in_orbit += 1,
+                ShipNavStatus::InTransit => summary.in_transit += 1,
+            }
+
+            summary.cargo_units += s.ship.cargo.units;
+            summary.cargo_capacity += s.ship.cargo.capacity;
+            summary.fuel_current += s.ship.fuel.current;
+            summary.fuel_capacity += s.ship.fuel.capacity;
+
+            if s.ship.fuel.capacity > 0
+                && (s.ship.fuel.current as f32) < s.ship.fuel.capacity as f32 * Self::LOW_FUEL_RATIO
+            {
+                summary.low_fuel.push(s.ship.symbol.clone());
+            }
+        }
+
+        summary
+    }
+}
+
 
 pub trait RenderWithWaypoints {
     fn render_with_waypoints(
@@ -43,6 +120,20 @@ pub trait RenderWithWaypoints {
     }
 }
 
+pub trait RenderWithFleet {
+    fn render_with_fleet(
+        &mut self,
+        ui: &mut Ui,
+        _conf: &Configuration,
+        _ships: &mut Vec<ShipWithNav>,
+        _waypoints: &Vec<Waypoint>,
+    ) where
+        Self: std::fmt::Debug,
+    {
+        ui.code(format!("{:?}", &self));
+    }
+}
+
 pub trait Render {
     fn render(&mut self, ui: &mut Ui, _conf: &Configuration)
     where
@@ -56,7 +147,7 @@ impl RenderWithWaypoints for ShipWithNav {
     fn render_with_waypoints(
         &mut self,
         ui: &mut Ui,
-        conf: &Configuration,
+        _conf: &Configuration,
         waypoints: &Vec<Waypoint>,
     ) where
         Self: std::fmt::Debug,
@@ -104,10 +195,21 @@ impl RenderWithWaypoints for ShipWithNav {
                 &self.ship.cargo.capacity - &self.ship.cargo.units
             ));
 
+            let mut sell: Option<(String, i32)> = None;
             for cargo in &self.ship.cargo.inventory {
                 ui.label(format!("Cargo:"));
                 ui.label(format!("\tName: {:?}", cargo.name));
                 ui.label(format!("\tUnits: {:?}", cargo.units));
+
+                if let ShipNavStatus::Docked = self.ship.nav.status {
+                    if ui.button(format!("Sell {}", cargo.symbol)).clicked() {
+                        sell = Some((cargo.symbol.clone(), cargo.units));
+                    }
+                }
+            }
+
+            if let Some((symbol, units)) = sell {
+                self.actions.push(ShipAction::Sell(symbol, units));
             }
 
             ui.label(format!("Status: {:?}", &self.ship.nav.status));
@@ -120,7 +222,11 @@ impl RenderWithWaypoints for ShipWithNav {
                             .selected_text(format!("{:?}", &mut self.destination))
                             .width(170.0)
                             .show_ui(ui, |ui| {
-                                for waypoint in waypoints {
+                                // Plain navigation only works within the current system
+                                for waypoint in waypoints
+                                    .iter()
+                                    .filter(|w| w.system_symbol == self.ship.nav.system_symbol)
+                                {
                                     ui.selectable_value(
                                         &mut self.destination,
                                         waypoint.symbol.clone(),
@@ -131,13 +237,8 @@ impl RenderWithWaypoints for ShipWithNav {
                     });
                     if self.destination != self.ship.nav.waypoint_symbol {
                         if ui.button("Begin journey").clicked() {
-                            let req = navigate_ship_request::NavigateShipRequest::new(
-                                self.destination.clone(),
-                            );
-                            match navigate_ship(&conf, &self.ship.symbol, Some(req)).block_on() {
-                                Ok(_) => println!("Ship navigating successfully"),
-                                Err(_) => println!("Could not start navigation"),
-                            }
+                            self.actions
+                                .push(ShipAction::Navigate(self.destination.clone()));
                         }
                     }
                 }
@@ -146,28 +247,21 @@ impl RenderWithWaypoints for ShipWithNav {
             match &self.ship.nav.status {
                 ShipNavStatus::InOrbit => {
                     if ui.button("Dock").clicked() {
-                        dock_ship(&conf, &self.ship.symbol, 0.0).block_on();
+                        self.actions.push(ShipAction::Dock);
+                    }
+                    if has_surveyor(&self.ship) && ui.button("Survey").clicked() {
+                        self.actions.push(ShipAction::Survey);
                     }
                 }
                 ShipNavStatus::Docked => {
-                    if self.ship.cargo.capacity > self.ship.cargo.units {
-                        if ui.button("Extract").clicked() {
-                            let req = extract_resources_request::ExtractResourcesRequest { survey: None };
-                            match extract_resources(&conf, &self.ship.symbol, Some(req)).block_on() {
-                                Ok(r) => println!("{:?}", r),
-                                Err(e) => println!("{:?}", e),
-                            }
+                    if self.ship.fuel.current < self.ship.fuel.capacity {
+                        if ui.button("Refuel").clicked() {
+                            self.actions.push(ShipAction::Refuel);
                         }
                     }
-                    if ui.button("Deliver").clicked() {
-                        let req = deliver_contract_request::DeliverContractRequest {
-                            ship_symbol: self.ship.symbol.clone(),
-                            trade_symbol: todo!(),
-                            units: todo!(),
-                        };
-                        match deliver_contract(&conf, "", Some(req)).block_on() {
-                            Ok(r) => println!("{:?}", r),
-                            Err(e) => println!("{:?}", e),
+                    if self.ship.cargo.capacity > self.ship.cargo.units {
+                        if ui.button("Extract").clicked() {
+                            self.actions.push(ShipAction::Extract);
                         }
                     }
                 }
@@ -179,6 +273,49 @@ impl RenderWithWaypoints for ShipWithNav {
     }
 }
 
+impl Render for Agent {
+    fn render(&mut self, ui: &mut Ui, _conf: &Configuration)
+    where
+        Self: std::fmt::Debug,
+    {
+        ui.vertical(|ui| {
+            ui.label(format!("Symbol: {}", self.symbol));
+            ui.label(format!("Faction: {}", self.starting_faction));
+            ui.label(format!("Credits: {}", self.credits));
+            ui.label(format!("Headquarters: {}", self.headquarters));
+        });
+    }
+}
+
+impl Render for FleetSummary {
+    fn render(&mut self, ui: &mut Ui, _conf: &Configuration)
+    where
+        Self: std::fmt::Debug,
+    {
+        ui.vertical(|ui| {
+            ui.label(format!("Ships: {}", self.ship_count));
+            ui.label(format!("\tDocked: {}", self.docked));
+            ui.label(format!("\tIn orbit: {}", self.in_orbit));
+            ui.label(format!("\tIn transit: {}", self.in_transit));
+            ui.label(format!(
+                "Cargo: {} / {}",
+                self.cargo_units, self.cargo_capacity
+            ));
+            ui.label(format!(
+                "Fuel: {} / {}",
+                self.fuel_current, self.fuel_capacity
+            ));
+
+            if self.low_fuel.len() > 0 {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("Low fuel: {}", self.low_fuel.join(", ")),
+                );
+            }
+        });
+    }
+}
+
 impl Render for Waypoint {
     fn render(&mut self, ui: &mut Ui, conf: &Configuration)
     where
@@ -222,11 +359,18 @@ impl Render for Waypoint {
     }
 }
 
-impl Render for Contract {
-    fn render(&mut self, ui: &mut Ui, conf: &Configuration)
-    where
+impl RenderWithFleet for Contract {
+    fn render_with_fleet(
+        &mut self,
+        ui: &mut Ui,
+        _conf: &Configuration,
+        ships: &mut Vec<ShipWithNav>,
+        waypoints: &Vec<Waypoint>,
+    ) where
         Self: std::fmt::Debug,
     {
+        let mut deliver: Option<(usize, String, i32)> = None;
+
         ui.vertical(|ui| {
             ui.label(format!("Type: {:?}", self.r#type));
             ui.label(format!("Faction: {:?}", self.faction_symbol));
@@ -242,35 +386,82 @@ impl Render for Contract {
                 "Payment (total): {:?}",
                 self.terms.payment.on_fulfilled + self.terms.payment.on_accepted
             ));
-            ui.label(format!("Deadline: {:?}", self.terms.deadline));
-            ui.label(format!("Expiration: {:?}", self.expiration));
+
+            if !self.accepted {
+                match seconds_until(&self.expiration) {
+                    Some(s) => ui.label(format!("Accept within: {}", format_countdown(s))),
+                    None => ui.label(format!("Expiration: {:?}", self.expiration)),
+                };
+            }
+
+            if !self.fulfilled {
+                let estimate = estimate_contract_seconds(self, ships, waypoints);
+                match seconds_until(&self.terms.deadline) {
+                    Some(remaining) => {
+                        let text = format!("Deadline in: {}", format_countdown(remaining));
+                        match estimate {
+                            Some(e) if e > remaining => ui.colored_label(
+                                egui::Color32::RED,
+                                format!("{} (estimated {} needed)", text, format_countdown(e)),
+                            ),
+                            _ => ui.label(text),
+                        };
+                    }
+                    None => {
+                        ui.label(format!("Deadline: {:?}", self.terms.deadline));
+                    }
+                }
+            }
 
             if let Some(d) = &self.terms.deliver {
                 for delivery in d {
                     ui.label(format!("Delivery:"));
                     ui.label(format!("\tDeliver: {:?}", delivery.trade_symbol));
                     ui.label(format!("\tDestination: {:?}", delivery.destination_symbol));
-                    ui.label(format!("\tRequired: {:?}", delivery.units_required));
-                    ui.label(format!("\tFulfilled: {:?}", delivery.units_fulfilled));
-                    ui.label(format!(
-                        "\tRemaining: {:?}",
-                        delivery.units_required - delivery.units_fulfilled
-                    ));
+                    ui.add(
+                        egui::ProgressBar::new(
+                            delivery.units_fulfilled as f32 / delivery.units_required.max(1) as f32,
+                        )
+                        .text(format!(
+                            "{} / {}",
+                            delivery.units_fulfilled, delivery.units_required
+                        )),
+                    );
+
+                    for (i, ship) in ships.iter().enumerate() {
+                        let units = units_in_cargo(ship, &delivery.trade_symbol);
+                        if units == 0 {
+                            continue;
+                        }
+
+                        ui.label(format!("\tIn cargo of {}: {}", ship.ship.symbol, units));
+
+                        let remaining = delivery.units_required - delivery.units_fulfilled;
+                        if self.accepted
+                            && remaining > 0
+                            && ship.ship.nav.waypoint_symbol == delivery.destination_symbol
+                            && matches!(ship.ship.nav.status, ShipNavStatus::Docked)
+                        {
+                            if ui
+                                .button(format!("Deliver from {}", ship.ship.symbol))
+                                .clicked()
+                            {
+                                deliver =
+                                    Some((i, delivery.trade_symbol.clone(), units.min(remaining)));
+                            }
+                        }
+                    }
                 }
             }
 
             ui.label(format!("Accepted: {:?}", self.accepted));
-
-            //ui.code(format!("{:?}", &self));
+            ui.label(format!("Fulfilled: {:?}", self.fulfilled));
         });
 
-        if self.accepted == false {
-            if ui.button("Accept!").clicked() {
-                match accept_contract(conf, &self.id, 0).block_on() {
-                    Ok(r) => println!("{:?}", r),
-                    Err(_) => println!("Failed to accept contract :("),
-                }
-            }
+        if let Some((i, trade_symbol, units)) = deliver {
+            ships[i]
+                .actions
+                .push(ShipAction::Deliver(self.id.clone(), trade_symbol, units));
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::contract_runner::buys;
+use crate::fleet_ops;
+use crate::history::now;
+use crate::navigation::{distance, find_waypoint};
+use crate::AppData;
+
+/// Something that happened to a ship since the last round
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShipEvent {
+    Arrived,
+    CooldownEnded,
+    CargoFull,
+    CargoEmpty,
+    Docked,
+}
+
+/// What a behavior did on its turn
+pub enum Outcome {
+    /// Nothing to do until something happens
+    Wait,
+    /// Started an action that locks the ship for this many seconds
+    Cooldown(i64),
+    /// Found the ship already where the event would have taken it
+    Event(ShipEvent),
+}
+
+/// A state machine driving one ship. States are named so they can be saved and shown
+pub trait ShipBehavior {
+    fn name(&self) -> &'static str;
+    fn initial_state(&self) -> &'static str;
+    /// The state to move to after an event, or None to stay put
+    fn transition(&self, state: &str, event: ShipEvent) -> Option<&'static str>;
+    fn act(
+        &self,
+        state: &str,
+        data: &mut AppData,
+        assignment: &BehaviorAssignment,
+    ) -> Result<Outcome, String>;
+}
+
+/// Every behavior that can be assigned from the UI
+pub fn behaviors() -> Vec<Box<dyn ShipBehavior>> {
+    vec![Box::new(MineAndSell)]
+}
+
+fn find_behavior(name: &str) -> Option<Box<dyn ShipBehavior>> {
+    behaviors().into_iter().find(|b| b.name() == name)
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct BehaviorAssignment {
+    pub ship_symbol: String,
+    pub behavior: String,
+    pub state: String,
+    /// Where the ship was when the behavior was assigned
+    pub home: String,
+    #[serde(skip)]
+    pub cooldown_until: i64,
+    #[serde(skip)]
+    pub was_in_transit: bool,
+    #[serde(skip)]
+    pub was_docked: bool,
+    #[serde(skip)]
+    pub was_cooling_down: bool,
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Behaviors {
+    pub assignments: Vec<BehaviorAssignment>,
+}
+
+impl Behaviors {
+    pub fn assignment_for(&self, ship_symbol: &str) -> Option<&BehaviorAssignment> {
+        self.assignments
+            .iter()
+            .find(|a| a.ship_symbol == ship_symbol)
+    }
+
+    /// Starts a behavior on a ship from its initial state, or stops it with None
+    pub fn assign(&mut self, ship: &Ship, behavior: Option<&str>) {
+        self.assignments.retain(|a| a.ship_symbol != ship.symbol);
+
+        if let Some(behavior) = behavior.and_then(find_behavior) {
+            self.assignments.push(BehaviorAssignment {
+                ship_symbol: ship.symbol.clone(),
+                behavior: behavior.name().to_owned(),
+                state: behavior.initial_state().to_owned(),
+                home: ship.nav.waypoint_symbol.clone(),
+                cooldown_until: 0,
+                was_in_transit: false,
+                was_docked: false,
+                was_cooling_down: false,
+            });
+        }
+    }
+
+    /// `busy` holds ships something else is driving, see `fleet_table::automated_ships`
+    pub fn render(&mut self, ui: &mut Ui, ships: &[Ship], busy: &HashMap<String, String>) {
+        let names: Vec<&'static str> = behaviors().iter().map(|b| b.name()).collect();
+
+        egui::Grid::new("behaviors").striped(true).show(ui, |ui| {
+            for ship in ships {
+                let assignment = self.assignment_for(&ship.symbol);
+                let current = assignment.map(|a| a.behavior.clone());
+                let state = assignment.map(|a| a.state.clone()).unwrap_or_default();
+
+                ui.label(&ship.symbol);
+                if let Some(automation) = busy.get(&ship.symbol) {
+                    ui.label(automation);
+                    ui.end_row();
+                    continue;
+                }
+                let mut selected = current.clone();
+                egui::ComboBox::from_id_source(format!("behavior_{}", ship.symbol))
+                    .selected_text(selected.clone().unwrap_or("None".to_owned()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut selected, None, "None");
+                        for name in &names {
+                            ui.selectable_value(&mut selected, Some(name.to_string()), *name);
+                        }
+                    });
+                ui.label(state);
+                ui.end_row();
+
+                if selected != current {
+                    self.assign(ship, selected.as_deref());
+                }
+            }
+        });
+    }
+}
+
+/// Works out which events have happened to a ship since the last round
+fn detect_events(assignment: &mut BehaviorAssignment, ship: &Ship, time: i64) -> Vec<ShipEvent> {
+    let mut events = vec![];
+
+    let in_transit = matches!(ship.nav.status, ShipNavStatus::InTransit);
+    let docked = matches!(ship.nav.status, ShipNavStatus::Docked);
+    let cooling_down = assignment.cooldown_until > time;
+
+    if assignment.was_in_transit && !in_transit {
+        events.push(ShipEvent::Arrived);
+    }
+    if assignment.was_cooling_down && !cooling_down {
+        events.push(ShipEvent::CooldownEnded);
+    }
+    if !assignment.was_docked && docked {
+        events.push(ShipEvent::Docked);
+    }
+    if ship.cargo.units >= ship.cargo.capacity {
+        events.push(ShipEvent::CargoFull);
+    }
+    if ship.cargo.units == 0 {
+        events.push(ShipEvent::CargoEmpty);
+    }
+
+    assignment.was_in_transit = in_transit;
+    assignment.was_docked = docked;
+    assignment.was_cooling_down = cooling_down;
+    events
+}
+
+fn apply_event(behavior: &dyn ShipBehavior, assignment: &mut BehaviorAssignment, event: ShipEvent) {
+    if let Some(next) = behavior.transition(&assignment.state, event) {
+        assignment.state = next.to_owned();
+    }
+}
+
+/// Feeds new events to one behavior and lets it act in whatever state that leaves it in
+fn step_behavior(
+    data: &mut AppData,
+    assignment: &mut BehaviorAssignment,
+    time: i64,
+) -> Result<(), String> {
+    let behavior = match find_behavior(&assignment.behavior) {
+        Some(b) => b,
+        None => return Err(format!("Unknown behavior: {}", assignment.behavior)),
+    };
+    let ship = match data
+        .ships
+        .iter()
+        .find(|s| s.ship.symbol == assignment.ship_symbol)
+    {
+        Some(s) => s.ship.clone(),
+        None => return Ok(()),
+    };
+
+    for event in detect_events(assignment, &ship, time) {
+        apply_event(behavior.as_ref(), assignment, event);
+    }
+
+    if assignment.was_in_transit || assignment.was_cooling_down {
+        return Ok(());
+    }
+
+    let state = assignment.state.clone();
+    match behavior.act(&state, data, assignment)? {
+        Outcome::Wait => {}
+        Outcome::Cooldown(seconds) => {
+            assignment.cooldown_until = time + seconds;
+            assignment.was_cooling_down = seconds > 0;
+        }
+        Outcome::Event(event) => apply_event(behavior.as_ref(), assignment, event),
+    }
+    Ok(())
+}
+
+/// Runs one round of every assigned behavior
+pub fn tick(data: &mut AppData) {
+    let time = now();
+    let mut assignments = std::mem::take(&mut data.behaviors.assignments);
+
+    for assignment in assignments.iter_mut() {
+        if let Err(e) = step_behavior(data, assignment, time) {
+            data.report_error(format!("{}: {}", assignment.ship_symbol, e));
+        }
+    }
+
+    // Keep any assignments that were made during the round
+    assignments.append(&mut data.behaviors.assignments);
+    data.behaviors.assignments = assignments;
+}
+
+/// Nearest marketplace to a waypoint, in the same system
+pub fn nearest_market(data: &AppData, from: &str) -> Option<String> {
+    let here = find_waypoint(&data.waypoints, from)?;
+    data.waypoints
+        .iter()
+        .filter(|w| w.system_symbol == here.system_symbol)
+        .filter(|w| {
+            w.traits
+                .iter()
+                .any(|t| t.symbol == waypoint_trait::Symbol::Marketplace)
+        })
+        .min_by(|a, b| distance(here, a).total_cmp(&distance(here, b)))
+        .map(|w| w.symbol.clone())
+}
+
+/// Flies to `waypoint` unless already there, in which case it reports arrival
+pub fn travel_to(data: &mut AppData, ship: &Ship, waypoint: &str) -> Result<Outcome, String> {
+    if ship.nav.waypoint_symbol == waypoint {
+        return Ok(Outcome::Event(ShipEvent::Arrived));
+    }
+    if let ShipNavStatus::Docked = ship.nav.status {
+        fleet_ops::orbit(data, &ship.symbol)?;
+    }
+    fleet_ops::navigate(data, &ship.symbol, waypoint)?;
+    Ok(Outcome::Wait)
+}
+
+/// Mines at home until the hold is full, sells at the nearest market and comes back
+pub struct MineAndSell;
+
+impl ShipBehavior for MineAndSell {
+    fn name(&self) -> &'static str {
+        "Mine and sell"
+    }
+
+    fn initial_state(&self) -> &'static str {
+        "mining"
+    }
+
+    fn transition(&self, state: &str, event: ShipEvent) -> Option<&'static str> {
+        match (state, event) {
+            ("mining", ShipEvent::CargoFull) => Some("to market"),
+            ("to market", ShipEvent::Arrived) => Some("selling"),
+            ("selling", ShipEvent::CargoEmpty) => Some("returning"),
+            ("returning", ShipEvent::Arrived) => Some("mining"),
+            _ => None,
+        }
+    }
+
+    fn act(
+        &self,
+        state: &str,
+        data: &mut AppData,
+        assignment: &BehaviorAssignment,
+    ) -> Result<Outcome, String> {
+        let ship = match data
+            .ships
+            .iter()
+            .find(|s| s.ship.symbol == assignment.ship_symbol)
+        {
+            Some(s) => s.ship.clone(),
+            None => return Ok(Outcome::Wait),
+        };
+
+        match state {
+            "mining" => {
+                if ship.nav.waypoint_symbol != assignment.home {
+                    return travel_to(data, &ship, &assignment.home);
+                }
+                if let ShipNavStatus::Docked = ship.nav.status {
+                    fleet_ops::orbit(data, &ship.symbol)?;
+                }
+                Ok(Outcome::Cooldown(fleet_ops::extract(data, &ship.symbol)?))
+            }
+            "to market" => match nearest_market(data, &assignment.home) {
+                Some(market) => travel_to(data, &ship, &market),
+                None => Err("no known market near home".to_owned()),
+            },
+            "selling" => {
+                if !matches!(ship.nav.status, ShipNavStatus::Docked) {
+                    fleet_ops::dock(data, &ship.symbol)?;
+                }
+                let market = data
+                    .markets
+                    .iter()
+                    .find(|m| m.symbol == ship.nav.waypoint_symbol)
+                    .cloned();
+                for item in &ship.cargo.inventory {
+                    match market.as_ref().map(|m| buys(m, &item.symbol)) {
+                        // Goods nobody here wants would only fill the hold on every trip
+                        Some(false) => {
+                            fleet_ops::jettison_cargo(data, &ship.symbol, &item.symbol, item.units)?
+                        }
+                        // A failed sale keeps us selling until it goes through
+                        _ => fleet_ops::sell(data, &ship.symbol, &item.symbol, item.units)?,
+                    }
+                }
+                let emptied = data
+                    .ships
+                    .iter()
+                    .any(|s| s.ship.symbol == ship.symbol && s.ship.cargo.units == 0);
+                if emptied {
+                    Ok(Outcome::Event(ShipEvent::CargoEmpty))
+                } else {
+                    Ok(Outcome::Wait)
+                }
+            }
+            "returning" => travel_to(data, &ship, &assignment.home),
+            _ => Err(format!("unknown state: {}", state)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENTS: [ShipEvent; 5] = [
+        ShipEvent::Arrived,
+        ShipEvent::CooldownEnded,
+        ShipEvent::CargoFull,
+        ShipEvent::CargoEmpty,
+        ShipEvent::Docked,
+    ];
+
+    fn ship(status: ShipNavStatus, units: i32) -> Ship {
+        let mut ship = Ship::default();
+        ship.symbol = "SHIP-1".to_owned();
+        ship.nav.waypoint_symbol = "X1-A-FIELD".to_owned();
+        ship.nav.status = status;
+        ship.cargo.capacity = 30;
+        ship.cargo.units = units;
+        ship
+    }
+
+    fn assignment(ship: &Ship) -> BehaviorAssignment {
+        let mut behaviors = Behaviors::default();
+        behaviors.assign(ship, Some(MineAndSell.name()));
+        behaviors.assignments.remove(0)
+    }
+
+    #[test]
+    fn mine_and_sell_goes_round_mining_selling_and_returning() {
+        let mut a = assignment(&ship(ShipNavStatus::InOrbit, 0));
+        assert_eq!(a.state, "mining");
+        assert_eq!(a.home, "X1-A-FIELD");
+        for (event, next) in [
+            (ShipEvent::CargoFull, "to market"),
+            (ShipEvent::Arrived, "selling"),
+            (ShipEvent::CargoEmpty, "returning"),
+            (ShipEvent::Arrived, "mining"),
+        ] {
+            apply_event(&MineAndSell, &mut a, event);
+            assert_eq!(a.state, next);
+        }
+    }
+
+    #[test]
+    fn mine_and_sell_ignores_events_that_do_not_move_it_on() {
+        let moves = [
+            ("mining", ShipEvent::CargoFull),
+            ("to market", ShipEvent::Arrived),
+            ("selling", ShipEvent::CargoEmpty),
+            ("returning", ShipEvent::Arrived),
+        ];
+        for state in ["mining", "to market", "selling", "returning"] {
+            for event in EVENTS {
+                if !moves.contains(&(state, event)) {
+                    assert_eq!(
+                        MineAndSell.transition(state, event),
+                        None,
+                        "{} {:?}",
+                        state,
+                        event
+                    );
+                }
+            }
+        }
+        for event in EVENTS {
+            assert_eq!(MineAndSell.transition("unknown", event), None);
+        }
+    }
+
+    #[test]
+    fn detects_arrival_docking_and_the_end_of_a_cooldown() {
+        let mut a = assignment(&ship(ShipNavStatus::InTransit, 10));
+        a.cooldown_until = 100;
+        assert_eq!(
+            detect_events(&mut a, &ship(ShipNavStatus::InTransit, 10), 50),
+            vec![]
+        );
+        assert_eq!(
+            detect_events(&mut a, &ship(ShipNavStatus::Docked, 10), 150),
+            vec![
+                ShipEvent::Arrived,
+                ShipEvent::CooldownEnded,
+                ShipEvent::Docked
+            ]
+        );
+        // Each is only seen as it happens
+        assert_eq!(
+            detect_events(&mut a, &ship(ShipNavStatus::Docked, 10), 200),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn detects_a_full_or_empty_hold_every_round() {
+        let mut a = assignment(&ship(ShipNavStatus::InOrbit, 0));
+        for _ in 0..2 {
+            assert_eq!(
+                detect_events(&mut a, &ship(ShipNavStatus::InOrbit, 30), 0),
+                vec![ShipEvent::CargoFull]
+            );
+            assert_eq!(
+                detect_events(&mut a, &ship(ShipNavStatus::InOrbit, 0), 0),
+                vec![ShipEvent::CargoEmpty]
+            );
+        }
+    }
+
+    #[test]
+    fn travelling_where_the_ship_already_is_arrives() {
+        let mut data = AppData::default();
+        let ship = ship(ShipNavStatus::Docked, 0);
+        assert!(matches!(
+            travel_to(&mut data, &ship, "X1-A-FIELD"),
+            Ok(Outcome::Event(ShipEvent::Arrived))
+        ));
+    }
+
+    #[test]
+    fn assigning_none_stops_a_behavior() {
+        let ship = ship(ShipNavStatus::InOrbit, 0);
+        let mut behaviors = Behaviors::default();
+        behaviors.assign(&ship, Some(MineAndSell.name()));
+        behaviors.assign(&ship, Some(MineAndSell.name()));
+        assert_eq!(behaviors.assignments.len(), 1);
+        behaviors.assign(&ship, None);
+        assert!(behaviors.assignment_for("SHIP-1").is_none());
+        behaviors.assign(&ship, Some("No such behavior"));
+        assert!(behaviors.assignments.is_empty());
+    }
+}
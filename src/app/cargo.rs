@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::spacetraders::ShipWithNav;
+
+/// A move of cargo from one ship to another
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub trade_symbol: String,
+    pub units: i32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TransferForm {
+    pub to: String,
+    pub trade_symbol: String,
+    pub units: i32,
+}
+
+/// Transfer forms being filled in, per sending ship
+#[derive(Debug, Default)]
+pub struct CargoTransfers {
+    pub forms: HashMap<String, TransferForm>,
+}
+
+pub fn free_space(ship: &Ship) -> i32 {
+    (ship.cargo.capacity - ship.cargo.units).max(0)
+}
+
+/// Other ships that can trade cargo with this one: same waypoint, same nav status
+pub fn co_located(ships: &[ShipWithNav], ship: &Ship) -> Vec<Ship> {
+    if let ShipNavStatus::InTransit = ship.nav.status {
+        return vec![];
+    }
+    ships
+        .iter()
+        .map(|s| &s.ship)
+        .filter(|s| s.symbol != ship.symbol)
+        .filter(|s| s.nav.waypoint_symbol == ship.nav.waypoint_symbol)
+        .filter(|s| s.nav.status == ship.nav.status)
+        .cloned()
+        .collect()
+}
+
+pub fn validate_transfer(
+    from: &Ship,
+    to: &Ship,
+    trade_symbol: &str,
+    units: i32,
+) -> Result<(), String> {
+    if units <= 0 {
+        return Err("Nothing to transfer".to_owned());
+    }
+    if from.nav.waypoint_symbol != to.nav.waypoint_symbol || from.nav.status != to.nav.status {
+        return Err(format!(
+            "{} and {} must be at the same waypoint, both docked or both in orbit",
+            from.symbol, to.symbol
+        ));
+    }
+    if let ShipNavStatus::InTransit = from.nav.status {
+        return Err("Ships in transit can't transfer cargo".to_owned());
+    }
+
+    let held: i32 = from
+        .cargo
+        .inventory
+        .iter()
+        .filter(|c| c.symbol == trade_symbol)
+        .map(|c| c.units)
+        .sum();
+    if held < units {
+        return Err(format!(
+            "{} only holds {} {}",
+            from.symbol, held, trade_symbol
+        ));
+    }
+    if free_space(to) < units {
+        return Err(format!(
+            "{} only has space for {} units",
+            to.symbol,
+            free_space(to)
+        ));
+    }
+    Ok(())
+}
+
+/// Adds transferred units to our copy of the receiving ship's hold
+pub fn receive(ship: &mut Ship, item: &ShipCargoItem, units: i32) {
+    match ship
+        .cargo
+        .inventory
+        .iter_mut()
+        .find(|c| c.symbol == item.symbol)
+    {
+        Some(c) => c.units += units,
+        None => {
+            let mut received = item.clone();
+            received.units = units;
+            ship.cargo.inventory.push(received);
+        }
+    }
+    ship.cargo.units += units;
+}
+
+impl CargoTransfers {
+    pub fn render(&mut self, ui: &mut Ui, ship: &Ship, partners: &[Ship]) -> Option<Transfer> {
+        let mut transfer = None;
+
+        egui::CollapsingHeader::new("Transfer cargo")
+            .id_source(format!("transfer_{}", ship.symbol))
+            .show(ui, |ui| {
+                if partners.len() == 0 {
+                    ui.label("No other ships here with the same nav status");
+                    return;
+                }
+                if ship.cargo.inventory.len() == 0 {
+                    ui.label("Cargo hold is empty");
+                    return;
+                }
+
+                let form = self.forms.entry(ship.symbol.clone()).or_default();
+                egui::ComboBox::from_id_source(format!("transfer_to_{}", ship.symbol))
+                    .selected_text(&form.to)
+                    .show_ui(ui, |ui| {
+                        for p in partners {
+                            ui.selectable_value(
+                                &mut form.to,
+                                p.symbol.clone(),
+                                format!("{} ({} free)", p.symbol, free_space(p)),
+                            );
+                        }
+                    });
+                egui::ComboBox::from_id_source(format!("transfer_good_{}", ship.symbol))
+                    .selected_text(&form.trade_symbol)
+                    .show_ui(ui, |ui| {
+                        for item in &ship.cargo.inventory {
+                            ui.selectable_value(
+                                &mut form.trade_symbol,
+                                item.symbol.clone(),
+                                format!("{} x{}", item.symbol, item.units),
+                            );
+                        }
+                    });
+                ui.add(egui::DragValue::new(&mut form.units).clamp_range(0..=ship.cargo.units));
+
+                let check = match partners.iter().find(|p| p.symbol == form.to) {
+                    Some(to) => validate_transfer(ship, to, &form.trade_symbol, form.units),
+                    None => Err("Pick a ship to transfer to".to_owned()),
+                };
+                let button = ui.add_enabled(check.is_ok(), egui::Button::new("Transfer"));
+                if let Err(e) = check {
+                    button.on_disabled_hover_text(e);
+                } else if button.clicked() {
+                    transfer = Some(Transfer {
+                        from: ship.symbol.clone(),
+                        to: form.to.clone(),
+                        trade_symbol: form.trade_symbol.clone(),
+                        units: form.units,
+                    });
+                }
+            });
+
+        transfer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ship(symbol: &str, waypoint: &str, status: ShipNavStatus, ore: i32) -> Ship {
+        let mut ship = Ship::default();
+        ship.symbol = symbol.to_owned();
+        ship.nav.waypoint_symbol = waypoint.to_owned();
+        ship.nav.status = status;
+        ship.cargo.capacity = 30;
+        ship.cargo.units = ore;
+        if ore > 0 {
+            ship.cargo.inventory.push(ShipCargoItem {
+                symbol: "IRON_ORE".to_owned(),
+                units: ore,
+                ..Default::default()
+            });
+        }
+        ship
+    }
+
+    #[test]
+    fn allows_a_transfer_between_docked_ships() {
+        let from = ship("A", "X1-A-1", ShipNavStatus::Docked, 10);
+        let to = ship("B", "X1-A-1", ShipNavStatus::Docked, 20);
+        assert_eq!(validate_transfer(&from, &to, "IRON_ORE", 10), Ok(()));
+    }
+
+    #[test]
+    fn needs_something_to_transfer() {
+        let from = ship("A", "X1-A-1", ShipNavStatus::Docked, 10);
+        let to = ship("B", "X1-A-1", ShipNavStatus::Docked, 0);
+        assert!(validate_transfer(&from, &to, "IRON_ORE", 0).is_err());
+        assert!(validate_transfer(&from, &to, "IRON_ORE", -5).is_err());
+    }
+
+    #[test]
+    fn needs_ships_side_by_side() {
+        let from = ship("A", "X1-A-1", ShipNavStatus::Docked, 10);
+        let elsewhere = ship("B", "X1-A-2", ShipNavStatus::Docked, 0);
+        let in_orbit = ship("C", "X1-A-1", ShipNavStatus::InOrbit, 0);
+        assert!(validate_transfer(&from, &elsewhere, "IRON_ORE", 5).is_err());
+        assert!(validate_transfer(&from, &in_orbit, "IRON_ORE", 5).is_err());
+
+        let moving = ship("D", "X1-A-1", ShipNavStatus::InTransit, 10);
+        let also_moving = ship("E", "X1-A-1", ShipNavStatus::InTransit, 0);
+        assert!(validate_transfer(&moving, &also_moving, "IRON_ORE", 5).is_err());
+    }
+
+    #[test]
+    fn needs_the_goods_and_the_space() {
+        let from = ship("A", "X1-A-1", ShipNavStatus::InOrbit, 10);
+        let to = ship("B", "X1-A-1", ShipNavStatus::InOrbit, 25);
+        assert!(validate_transfer(&from, &to, "IRON_ORE", 11).is_err());
+        assert!(validate_transfer(&from, &to, "COPPER_ORE", 1).is_err());
+        assert!(validate_transfer(&from, &to, "IRON_ORE", 6).is_err());
+        assert_eq!(validate_transfer(&from, &to, "IRON_ORE", 5), Ok(()));
+    }
+}
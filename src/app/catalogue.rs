@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use spacedust::models::*;
+
+/// Where the catalogue is kept between runs. It's far too big for the app state blob
+pub const CATALOGUE_PATH: &str = "system_catalogue.json";
+
+/// Every system in the universe, fetched once per server reset and kept on disk
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SystemCatalogue {
+    /// Reset date reported by the server when the catalogue was filled
+    pub reset_date: String,
+    pub complete: bool,
+    systems: Vec<System>,
+
+    #[serde(skip)]
+    index: HashMap<String, usize>,
+    /// Set once we've compared `reset_date` with the server this run
+    #[serde(skip)]
+    pub checked: bool,
+    /// Set from the UI to pull the bulk dump instead of paging through the API
+    #[serde(skip)]
+    pub download_requested: bool,
+}
+
+impl SystemCatalogue {
+    pub fn load(path: &str) -> Self {
+        let mut catalogue: SystemCatalogue = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        catalogue.reindex();
+        catalogue
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| e.to_string())
+    }
+
+    /// There's no file system in the browser, so the catalogue is fetched again each visit
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self, _path: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn reindex(&mut self) {
+        self.index = self
+            .systems
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.symbol.clone(), i))
+            .collect();
+    }
+
+    /// Drops everything, e.g. after the server has been reset
+    pub fn clear(&mut self, reset_date: &str) {
+        self.systems.clear();
+        self.index.clear();
+        self.complete = false;
+        self.reset_date = reset_date.to_owned();
+    }
+
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    pub fn systems(&self) -> &[System] {
+        &self.systems
+    }
+
+    /// Adds systems, replacing any we already have with the same symbol
+    pub fn add(&mut self, systems: Vec<System>) {
+        for system in systems {
+            match self.index.get(&system.symbol) {
+                Some(i) => self.systems[*i] = system,
+                None => {
+                    self.index.insert(system.symbol.clone(), self.systems.len());
+                    self.systems.push(system);
+                }
+            }
+        }
+    }
+
+    /// Loads the bulk systems.json dump, returning how many systems it held
+    pub fn import_json(&mut self, json: &str) -> Result<usize, String> {
+        let systems: Vec<System> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let count = systems.len();
+        self.add(systems);
+        self.complete = true;
+        Ok(count)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&System> {
+        self.index.get(symbol).map(|i| &self.systems[*i])
+    }
+
+    pub fn in_sector(&self, sector: &str) -> Vec<&System> {
+        self.systems
+            .iter()
+            .filter(|s| s.sector_symbol == sector)
+            .collect()
+    }
+
+    pub fn of_type(&self, system_type: SystemType) -> Vec<&System> {
+        self.systems
+            .iter()
+            .filter(|s| s.r#type == system_type)
+            .collect()
+    }
+
+    pub fn with_faction(&self, faction: &str) -> Vec<&System> {
+        self.systems
+            .iter()
+            .filter(|s| s.factions.iter().any(|f| f.symbol == faction))
+            .collect()
+    }
+
+    /// The `count` systems closest to a point, nearest first
+    pub fn nearest(&self, x: i32, y: i32, count: usize) -> Vec<&System> {
+        let mut by_distance: Vec<(i64, &System)> = self
+            .systems
+            .iter()
+            .map(|s| {
+                let dx = (s.x - x) as i64;
+                let dy = (s.y - y) as i64;
+                (dx * dx + dy * dy, s)
+            })
+            .collect();
+        by_distance.sort_by_key(|(d, _)| *d);
+        by_distance
+            .into_iter()
+            .take(count)
+            .map(|(_, s)| s)
+            .collect()
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::time::Duration;
+
+use egui::Ui;
+use spacedust::apis::configuration::Configuration;
+use strum::IntoEnumIterator;
+
+use crate::message_handler::Message;
+
+pub const CONFIG_PATH: &str = "cyan_fleet_control.toml";
+pub const DEFAULT_BASE_URL: &str = "https://api.spacetraders.io/v2";
+pub const DATABASE_PATH: &str = "cyan_fleet_control.db";
+pub const DEFAULT_API_PORT: u16 = 7878;
+
+const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+const USAGE: &str = "Usage: cyan_fleet_control [--config PATH] [--base-url URL] \
+[--profile NAME] [--token TOKEN] [--log-level LEVEL] [--database PATH] [--api-port PORT]";
+
+// Settings come from the config file, then environment variables, then command line
+// flags, each overriding the last. Overrides are kept apart from the file's values so
+// saving from the Settings window never writes them out
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub token: String,
+}
+
+/// Values set by environment variables or command line flags
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub base_url: Option<String>,
+    pub profile: Option<String>,
+    pub token: Option<String>,
+    pub log_level: Option<String>,
+    pub database_path: Option<String>,
+    pub api_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub base_url: String,
+    /// Which of `profiles` to play as
+    pub profile: String,
+    pub profiles: Vec<Profile>,
+    /// Seconds between runs of each message, by name. Unlisted messages run every round
+    pub refresh_intervals: BTreeMap<String, u64>,
+    /// Pause after each message. This is a flat delay rather than a limit on requests
+    /// per second, as a message can make any number of requests
+    pub message_delay_ms: u64,
+    pub log_level: String,
+    pub database_path: String,
+    /// Port for the control API on localhost, which is off when unset
+    pub api_port: Option<u16>,
+    #[serde(skip)]
+    pub overrides: Overrides,
+    /// Where the file was loaded from and is saved to
+    #[serde(skip)]
+    pub path: String,
+    /// Result of the last save, shown in the Settings window
+    #[serde(skip)]
+    pub status: Option<String>,
+    #[serde(skip)]
+    pub token_entry: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            profile: String::new(),
+            profiles: vec![],
+            refresh_intervals: BTreeMap::new(),
+            message_delay_ms: 1000,
+            log_level: "info".to_owned(),
+            database_path: DATABASE_PATH.to_owned(),
+            api_port: None,
+            overrides: Overrides::default(),
+            path: CONFIG_PATH.to_owned(),
+            status: None,
+            token_entry: String::new(),
+        }
+    }
+}
+
+/// The value following a flag
+fn flag_value(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} needs a value\n{}", flag, USAGE))
+}
+
+fn parse_port(text: &str) -> Result<u16, String> {
+    text.parse()
+        .map_err(|_| format!("Invalid port: {}\n{}", text, USAGE))
+}
+
+impl Config {
+    /// Reads the config file and applies environment and command line overrides. A
+    /// missing file just means defaults
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        Self::load_with(args, |name| env::var(name).ok())
+    }
+
+    /// `load` with the environment looked up through `var`
+    fn load_with(
+        args: impl IntoIterator<Item = String>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let mut path = var("CYAN_CONFIG").unwrap_or(CONFIG_PATH.to_owned());
+        let mut overrides = Overrides {
+            base_url: var("SPACETRADERS_BASE_URL"),
+            profile: var("CYAN_PROFILE"),
+            token: var("SPACETRADERS_TOKEN"),
+            log_level: var("CYAN_LOG_LEVEL"),
+            database_path: var("CYAN_DATABASE"),
+            api_port: match var("CYAN_API_PORT") {
+                Some(port) => Some(parse_port(&port)?),
+                None => None,
+            },
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => path = flag_value(&arg, &mut args)?,
+                "--base-url" => overrides.base_url = Some(flag_value(&arg, &mut args)?),
+                "--profile" => overrides.profile = Some(flag_value(&arg, &mut args)?),
+                "--token" => overrides.token = Some(flag_value(&arg, &mut args)?),
+                "--log-level" => overrides.log_level = Some(flag_value(&arg, &mut args)?),
+                "--database" => overrides.database_path = Some(flag_value(&arg, &mut args)?),
+                "--api-port" => {
+                    overrides.api_port = Some(parse_port(&flag_value(&arg, &mut args)?)?)
+                }
+                "--help" | "-h" => return Err(USAGE.to_owned()),
+                _ => return Err(format!("Unknown argument: {}\n{}", arg, USAGE)),
+            }
+        }
+
+        let mut config: Config = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        };
+        config.path = path;
+        config.overrides = overrides;
+        Ok(config)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) -> Result<(), String> {
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, text).map_err(|e| e.to_string())
+    }
+
+    /// There are no files in the browser. The config goes to local storage with the
+    /// rest of the app state instead, see `persistence::save`
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Where saved settings go, for showing to the user
+    fn saved_to(&self) -> &str {
+        if cfg!(target_arch = "wasm32") {
+            "browser storage"
+        } else {
+            &self.path
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.overrides.base_url.as_deref().unwrap_or(&self.base_url)
+    }
+
+    pub fn profile(&self) -> &str {
+        self.overrides.profile.as_deref().unwrap_or(&self.profile)
+    }
+
+    /// The token given directly, or else the active profile's
+    pub fn token(&self) -> Option<String> {
+        if let Some(token) = &self.overrides.token {
+            return Some(token.clone());
+        }
+        self.profiles
+            .iter()
+            .find(|p| p.name == self.profile())
+            .map(|p| p.token.clone())
+            .filter(|t| t.len() > 0)
+    }
+
+    pub fn log_level(&self) -> &str {
+        self.overrides
+            .log_level
+            .as_deref()
+            .unwrap_or(&self.log_level)
+    }
+
+    pub fn database_path(&self) -> &str {
+        self.overrides
+            .database_path
+            .as_deref()
+            .unwrap_or(&self.database_path)
+    }
+
+    pub fn api_port(&self) -> Option<u16> {
+        self.overrides.api_port.or(self.api_port)
+    }
+
+    pub fn refresh_interval(&self, message: Message) -> Duration {
+        let seconds = self
+            .refresh_intervals
+            .get(&format!("{:?}", message))
+            .copied()
+            .unwrap_or(0);
+        Duration::from_secs(seconds)
+    }
+
+    pub fn message_delay(&self) -> Duration {
+        Duration::from_millis(self.message_delay_ms)
+    }
+
+    /// Points the API client at the configured server and account
+    pub fn apply(&self, conf: &mut Configuration) {
+        conf.base_path = self.base_url().trim_end_matches('/').to_owned();
+        conf.bearer_access_token = self.token();
+    }
+
+    /// Edits the file settings. Returns true when the user saved them
+    pub fn render(&mut self, ui: &mut Ui) -> bool {
+        let mut saved = false;
+        ui.label(format!("Settings are saved to {}", self.saved_to()));
+
+        egui::Grid::new("settings").show(ui, |ui| {
+            ui.label("API base URL");
+            ui.text_edit_singleline(&mut self.base_url);
+            overridden_label(ui, &self.overrides.base_url);
+            ui.end_row();
+
+            ui.label("Profile");
+            egui::ComboBox::from_id_source("settings_profile")
+                .selected_text(&self.profile)
+                .show_ui(ui, |ui| {
+                    for p in &self.profiles {
+                        ui.selectable_value(&mut self.profile, p.name.clone(), &p.name);
+                    }
+                });
+            overridden_label(ui, &self.overrides.profile);
+            ui.end_row();
+
+            ui.label("Log level");
+            egui::ComboBox::from_id_source("settings_log_level")
+                .selected_text(&self.log_level)
+                .show_ui(ui, |ui| {
+                    for level in LOG_LEVELS {
+                        ui.selectable_value(&mut self.log_level, level.to_owned(), level);
+                    }
+                });
+            overridden_label(ui, &self.overrides.log_level);
+            ui.end_row();
+
+            ui.label("Database");
+            ui.text_edit_singleline(&mut self.database_path);
+            overridden_label(ui, &self.overrides.database_path);
+            ui.end_row();
+
+            ui.label("Control API port");
+            ui.horizontal(|ui| {
+                let mut enabled = self.api_port.is_some();
+                ui.checkbox(&mut enabled, "");
+                match (enabled, &mut self.api_port) {
+                    (true, Some(port)) => {
+                        ui.add(egui::DragValue::new(port).clamp_range(1024..=65535));
+                    }
+                    (true, None) => self.api_port = Some(DEFAULT_API_PORT),
+                    (false, _) => self.api_port = None,
+                }
+            });
+            overridden_label(ui, &self.overrides.api_port.map(|p| p.to_string()));
+            ui.end_row();
+
+            ui.label("Delay between messages (ms)");
+            ui.add(egui::DragValue::new(&mut self.message_delay_ms).clamp_range(0..=60_000));
+            ui.end_row();
+        });
+        ui.label("Log level, database and control API changes take effect on restart");
+        ui.label(
+            "The delay is a flat pause after each message, not a requests per second limit. \
+            A message can make several requests, so raise it if the server starts refusing them",
+        );
+
+        ui.separator();
+        ui.label("Profiles");
+        if self.overrides.token.is_some() {
+            ui.label("A token was given on the command line or in SPACETRADERS_TOKEN");
+        }
+        let mut remove = None;
+        for (i, p) in self.profiles.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut p.name).desired_width(100.0));
+                ui.add(egui::TextEdit::singleline(&mut p.token).password(true));
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.profiles.remove(i);
+        }
+        if ui.button("Add profile").clicked() {
+            self.profiles.push(Profile::default());
+        }
+
+        ui.separator();
+        ui.label("Refresh intervals (seconds, 0 for every round)");
+        egui::Grid::new("settings_intervals").show(ui, |ui| {
+            for message in Message::iter() {
+                let name = format!("{:?}", message);
+                let mut seconds = self.refresh_intervals.get(&name).copied().unwrap_or(0);
+                ui.label(&name);
+                // The browser never runs these, see `Message::is_blocking`
+                if cfg!(target_arch = "wasm32") && message.is_blocking() {
+                    ui.label("desktop app only");
+                    ui.end_row();
+                    continue;
+                }
+                if ui
+                    .add(egui::DragValue::new(&mut seconds).clamp_range(0..=86_400))
+                    .changed()
+                {
+                    if seconds == 0 {
+                        self.refresh_intervals.remove(&name);
+                    } else {
+                        self.refresh_intervals.insert(name, seconds);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        if ui.button("Save").clicked() {
+            self.save_with_status();
+            saved = true;
+        }
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+        saved
+    }
+
+    /// Asks for a token when none is configured. Returns true once one is entered,
+    /// which is kept as the active profile's token
+    pub fn render_token_prompt(&mut self, ui: &mut Ui) -> bool {
+        ui.label("Paste your agent's token to connect");
+        ui.add(egui::TextEdit::singleline(&mut self.token_entry).password(true));
+        let token = self.token_entry.trim().to_owned();
+        let button = ui.add_enabled(token.len() > 0, egui::Button::new("Connect"));
+        if !button.clicked() {
+            return false;
+        }
+
+        if self.profile().len() == 0 {
+            self.profile = "default".to_owned();
+        }
+        let name = self.profile().to_owned();
+        match self.profiles.iter_mut().find(|p| p.name == name) {
+            Some(p) => p.token = token,
+            None => self.profiles.push(Profile { name, token }),
+        }
+        self.token_entry.clear();
+        self.save_with_status();
+        true
+    }
+
+    fn save_with_status(&mut self) {
+        self.status = Some(match self.save() {
+            Ok(_) => format!("Saved to {}", self.saved_to()),
+            Err(e) => format!("Could not save: {}", e),
+        });
+    }
+}
+
+fn overridden_label(ui: &mut Ui, value: &Option<String>) {
+    match value {
+        Some(v) => ui.label(format!("overridden: {}", v)),
+        None => ui.label(""),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    /// Writes a config file for one test and returns its path
+    fn config_file(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "cyan_config_test_{}_{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, text).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    fn load(list: &[&str], env: &[(&str, &str)]) -> Result<Config, String> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::load_with(args(list), |name| env.get(name).cloned())
+    }
+
+    #[test]
+    fn defaults_without_a_file() {
+        let config = load(&["--config", "/nonexistent/cyan.toml"], &[]).unwrap();
+        assert_eq!(config.base_url(), DEFAULT_BASE_URL);
+        assert_eq!(config.database_path(), DATABASE_PATH);
+        assert_eq!(config.api_port(), None);
+        assert_eq!(config.path, "/nonexistent/cyan.toml");
+    }
+
+    #[test]
+    fn environment_overrides_the_file_and_flags_override_both() {
+        let path = config_file(
+            "precedence",
+            "base_url = \"http://file\"\nlog_level = \"warn\"\napi_port = 1111\n",
+        );
+        let env = [
+            ("CYAN_CONFIG", path.as_str()),
+            ("SPACETRADERS_BASE_URL", "http://env"),
+            ("CYAN_LOG_LEVEL", "debug"),
+            ("CYAN_API_PORT", "2222"),
+        ];
+
+        let config = load(&[], &env).unwrap();
+        assert_eq!(config.base_url(), "http://env");
+        assert_eq!(config.log_level(), "debug");
+        assert_eq!(config.api_port(), Some(2222));
+        // Overrides are kept apart, so saving writes the file's own values back
+        assert_eq!(config.base_url, "http://file");
+
+        let config = load(&["--base-url", "http://flag", "--api-port", "3333"], &env).unwrap();
+        assert_eq!(config.base_url(), "http://flag");
+        assert_eq!(config.log_level(), "debug");
+        assert_eq!(config.api_port(), Some(3333));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn config_flag_overrides_the_environment_path() {
+        let from_env = config_file("env_path", "base_url = \"http://env-file\"\n");
+        let from_flag = config_file("flag_path", "base_url = \"http://flag-file\"\n");
+        let config = load(&["--config", &from_flag], &[("CYAN_CONFIG", &from_env)]).unwrap();
+        assert_eq!(config.base_url(), "http://flag-file");
+        assert_eq!(config.path, from_flag);
+
+        let _ = std::fs::remove_file(from_env);
+        let _ = std::fs::remove_file(from_flag);
+    }
+
+    #[test]
+    fn token_flag_beats_the_profile() {
+        let path = config_file(
+            "token",
+            "profile = \"main\"\n[[profiles]]\nname = \"main\"\ntoken = \"from-file\"\n",
+        );
+        assert_eq!(
+            load(&["--config", &path], &[]).unwrap().token().as_deref(),
+            Some("from-file")
+        );
+        let config = load(&["--config", &path, "--token", "from-flag"], &[]).unwrap();
+        assert_eq!(config.token().as_deref(), Some("from-flag"));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(load(&["--verbose"], &[]).is_err());
+        assert!(load(&["--api-port"], &[]).is_err());
+        assert!(load(&["--api-port", "not-a-port"], &[]).is_err());
+        assert!(load(&[], &[("CYAN_API_PORT", "99999")]).is_err());
+    }
+}
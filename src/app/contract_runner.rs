@@ -0,0 +1,733 @@
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::contracts::{is_complete, units_in_cargo};
+use crate::fleet_ops;
+use crate::fleet_table::automated_by;
+use crate::history::now;
+use crate::navigation::{distance, find_waypoint};
+use crate::spacetraders::ShipWithNav;
+use crate::AppData;
+
+/// Fuel price used for estimates when no market has reported one yet
+const DEFAULT_FUEL_PRICE: i64 = 100;
+/// Refuel whenever a ship is docked at a market with less than this fraction left
+const REFUEL_RATIO: f32 = 0.5;
+
+/// Where a contract good comes from
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Source {
+    Mine(String),
+    Buy(String),
+}
+
+impl Source {
+    pub fn waypoint(&self) -> &str {
+        match self {
+            Source::Mine(w) | Source::Buy(w) => w,
+        }
+    }
+}
+
+/// One ship working on one delivery of a contract
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ContractJob {
+    pub ship_symbol: String,
+    pub contract_id: String,
+    pub trade_symbol: String,
+    pub destination: String,
+    pub source: Source,
+    #[serde(skip)]
+    pub cooldown_until: i64,
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ContractRunner {
+    /// Ids of contracts the runner is allowed to work on
+    pub automated: Vec<String>,
+    pub jobs: Vec<ContractJob>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProfitEstimate {
+    pub revenue: i64,
+    pub goods: i64,
+    pub fuel: i64,
+    /// Deliveries we couldn't find a source for
+    pub unsourced: Vec<String>,
+}
+
+impl ProfitEstimate {
+    pub fn net(&self) -> i64 {
+        self.revenue - self.goods - self.fuel
+    }
+
+    pub fn render(&self, ui: &mut Ui) {
+        ui.label(format!(
+            "Estimated profit: {} (revenue {}, goods {}, fuel {})",
+            self.net(),
+            self.revenue,
+            self.goods,
+            self.fuel
+        ));
+        if self.unsourced.len() > 0 {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!("No known source for: {}", self.unsourced.join(", ")),
+            );
+        }
+    }
+}
+
+impl ContractRunner {
+    pub fn is_automated(&self, contract_id: &str) -> bool {
+        self.automated.iter().any(|c| c == contract_id)
+    }
+
+    pub fn set_automated(&mut self, contract_id: &str, automated: bool) {
+        self.automated.retain(|c| c != contract_id);
+        if automated {
+            self.automated.push(contract_id.to_owned());
+        } else {
+            self.jobs.retain(|j| j.contract_id != contract_id);
+        }
+    }
+
+    pub fn job_for_ship(&self, ship_symbol: &str) -> Option<&ContractJob> {
+        self.jobs.iter().find(|j| j.ship_symbol == ship_symbol)
+    }
+}
+
+fn purchase_price(market: &Market, trade_symbol: &str) -> Option<i64> {
+    market
+        .trade_goods
+        .as_ref()?
+        .iter()
+        .find(|g| g.symbol == trade_symbol)
+        .map(|g| g.purchase_price as i64)
+}
+
+fn sells(market: &Market, trade_symbol: &str) -> bool {
+    purchase_price(market, trade_symbol).is_some()
+        || market
+            .exports
+            .iter()
+            .chain(market.exchange.iter())
+            .any(|g| g.symbol.to_string() == trade_symbol)
+}
+
+/// Whether a market takes a good off our hands
+pub fn buys(market: &Market, trade_symbol: &str) -> bool {
+    market
+        .imports
+        .iter()
+        .chain(market.exchange.iter())
+        .any(|g| g.symbol.to_string() == trade_symbol)
+        || market.trade_goods.as_ref().map_or(false, |goods| {
+            goods.iter().any(|g| g.symbol == trade_symbol)
+        })
+}
+
+pub fn fuel_price(markets: &[Market]) -> i64 {
+    markets
+        .iter()
+        .filter_map(|m| purchase_price(m, "FUEL"))
+        .min()
+        .unwrap_or(DEFAULT_FUEL_PRICE)
+}
+
+pub fn can_mine(ship: &ShipWithNav) -> bool {
+    ship.ship.mounts.iter().any(|m| {
+        matches!(
+            m.symbol,
+            ship_mount::Symbol::MountMiningLaserI
+                | ship_mount::Symbol::MountMiningLaserIi
+                | ship_mount::Symbol::MountMiningLaserIii
+        )
+    })
+}
+
+/// Buying is preferred over mining: the cheapest market with a known price wins, then any
+/// market that trades the good, then the asteroid field closest to the destination
+pub fn choose_source(
+    trade_symbol: &str,
+    destination: &str,
+    markets: &[Market],
+    waypoints: &[Waypoint],
+) -> Option<Source> {
+    let cheapest = markets
+        .iter()
+        .filter_map(|m| purchase_price(m, trade_symbol).map(|p| (p, m)))
+        .min_by_key(|(p, _)| *p);
+    if let Some((_, m)) = cheapest {
+        return Some(Source::Buy(m.symbol.clone()));
+    }
+
+    if let Some(m) = markets.iter().find(|m| sells(m, trade_symbol)) {
+        return Some(Source::Buy(m.symbol.clone()));
+    }
+
+    let to = find_waypoint(waypoints, destination);
+    waypoints
+        .iter()
+        .filter(|w| w.r#type == WaypointType::AsteroidField)
+        .min_by_key(|w| to.map(|to| distance(w, to) as i64).unwrap_or(0))
+        .map(|w| Source::Mine(w.symbol.clone()))
+}
+
+/// What a contract would earn if we sourced every outstanding delivery ourselves
+pub fn estimate_profit(
+    contract: &Contract,
+    ships: &[ShipWithNav],
+    markets: &[Market],
+    waypoints: &[Waypoint],
+) -> ProfitEstimate {
+    let mut estimate = ProfitEstimate {
+        revenue: (contract.terms.payment.on_accepted + contract.terms.payment.on_fulfilled) as i64,
+        ..Default::default()
+    };
+
+    let capacity = ships
+        .iter()
+        .map(|s| s.ship.cargo.capacity)
+        .max()
+        .unwrap_or(0)
+        .max(1) as i64;
+    let fuel_price = fuel_price(markets);
+
+    for delivery in contract.terms.deliver.iter().flatten() {
+        let remaining = (delivery.units_required - delivery.units_fulfilled) as i64;
+        if remaining <= 0 {
+            continue;
+        }
+
+        let source = match choose_source(
+            &delivery.trade_symbol,
+            &delivery.destination_symbol,
+            markets,
+            waypoints,
+        ) {
+            Some(s) => s,
+            None => {
+                estimate.unsourced.push(delivery.trade_symbol.clone());
+                continue;
+            }
+        };
+
+        if let Source::Buy(market) = &source {
+            let price = markets
+                .iter()
+                .find(|m| &m.symbol == market)
+                .and_then(|m| purchase_price(m, &delivery.trade_symbol))
+                .unwrap_or(0);
+            estimate.goods += price * remaining;
+        }
+
+        // Cruising burns roughly one unit of fuel per unit of distance, there and back
+        let trips = (remaining + capacity - 1) / capacity;
+        let leg = match (
+            find_waypoint(waypoints, source.waypoint()),
+            find_waypoint(waypoints, &delivery.destination_symbol),
+        ) {
+            (Some(a), Some(b)) => distance(a, b).round() as i64,
+            _ => 0,
+        };
+        estimate.fuel += trips * leg * 2 * fuel_price;
+    }
+
+    estimate
+}
+
+fn at_market(data: &AppData, ship: &ShipWithNav) -> bool {
+    data.markets
+        .iter()
+        .any(|m| m.symbol == ship.ship.nav.waypoint_symbol)
+}
+
+/// Gives every outstanding delivery of an automated contract a ship, if one is free
+fn assign_jobs(data: &mut AppData) {
+    let mut new_jobs: Vec<ContractJob> = vec![];
+
+    for contract in &data.contracts {
+        if !contract.accepted
+            || contract.fulfilled
+            || !data.contract_runner.is_automated(&contract.id)
+        {
+            continue;
+        }
+
+        for delivery in contract.terms.deliver.iter().flatten() {
+            if delivery.units_fulfilled >= delivery.units_required {
+                continue;
+            }
+
+            let has_job = data
+                .contract_runner
+                .jobs
+                .iter()
+                .chain(new_jobs.iter())
+                .any(|j| j.contract_id == contract.id && j.trade_symbol == delivery.trade_symbol);
+            if has_job {
+                continue;
+            }
+
+            let source = match choose_source(
+                &delivery.trade_symbol,
+                &delivery.destination_symbol,
+                &data.markets,
+                &data.waypoints,
+            ) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let ship = data.ships.iter().find(|s| {
+                let busy = automated_by(data, &s.ship.symbol, None).is_some()
+                    || new_jobs.iter().any(|j| j.ship_symbol == s.ship.symbol);
+                let capable = match source {
+                    Source::Mine(_) => can_mine(s),
+                    Source::Buy(_) => s.ship.cargo.capacity > 0,
+                };
+                !busy && capable
+            });
+
+            if let Some(ship) = ship {
+                new_jobs.push(ContractJob {
+                    ship_symbol: ship.ship.symbol.clone(),
+                    contract_id: contract.id.clone(),
+                    trade_symbol: delivery.trade_symbol.clone(),
+                    destination: delivery.destination_symbol.clone(),
+                    source,
+                    cooldown_until: 0,
+                });
+            }
+        }
+    }
+
+    for job in new_jobs {
+        data.log.push(format!(
+            "{}: assigned to deliver {} for contract {}",
+            job.ship_symbol, job.trade_symbol, job.contract_id
+        ));
+        data.contract_runner.jobs.push(job);
+    }
+}
+
+/// Moves a ship to a waypoint, leaving the dock first if needed
+fn go_to(data: &mut AppData, ship: &ShipWithNav, waypoint: &str) -> Result<(), String> {
+    if let ShipNavStatus::Docked = ship.ship.nav.status {
+        fleet_ops::orbit(data, &ship.ship.symbol)?;
+    }
+    fleet_ops::navigate(data, &ship.ship.symbol, waypoint)
+}
+
+fn ensure_docked(data: &mut AppData, ship: &ShipWithNav) -> Result<(), String> {
+    match ship.ship.nav.status {
+        ShipNavStatus::Docked => Ok(()),
+        _ => fleet_ops::dock(data, &ship.ship.symbol),
+    }
+}
+
+/// Sells cargo a job has no use for where there's a market for it and jettisons it
+/// otherwise
+fn clear_cargo(
+    data: &mut AppData,
+    ship: &ShipWithNav,
+    trade_symbol: &str,
+    units: i32,
+) -> Result<(), String> {
+    let sellable = data
+        .markets
+        .iter()
+        .find(|m| m.symbol == ship.ship.nav.waypoint_symbol)
+        .map_or(false, |m| buys(m, trade_symbol));
+    if sellable {
+        ensure_docked(data, ship)?;
+        if fleet_ops::sell(data, &ship.ship.symbol, trade_symbol, units).is_ok() {
+            return Ok(());
+        }
+    }
+    fleet_ops::jettison_cargo(data, &ship.ship.symbol, trade_symbol, units)
+}
+
+/// Advances one job by a single action. Returns the new cooldown, if any
+fn step_job(data: &mut AppData, job: &ContractJob) -> Result<Option<i64>, String> {
+    let mut ship = match data.ships.iter().find(|s| s.ship.symbol == job.ship_symbol) {
+        Some(s) => s.clone(),
+        None => return Ok(None),
+    };
+
+    if let ShipNavStatus::InTransit = ship.ship.nav.status {
+        return Ok(None);
+    }
+
+    let remaining = data
+        .contracts
+        .iter()
+        .find(|c| c.id == job.contract_id)
+        .and_then(|c| c.terms.deliver.as_ref())
+        .and_then(|d| d.iter().find(|d| d.trade_symbol == job.trade_symbol))
+        .map(|d| d.units_required - d.units_fulfilled)
+        .unwrap_or(0);
+    let held = units_in_cargo(&ship, &job.trade_symbol);
+    let free = ship.ship.cargo.capacity - ship.ship.cargo.units;
+    let here = ship.ship.nav.waypoint_symbol.clone();
+
+    if at_market(data, &ship)
+        && (ship.ship.fuel.current as f32) < ship.ship.fuel.capacity as f32 * REFUEL_RATIO
+    {
+        let refuelled =
+            ensure_docked(data, &ship).and_then(|_| fleet_ops::refuel(data, &ship.ship.symbol));
+        if refuelled.is_ok() {
+            return Ok(None);
+        }
+        // Already reported, carry on with the job rather than stall on the fuel
+        ship = match data.ships.iter().find(|s| s.ship.symbol == job.ship_symbol) {
+            Some(s) => s.clone(),
+            None => return Ok(None),
+        };
+    }
+
+    // Mining brings up whatever is there, so make room by getting rid of the rest
+    if free == 0 {
+        let other = ship
+            .ship
+            .cargo
+            .inventory
+            .iter()
+            .find(|i| i.symbol != job.trade_symbol)
+            .map(|i| (i.symbol.clone(), i.units));
+        if let Some((trade_symbol, units)) = other {
+            clear_cargo(data, &ship, &trade_symbol, units)?;
+            return Ok(None);
+        }
+    }
+
+    // Deliver once we hold everything that's left or can't carry any more
+    if held > 0 && (held >= remaining || free == 0) {
+        if here != job.destination {
+            go_to(data, &ship, &job.destination)?;
+            return Ok(None);
+        }
+        ensure_docked(data, &ship)?;
+        fleet_ops::deliver(
+            data,
+            &ship.ship.symbol,
+            &job.contract_id,
+            &job.trade_symbol,
+            held.min(remaining),
+        )?;
+        return Ok(None);
+    }
+
+    if free == 0 {
+        return Err(format!(
+            "{}: cargo full without any {}",
+            ship.ship.symbol, job.trade_symbol
+        ));
+    }
+
+    if here != job.source.waypoint() {
+        go_to(data, &ship, job.source.waypoint())?;
+        return Ok(None);
+    }
+
+    match &job.source {
+        Source::Buy(_) => {
+            ensure_docked(data, &ship)?;
+            fleet_ops::purchase(
+                data,
+                &ship.ship.symbol,
+                &job.trade_symbol,
+                (remaining - held).min(free),
+            )?;
+            Ok(None)
+        }
+        Source::Mine(_) => {
+            if let ShipNavStatus::Docked = ship.ship.nav.status {
+                fleet_ops::orbit(data, &ship.ship.symbol)?;
+            }
+            fleet_ops::extract(data, &ship.ship.symbol).map(Some)
+        }
+    }
+}
+
+/// Runs one round of contract automation: fulfils finished contracts, hands out
+/// deliveries to free ships and advances every job by one action
+pub fn tick(data: &mut AppData) {
+    let finished: Vec<String> = data
+        .contracts
+        .iter()
+        .filter(|c| {
+            data.contract_runner.is_automated(&c.id) && c.accepted && !c.fulfilled && is_complete(c)
+        })
+        .map(|c| c.id.clone())
+        .collect();
+    for id in finished {
+        let _ = fleet_ops::fulfill(data, &id);
+    }
+
+    // Drop jobs whose delivery is done or whose contract went away
+    let contracts = &data.contracts;
+    let runner = &mut data.contract_runner;
+    runner.jobs.retain(|j| {
+        runner.automated.iter().any(|c| c == &j.contract_id)
+            && contracts
+                .iter()
+                .find(|c| c.id == j.contract_id)
+                .and_then(|c| c.terms.deliver.as_ref())
+                .and_then(|d| d.iter().find(|d| d.trade_symbol == j.trade_symbol))
+                .map(|d| d.units_fulfilled < d.units_required)
+                .unwrap_or(false)
+    });
+
+    assign_jobs(data);
+
+    let time = now();
+    for i in 0..data.contract_runner.jobs.len() {
+        let job = data.contract_runner.jobs[i].clone();
+        if job.cooldown_until > time {
+            continue;
+        }
+
+        match step_job(data, &job) {
+            Ok(Some(cooldown)) => {
+                if let Some(j) = data.contract_runner.jobs.get_mut(i) {
+                    j.cooldown_until = time + cooldown;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => data.report_error(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoint(symbol: &str, r#type: WaypointType, x: i32, y: i32) -> Waypoint {
+        Waypoint {
+            symbol: symbol.to_owned(),
+            r#type,
+            system_symbol: "X1-A".to_owned(),
+            x,
+            y,
+            ..Default::default()
+        }
+    }
+
+    /// The destination at the origin, a market 50 units away and asteroid fields at 10
+    /// and 100
+    fn waypoints() -> Vec<Waypoint> {
+        vec![
+            waypoint("X1-A-DEST", WaypointType::Planet, 0, 0),
+            waypoint("X1-A-MARKET", WaypointType::Planet, 30, 40),
+            waypoint("X1-A-FAR", WaypointType::AsteroidField, 100, 0),
+            waypoint("X1-A-NEAR", WaypointType::AsteroidField, 10, 0),
+        ]
+    }
+
+    fn market(symbol: &str, prices: &[(&str, i32)]) -> Market {
+        Market {
+            symbol: symbol.to_owned(),
+            trade_goods: Some(
+                prices
+                    .iter()
+                    .map(|(good, price)| MarketTradeGood {
+                        symbol: good.to_string(),
+                        purchase_price: *price,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn contract(id: &str, deliveries: &[(&str, i32, i32)]) -> Contract {
+        Contract {
+            id: id.to_owned(),
+            accepted: true,
+            terms: Box::new(ContractTerms {
+                payment: Box::new(ContractPayment {
+                    on_accepted: 1000,
+                    on_fulfilled: 5000,
+                }),
+                deliver: Some(
+                    deliveries
+                        .iter()
+                        .map(|(good, required, fulfilled)| ContractDeliverGood {
+                            trade_symbol: good.to_string(),
+                            destination_symbol: "X1-A-DEST".to_owned(),
+                            units_required: *required,
+                            units_fulfilled: *fulfilled,
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn ship(symbol: &str, capacity: i32, miner: bool) -> ShipWithNav {
+        let mut ship = Ship::default();
+        ship.symbol = symbol.to_owned();
+        ship.nav.waypoint_symbol = "X1-A-DEST".to_owned();
+        ship.cargo.capacity = capacity;
+        if miner {
+            ship.mounts.push(ShipMount {
+                symbol: ship_mount::Symbol::MountMiningLaserI,
+                ..Default::default()
+            });
+        }
+        ShipWithNav {
+            ship,
+            destination: "X1-A-DEST".to_owned(),
+            route_target: String::new(),
+            actions: vec![],
+        }
+    }
+
+    fn data_with(contracts: Vec<Contract>, ships: Vec<ShipWithNav>) -> AppData {
+        let mut data = AppData::default();
+        for c in &contracts {
+            data.contract_runner.set_automated(&c.id, true);
+        }
+        data.contracts = contracts;
+        data.ships = ships;
+        data.waypoints = waypoints();
+        data
+    }
+
+    #[test]
+    fn buys_from_the_cheapest_market() {
+        let markets = vec![
+            market("X1-A-DEAR", &[("IRON_ORE", 20)]),
+            market("X1-A-MARKET", &[("IRON_ORE", 10)]),
+        ];
+        assert_eq!(
+            choose_source("IRON_ORE", "X1-A-DEST", &markets, &waypoints()),
+            Some(Source::Buy("X1-A-MARKET".to_owned()))
+        );
+    }
+
+    #[test]
+    fn buys_where_the_good_is_traded_without_a_known_price() {
+        let mut exporter = market("X1-A-MARKET", &[]);
+        exporter.exports.push(TradeGood {
+            symbol: TradeSymbol::IronOre,
+            ..Default::default()
+        });
+        let markets = vec![market("X1-A-OTHER", &[("COPPER_ORE", 5)]), exporter];
+        assert_eq!(
+            choose_source("IRON_ORE", "X1-A-DEST", &markets, &waypoints()),
+            Some(Source::Buy("X1-A-MARKET".to_owned()))
+        );
+    }
+
+    #[test]
+    fn mines_at_the_asteroid_field_nearest_the_destination() {
+        let markets = vec![market("X1-A-MARKET", &[("COPPER_ORE", 5)])];
+        assert_eq!(
+            choose_source("IRON_ORE", "X1-A-DEST", &markets, &waypoints()),
+            Some(Source::Mine("X1-A-NEAR".to_owned()))
+        );
+        let no_fields: Vec<Waypoint> = waypoints()
+            .into_iter()
+            .filter(|w| w.r#type != WaypointType::AsteroidField)
+            .collect();
+        assert_eq!(
+            choose_source("IRON_ORE", "X1-A-DEST", &markets, &no_fields),
+            None
+        );
+    }
+
+    #[test]
+    fn estimates_goods_and_fuel_for_what_is_left_to_deliver() {
+        let markets = vec![market("X1-A-MARKET", &[("IRON_ORE", 10), ("FUEL", 2)])];
+        // 25 units left in a 10 unit hold is 3 trips of 50 each way
+        let contract = contract("C1", &[("IRON_ORE", 30, 5), ("COPPER_ORE", 10, 10)]);
+        let estimate = estimate_profit(&contract, &[ship("S1", 10, false)], &markets, &waypoints());
+        assert_eq!(estimate.revenue, 6000);
+        assert_eq!(estimate.goods, 250);
+        assert_eq!(estimate.fuel, 3 * 50 * 2 * 2);
+        assert_eq!(estimate.net(), 6000 - 250 - 600);
+        assert!(estimate.unsourced.is_empty());
+    }
+
+    #[test]
+    fn estimates_list_deliveries_without_a_source() {
+        let no_fields: Vec<Waypoint> = waypoints()
+            .into_iter()
+            .filter(|w| w.r#type != WaypointType::AsteroidField)
+            .collect();
+        let contract = contract("C1", &[("IRON_ORE", 30, 0)]);
+        let estimate = estimate_profit(&contract, &[], &[], &no_fields);
+        assert_eq!(estimate.unsourced, vec!["IRON_ORE".to_owned()]);
+        assert_eq!(estimate.fuel, 0);
+        assert_eq!(estimate.net(), 6000);
+    }
+
+    #[test]
+    fn assigns_one_free_ship_to_each_outstanding_delivery() {
+        let mut data = data_with(
+            vec![contract(
+                "C1",
+                &[
+                    ("IRON_ORE", 30, 0),
+                    ("COPPER_ORE", 10, 0),
+                    ("GOLD_ORE", 5, 5),
+                ],
+            )],
+            vec![
+                ship("S1", 30, false),
+                ship("S2", 30, false),
+                ship("S3", 30, false),
+            ],
+        );
+        data.markets = vec![market(
+            "X1-A-MARKET",
+            &[("IRON_ORE", 10), ("COPPER_ORE", 10), ("GOLD_ORE", 10)],
+        )];
+        assign_jobs(&mut data);
+        let jobs: Vec<(&str, &str)> = data
+            .contract_runner
+            .jobs
+            .iter()
+            .map(|j| (j.ship_symbol.as_str(), j.trade_symbol.as_str()))
+            .collect();
+        assert_eq!(jobs, vec![("S1", "IRON_ORE"), ("S2", "COPPER_ORE")]);
+
+        // Running again doesn't hand out the same deliveries twice
+        assign_jobs(&mut data);
+        assert_eq!(data.contract_runner.jobs.len(), 2);
+    }
+
+    #[test]
+    fn only_miners_take_deliveries_that_have_to_be_mined() {
+        let mut data = data_with(
+            vec![contract("C1", &[("IRON_ORE", 30, 0)])],
+            vec![ship("HAULER", 30, false), ship("MINER", 30, true)],
+        );
+        assign_jobs(&mut data);
+        let job = data.contract_runner.job_for_ship("MINER").unwrap();
+        assert_eq!(job.source, Source::Mine("X1-A-NEAR".to_owned()));
+        assert!(data.contract_runner.job_for_ship("HAULER").is_none());
+    }
+
+    #[test]
+    fn leaves_contracts_that_are_not_automated_or_accepted() {
+        let mut unaccepted = contract("C2", &[("IRON_ORE", 30, 0)]);
+        unaccepted.accepted = false;
+        let mut data = data_with(
+            vec![contract("C1", &[("IRON_ORE", 30, 0)]), unaccepted],
+            vec![ship("S1", 30, true), ship("S2", 30, true)],
+        );
+        data.contract_runner.set_automated("C1", false);
+        assign_jobs(&mut data);
+        assert!(data.contract_runner.jobs.is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+use spacedust::models::*;
+
+use crate::history::now;
+use crate::navigation::ship_travel_seconds;
+use crate::spacetraders::ShipWithNav;
+
+/// Rough extraction throughput used for deadline estimates
+const UNITS_PER_EXTRACTION: i64 = 7;
+const EXTRACTION_COOLDOWN_SECONDS: i64 = 70;
+
+/// Seconds from now until an RFC 3339 timestamp, negative if it has passed
+pub fn seconds_until(timestamp: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|t| t.timestamp() - now())
+}
+
+pub fn format_countdown(seconds: i64) -> String {
+    if seconds <= 0 {
+        return "passed".to_owned();
+    }
+
+    let days = seconds / 86400;
+    let hours = (seconds % 86400) / 3600;
+    let minutes = (seconds % 3600) / 60;
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m {}s", minutes, seconds % 60)
+    }
+}
+
+pub fn units_in_cargo(ship: &ShipWithNav, trade_symbol: &str) -> i32 {
+    ship.ship
+        .cargo
+        .inventory
+        .iter()
+        .filter(|c| c.symbol == trade_symbol)
+        .map(|c| c.units)
+        .sum()
+}
+
+/// Credits earned per unit delivered, spreading both payments over every unit required
+pub fn payment_per_unit(contract: &Contract) -> f64 {
+    let units: i32 = match &contract.terms.deliver {
+        Some(d) => d.iter().map(|d| d.units_required).sum(),
+        None => 0,
+    };
+
+    if units == 0 {
+        return 0.0;
+    }
+
+    (contract.terms.payment.on_accepted + contract.terms.payment.on_fulfilled) as f64 / units as f64
+}
+
+pub fn is_complete(contract: &Contract) -> bool {
+    match &contract.terms.deliver {
+        Some(d) => d.iter().all(|d| d.units_fulfilled >= d.units_required),
+        None => true,
+    }
+}
+
+/// Estimated seconds to finish one delivery: units already in cargo are hauled by the
+/// ship holding them, the rest has to be mined first
+pub fn estimate_delivery_seconds(
+    delivery: &ContractDeliverGood,
+    ships: &[ShipWithNav],
+    waypoints: &[Waypoint],
+) -> Option<i64> {
+    let remaining = (delivery.units_required - delivery.units_fulfilled) as i64;
+    if remaining <= 0 {
+        return Some(0);
+    }
+
+    let in_cargo: i64 = ships
+        .iter()
+        .map(|s| units_in_cargo(s, &delivery.trade_symbol) as i64)
+        .sum();
+    let to_mine = (remaining - in_cargo).max(0);
+    let mining =
+        (to_mine + UNITS_PER_EXTRACTION - 1) / UNITS_PER_EXTRACTION * EXTRACTION_COOLDOWN_SECONDS;
+
+    let travel = ships
+        .iter()
+        .filter_map(|s| ship_travel_seconds(s, &delivery.destination_symbol, waypoints))
+        .min()?;
+
+    Some(mining + travel)
+}
+
+/// Estimated seconds to finish every outstanding delivery of a contract
+pub fn estimate_contract_seconds(
+    contract: &Contract,
+    ships: &[ShipWithNav],
+    waypoints: &[Waypoint],
+) -> Option<i64> {
+    match &contract.terms.deliver {
+        Some(d) => d
+            .iter()
+            .map(|d| estimate_delivery_seconds(d, ships, waypoints))
+            .sum(),
+        None => Some(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navigation::travel_seconds;
+
+    fn delivery(required: i32, fulfilled: i32) -> ContractDeliverGood {
+        ContractDeliverGood {
+            trade_symbol: "IRON_ORE".to_owned(),
+            destination_symbol: "X1-A-DEST".to_owned(),
+            units_required: required,
+            units_fulfilled: fulfilled,
+        }
+    }
+
+    fn contract(deliver: Option<Vec<ContractDeliverGood>>) -> Contract {
+        Contract {
+            terms: Box::new(ContractTerms {
+                payment: Box::new(ContractPayment {
+                    on_accepted: 1000,
+                    on_fulfilled: 3000,
+                }),
+                deliver,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn waypoint(symbol: &str, x: i32) -> Waypoint {
+        Waypoint {
+            symbol: symbol.to_owned(),
+            x,
+            ..Default::default()
+        }
+    }
+
+    fn ship_at(waypoint: &str, ore: i32) -> ShipWithNav {
+        let mut ship = Ship::default();
+        ship.nav.waypoint_symbol = waypoint.to_owned();
+        ship.engine.speed = 10.0;
+        if ore > 0 {
+            ship.cargo.inventory.push(ShipCargoItem {
+                symbol: "IRON_ORE".to_owned(),
+                units: ore,
+                ..Default::default()
+            });
+        }
+        ShipWithNav {
+            ship,
+            destination: waypoint.to_owned(),
+            route_target: String::new(),
+            actions: vec![],
+        }
+    }
+
+    #[test]
+    fn counts_seconds_to_future_and_past_timestamps() {
+        let in_an_hour = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        let until = seconds_until(&in_an_hour).unwrap();
+        assert!((3599..=3600).contains(&until));
+
+        let an_hour_ago = (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        let until = seconds_until(&an_hour_ago).unwrap();
+        assert!((-3601..=-3599).contains(&until));
+    }
+
+    #[test]
+    fn bad_timestamps_have_no_countdown() {
+        assert_eq!(seconds_until(""), None);
+        assert_eq!(seconds_until("tomorrow"), None);
+        assert_eq!(seconds_until("2023-06-01 12:00"), None);
+    }
+
+    #[test]
+    fn formats_countdowns_at_the_largest_useful_unit() {
+        assert_eq!(format_countdown(90061), "1d 1h 1m");
+        assert_eq!(format_countdown(3720), "1h 2m");
+        assert_eq!(format_countdown(125), "2m 5s");
+        assert_eq!(format_countdown(0), "passed");
+        assert_eq!(format_countdown(-30), "passed");
+    }
+
+    #[test]
+    fn spreads_payment_over_every_unit_required() {
+        let c = contract(Some(vec![delivery(30, 10), delivery(10, 0)]));
+        assert_eq!(payment_per_unit(&c), 100.0);
+        assert_eq!(payment_per_unit(&contract(Some(vec![delivery(0, 0)]))), 0.0);
+        assert_eq!(payment_per_unit(&contract(None)), 0.0);
+    }
+
+    #[test]
+    fn complete_once_every_delivery_is_fulfilled() {
+        assert!(!is_complete(&contract(Some(vec![
+            delivery(10, 10),
+            delivery(10, 9)
+        ]))));
+        assert!(is_complete(&contract(Some(vec![
+            delivery(10, 10),
+            delivery(10, 12)
+        ]))));
+        assert!(is_complete(&contract(Some(vec![]))));
+        assert!(is_complete(&contract(None)));
+    }
+
+    #[test]
+    fn a_fulfilled_delivery_takes_no_time() {
+        assert_eq!(
+            estimate_delivery_seconds(&delivery(10, 10), &[], &[]),
+            Some(0)
+        );
+        assert_eq!(
+            estimate_delivery_seconds(&delivery(0, 0), &[], &[]),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn estimates_mining_what_is_not_in_cargo_then_travelling() {
+        let waypoints = vec![waypoint("X1-A-DEST", 0), waypoint("X1-A-FIELD", 100)];
+        let travel = travel_seconds(10.0, 100.0);
+
+        // 20 units left with 6 in a hold leaves 14 to mine, two extractions
+        let ships = vec![ship_at("X1-A-FIELD", 6)];
+        assert_eq!(
+            estimate_delivery_seconds(&delivery(30, 10), &ships, &waypoints),
+            Some(2 * EXTRACTION_COOLDOWN_SECONDS + travel)
+        );
+
+        // Nothing to mine when the hold already has enough, and the ship already there
+        // doesn't travel
+        let ships = vec![ship_at("X1-A-FIELD", 6), ship_at("X1-A-DEST", 20)];
+        assert_eq!(
+            estimate_delivery_seconds(&delivery(30, 10), &ships, &waypoints),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn no_estimate_without_a_ship_that_can_get_there() {
+        let ships = vec![ship_at("X1-A-UNKNOWN", 0)];
+        assert_eq!(
+            estimate_delivery_seconds(&delivery(10, 0), &ships, &[waypoint("X1-A-DEST", 0)]),
+            None
+        );
+        assert_eq!(estimate_delivery_seconds(&delivery(10, 0), &[], &[]), None);
+    }
+}
@@ -0,0 +1,393 @@
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam_queue::SegQueue;
+use serde_json::json;
+use strum::IntoEnumIterator;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::message_handler::Message;
+use crate::AppData;
+
+// A small JSON API on localhost so other tools can follow and drive the running client
+// using its token:
+//
+//   GET  /agent, /ships, /waypoints, /contracts, /markets, /log
+//   GET  /messages          names of every message
+//   POST /messages/{name}   handle a message next, ahead of the schedule
+//   GET  /events            server-sent events as they happen, see `events::Event`
+//
+// Only one request is read per connection and nothing we serve needs a request body.
+// Event streams resume after the Last-Event-ID header or a `since` query parameter.
+//
+// Web pages can reach localhost too, so requests naming any other Host (DNS rebinding)
+// or carrying an Origin (anything sent by a browser page) are refused
+
+const MAX_REQUEST_BYTES: usize = 8192;
+/// How long a client gets to send its request before we hang up
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const EVENT_POLL: Duration = Duration::from_millis(250);
+/// Polls between keep-alive comments on a quiet event stream
+const KEEP_ALIVE_POLLS: u32 = 60;
+
+pub async fn serve(port: u16, state: Arc<Mutex<AppData>>, requested: Arc<SegQueue<Message>>) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(l) => l,
+        Err(e) => {
+            state.lock().unwrap().log.push(format!(
+                "Control API could not listen on port {}: {}",
+                port, e
+            ));
+            return;
+        }
+    };
+    state.lock().unwrap().log.push(format!(
+        "Control API listening on http://127.0.0.1:{}",
+        port
+    ));
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((s, _)) => s,
+            Err(_) => continue,
+        };
+        let state = state.clone();
+        let requested = requested.clone();
+        tokio::spawn(async move {
+            let _ = handle(stream, &state, &requested).await;
+        });
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    state: &Mutex<AppData>,
+    requested: &SegQueue<Message>,
+) -> std::io::Result<()> {
+    let mut buf = vec![0; MAX_REQUEST_BYTES];
+    let mut len = 0;
+    let read = tokio::time::timeout(READ_TIMEOUT, async {
+        while len < buf.len() {
+            let n = stream.read(&mut buf[len..]).await?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+            if buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    })
+    .await;
+    match read {
+        Ok(result) => result?,
+        Err(_) => {
+            let timed_out = error("408 Request Timeout", "Request took too long to arrive");
+            return respond(stream, timed_out).await;
+        }
+    }
+
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("");
+
+    if let Some(refused) = refusal(&request, stream.local_addr()?.port()) {
+        return respond(stream, refused).await;
+    }
+
+    if method == "GET" && path.split('?').next() == Some("/events") {
+        let since = header(&request, "last-event-id")
+            .or_else(|| query(path, "since"))
+            .and_then(|id| id.parse().ok());
+        return stream_events(stream, state, since).await;
+    }
+
+    respond(stream, route(method, path, state, requested)).await
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    (status, body): (&'static str, String),
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Why a request to the server listening on `port` is refused, if it is
+fn refusal(request: &str, port: u16) -> Option<(&'static str, String)> {
+    if header(request, "origin").is_some() {
+        return Some(error(
+            "403 Forbidden",
+            "Requests from web pages are not allowed",
+        ));
+    }
+    if !is_local_host(header(request, "host"), port) {
+        return Some(error(
+            "403 Forbidden",
+            "Host must be 127.0.0.1 or localhost",
+        ));
+    }
+    None
+}
+
+/// Whether a Host header names this server on the loopback address. The name is what
+/// matters against DNS rebinding, so a header without a port is taken to mean ours
+fn is_local_host(host: Option<&str>, port: u16) -> bool {
+    let host = match host {
+        Some(h) => h,
+        None => return false,
+    };
+    // IPv6 addresses are in brackets, since they have colons of their own
+    let (name, host_port) = match host.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((name, "")) => (name, None),
+            Some((name, rest)) => match rest.strip_prefix(':') {
+                Some(p) => (name, Some(p)),
+                None => return false,
+            },
+            None => return false,
+        },
+        None => match host.split_once(':') {
+            Some((name, p)) => (name, Some(p)),
+            None => (host, None),
+        },
+    };
+    let local = name == "127.0.0.1" || name == "::1" || name.eq_ignore_ascii_case("localhost");
+    local && host_port.map_or(true, |p| p == port.to_string())
+}
+
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request
+        .lines()
+        .skip(1)
+        .take_while(|l| l.len() > 0)
+        .filter_map(|l| l.split_once(':'))
+        .find(|(n, _)| n.trim().eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.trim())
+}
+
+fn query<'a>(path: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = path.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|p| p.split_once('='))
+        .find(|(n, _)| *n == name)
+        .map(|(_, v)| v)
+}
+
+/// Sends events until the client goes away, starting after `since` or, without it,
+/// with the next new event
+async fn stream_events(
+    mut stream: TcpStream,
+    state: &Mutex<AppData>,
+    since: Option<u64>,
+) -> std::io::Result<()> {
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+        )
+        .await?;
+
+    let newest = state.lock().unwrap().events.last_id();
+    let mut last_id = match since {
+        Some(id) if id <= newest => id,
+        // Not one of ours, so send everything we still have
+        Some(_) => 0,
+        None => newest,
+    };
+    let mut quiet_polls = 0;
+    loop {
+        let events = state.lock().unwrap().events.since(last_id);
+        if events.len() == 0 {
+            quiet_polls += 1;
+            // Writing something now and then is how we find out the client has gone
+            if quiet_polls >= KEEP_ALIVE_POLLS {
+                stream.write_all(b": keep-alive\n\n").await?;
+                quiet_polls = 0;
+            }
+        } else {
+            quiet_polls = 0;
+        }
+
+        for record in events {
+            last_id = record.id;
+            let json = match serde_json::to_string(&record) {
+                Ok(j) => j,
+                Err(_) => continue,
+            };
+            let message = format!(
+                "id: {}\nevent: {}\ndata: {}\n\n",
+                record.id,
+                record.event.name(),
+                json
+            );
+            stream.write_all(message.as_bytes()).await?;
+        }
+        tokio::time::sleep(EVENT_POLL).await;
+    }
+}
+
+fn ok(value: &impl serde::Serialize) -> (&'static str, String) {
+    match serde_json::to_string(value) {
+        Ok(body) => ("200 OK", body),
+        Err(e) => error("500 Internal Server Error", &e.to_string()),
+    }
+}
+
+fn error(status: &'static str, message: &str) -> (&'static str, String) {
+    (status, json!({ "error": message }).to_string())
+}
+
+/// Status line and JSON body for a request
+fn route(
+    method: &str,
+    path: &str,
+    state: &Mutex<AppData>,
+    requested: &SegQueue<Message>,
+) -> (&'static str, String) {
+    let path = path.split('?').next().unwrap_or("").trim_end_matches('/');
+    let data = state.lock().unwrap();
+
+    match (method, path) {
+        ("GET", "/agent") => ok(&data.agent),
+        ("GET", "/ships") => ok(&data.ships),
+        ("GET", "/waypoints") => ok(&data.waypoints),
+        ("GET", "/contracts") => ok(&data.contracts),
+        ("GET", "/markets") => ok(&data.markets),
+        ("GET", "/log") => ok(&data.log),
+        ("GET", "/messages") => {
+            let names: Vec<String> = Message::iter().map(|m| format!("{:?}", m)).collect();
+            ok(&names)
+        }
+        ("POST", p) if p.starts_with("/messages/") => {
+            let name = &p["/messages/".len()..];
+            match Message::from_str(name) {
+                Ok(m) => {
+                    requested.push(m);
+                    ("202 Accepted", json!({ "queued": name }).to_string())
+                }
+                Err(_) => error("404 Not Found", &format!("No message called {}", name)),
+            }
+        }
+        (
+            _,
+            "/agent" | "/ships" | "/waypoints" | "/contracts" | "/markets" | "/log" | "/messages"
+            | "/events",
+        ) => error("405 Method Not Allowed", "Use GET"),
+        ("GET", p) if p.starts_with("/messages/") => error("405 Method Not Allowed", "Use POST"),
+        _ => error("404 Not Found", &format!("Nothing at {}", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(path: &str, headers: &[&str]) -> String {
+        format!("GET {} HTTP/1.1\r\n{}\r\n\r\n", path, headers.join("\r\n"))
+    }
+
+    fn routed(method: &str, path: &str) -> (&'static str, SegQueue<Message>) {
+        let state = Mutex::new(AppData::default());
+        let requested = SegQueue::new();
+        let (status, _) = route(method, path, &state, &requested);
+        (status, requested)
+    }
+
+    #[test]
+    fn accepts_loopback_hosts_with_or_without_our_port() {
+        assert!(is_local_host(Some("127.0.0.1:8321"), 8321));
+        assert!(is_local_host(Some("localhost:8321"), 8321));
+        assert!(is_local_host(Some("LocalHost:8321"), 8321));
+        assert!(is_local_host(Some("[::1]:8321"), 8321));
+        assert!(is_local_host(Some("localhost"), 8321));
+        assert!(is_local_host(Some("127.0.0.1"), 8321));
+        assert!(is_local_host(Some("[::1]"), 8321));
+    }
+
+    #[test]
+    fn refuses_other_hosts_and_ports() {
+        assert!(!is_local_host(None, 8321));
+        assert!(!is_local_host(Some(""), 8321));
+        assert!(!is_local_host(Some("evil.example:8321"), 8321));
+        assert!(!is_local_host(Some("localhost.evil.example"), 8321));
+        assert!(!is_local_host(Some("localhost:80"), 8321));
+        assert!(!is_local_host(Some("[::1]:80"), 8321));
+        assert!(!is_local_host(Some("[::1]8321"), 8321));
+        assert!(!is_local_host(Some("[::1"), 8321));
+        assert!(!is_local_host(Some("::1"), 8321));
+    }
+
+    #[test]
+    fn refuses_requests_from_web_pages() {
+        let from_page = get(
+            "/ships",
+            &["Host: localhost:8321", "Origin: http://evil.example"],
+        );
+        assert_eq!(refusal(&from_page, 8321).unwrap().0, "403 Forbidden");
+
+        let rebound = get("/ships", &["Host: evil.example:8321"]);
+        assert_eq!(refusal(&rebound, 8321).unwrap().0, "403 Forbidden");
+
+        assert_eq!(
+            refusal(&get("/ships", &[]), 8321).unwrap().0,
+            "403 Forbidden"
+        );
+        assert_eq!(
+            refusal(&get("/ships", &["Host: localhost:8321"]), 8321),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_headers_case_insensitively_up_to_the_body() {
+        let request = "GET /events HTTP/1.1\r\nHost: localhost:8321\r\nLast-Event-ID:  42 \r\n\r\nOrigin: body\r\n";
+        assert_eq!(header(request, "host"), Some("localhost:8321"));
+        assert_eq!(header(request, "last-event-id"), Some("42"));
+        assert_eq!(header(request, "origin"), None);
+        // The request line isn't a header
+        assert_eq!(header(request, "GET /events HTTP/1.1"), None);
+    }
+
+    #[test]
+    fn reads_query_parameters() {
+        assert_eq!(query("/events?since=42", "since"), Some("42"));
+        assert_eq!(query("/events?a=1&since=7&b", "since"), Some("7"));
+        assert_eq!(query("/events?sinceless=1", "since"), None);
+        assert_eq!(query("/events", "since"), None);
+    }
+
+    #[test]
+    fn routes_reads_by_method_and_path() {
+        assert_eq!(routed("GET", "/agent").0, "200 OK");
+        assert_eq!(routed("GET", "/ships/?fields=all").0, "200 OK");
+        assert_eq!(routed("GET", "/messages").0, "200 OK");
+        assert_eq!(routed("DELETE", "/ships").0, "405 Method Not Allowed");
+        assert_eq!(
+            routed("GET", "/messages/GetFleet").0,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(routed("GET", "/nowhere").0, "404 Not Found");
+    }
+
+    #[test]
+    fn queues_posted_messages() {
+        let (status, requested) = routed("POST", "/messages/GetFleet");
+        assert_eq!(status, "202 Accepted");
+        assert_eq!(requested.pop(), Some(Message::GetFleet));
+
+        let (status, requested) = routed("POST", "/messages/Nothing");
+        assert_eq!(status, "404 Not Found");
+        assert!(requested.is_empty());
+    }
+}
@@ -0,0 +1,711 @@
+use egui::Ui;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Transaction};
+use spacedust::models::*;
+
+use crate::contracts::{format_countdown, seconds_until};
+use crate::history::now;
+use crate::ledger::LedgerEntry;
+
+// Game state is kept one row per object, with the columns worth filtering on pulled
+// out and the full object alongside as JSON. Market prices also get a row per good so
+// they can be compared across markets. The schema version lives in `PRAGMA user_version`
+
+/// Each entry moves the schema up one version. Add new entries rather than editing
+/// old ones, which have already run against existing databases
+const MIGRATIONS: &[&str] = &["CREATE TABLE agents (
+        symbol TEXT PRIMARY KEY,
+        headquarters TEXT NOT NULL,
+        credits INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE ships (
+        symbol TEXT PRIMARY KEY,
+        system_symbol TEXT NOT NULL,
+        waypoint_symbol TEXT NOT NULL,
+        status TEXT NOT NULL,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE waypoints (
+        symbol TEXT PRIMARY KEY,
+        system_symbol TEXT NOT NULL,
+        type TEXT NOT NULL,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX waypoints_system ON waypoints (system_symbol);
+    CREATE TABLE systems (
+        symbol TEXT PRIMARY KEY,
+        sector_symbol TEXT NOT NULL,
+        type TEXT NOT NULL,
+        x INTEGER NOT NULL,
+        y INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE markets (
+        symbol TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE contracts (
+        id TEXT PRIMARY KEY,
+        faction_symbol TEXT NOT NULL,
+        accepted INTEGER NOT NULL,
+        fulfilled INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE surveys (
+        signature TEXT PRIMARY KEY,
+        waypoint_symbol TEXT NOT NULL,
+        size TEXT NOT NULL,
+        expiration TEXT NOT NULL,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE transactions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp INTEGER NOT NULL,
+        ship_symbol TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        trade_symbol TEXT NOT NULL,
+        units INTEGER NOT NULL,
+        amount INTEGER NOT NULL
+    );
+    CREATE INDEX transactions_ship ON transactions (ship_symbol, timestamp);
+    CREATE TABLE market_goods (
+        market_symbol TEXT NOT NULL,
+        trade_symbol TEXT NOT NULL,
+        purchase_price INTEGER NOT NULL,
+        sell_price INTEGER NOT NULL,
+        trade_volume INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (market_symbol, trade_symbol)
+    );
+    CREATE INDEX market_goods_trade ON market_goods (trade_symbol);"];
+
+const TABLES: [&str; 9] = [
+    "agents",
+    "ships",
+    "waypoints",
+    "systems",
+    "markets",
+    "market_goods",
+    "contracts",
+    "surveys",
+    "transactions",
+];
+
+/// The API name of an enum value, e.g. "IN_ORBIT"
+fn enum_text<T: serde::Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
+
+fn json<T: serde::Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// A recorded price for one good at one market
+#[derive(Debug, Clone)]
+pub struct Price {
+    pub market_symbol: String,
+    pub purchase_price: i64,
+    pub sell_price: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug)]
+pub struct Database {
+    conn: Connection,
+    pub path: String,
+}
+
+impl Database {
+    /// Opens (or creates) the database and brings its schema up to date
+    pub fn open(path: &str) -> Result<Self, String> {
+        let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+        migrate(&mut conn).map_err(|e| format!("migration failed: {}", e))?;
+        Ok(Self {
+            conn,
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn schema_version(&self) -> rusqlite::Result<usize> {
+        self.conn.query_row("PRAGMA user_version", [], |r| r.get(0))
+    }
+
+    pub fn store_agent(&self, agent: &Agent) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO agents VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                agent.symbol,
+                agent.headquarters,
+                agent.credits as i64,
+                json(agent)?,
+                now()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the stored fleet with a full fetch of it, so sold or scrapped ships go
+    pub fn store_ships<'a>(
+        &self,
+        ships: impl IntoIterator<Item = &'a Ship>,
+    ) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM ships", [])?;
+        {
+            let mut insert =
+                tx.prepare("INSERT OR REPLACE INTO ships VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            let time = now();
+            for ship in ships {
+                insert.execute(params![
+                    ship.symbol,
+                    ship.nav.system_symbol,
+                    ship.nav.waypoint_symbol,
+                    enum_text(&ship.nav.status),
+                    json(ship)?,
+                    time
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Replaces the stored waypoints with every one we know of
+    pub fn store_waypoints(&self, waypoints: &[Waypoint]) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM waypoints", [])?;
+        {
+            let mut insert =
+                tx.prepare("INSERT OR REPLACE INTO waypoints VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            let time = now();
+            for w in waypoints {
+                insert.execute(params![
+                    w.symbol,
+                    w.system_symbol,
+                    enum_text(&w.r#type),
+                    json(w)?,
+                    time
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Adds or updates systems, as the catalogue is filled a page at a time
+    pub fn store_systems(&self, systems: &[System]) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        insert_systems(&tx, systems)?;
+        tx.commit()
+    }
+
+    /// Replaces the stored systems with the whole catalogue, after a reset or a download
+    pub fn replace_systems(&self, systems: &[System]) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM systems", [])?;
+        insert_systems(&tx, systems)?;
+        tx.commit()
+    }
+
+    /// Replaces the stored markets with every one we know of. Recorded prices are kept
+    /// for those markets, as they're only fetched with a ship there
+    pub fn store_markets(&self, markets: &[Market]) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM markets", [])?;
+        {
+            let mut insert = tx.prepare("INSERT OR REPLACE INTO markets VALUES (?1, ?2, ?3)")?;
+            let mut insert_good =
+                tx.prepare("INSERT OR REPLACE INTO market_goods VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            let time = now();
+            for m in markets {
+                insert.execute(params![m.symbol, json(m)?, time])?;
+                for g in m.trade_goods.iter().flatten() {
+                    insert_good.execute(params![
+                        m.symbol,
+                        g.symbol,
+                        g.purchase_price,
+                        g.sell_price,
+                        g.trade_volume,
+                        time
+                    ])?;
+                }
+            }
+        }
+        tx.execute(
+            "DELETE FROM market_goods WHERE market_symbol NOT IN (SELECT symbol FROM markets)",
+            [],
+        )?;
+        tx.commit()
+    }
+
+    /// Replaces the stored contracts with a full fetch of them
+    pub fn store_contracts(&self, contracts: &[Contract]) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM contracts", [])?;
+        {
+            let mut insert =
+                tx.prepare("INSERT OR REPLACE INTO contracts VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            let time = now();
+            for c in contracts {
+                insert.execute(params![
+                    c.id,
+                    c.faction_symbol,
+                    c.accepted,
+                    c.fulfilled,
+                    json(c)?,
+                    time
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    pub fn store_surveys(&self, surveys: &[Survey]) -> rusqlite::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut insert =
+                tx.prepare("INSERT OR REPLACE INTO surveys VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            let time = now();
+            for s in surveys {
+                insert.execute(params![
+                    s.signature,
+                    s.symbol,
+                    enum_text(&s.size),
+                    s.expiration,
+                    json(s)?,
+                    time
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Unexpired surveys of a waypoint, largest deposits first
+    pub fn surveys_at(&self, waypoint_symbol: &str) -> rusqlite::Result<Vec<Survey>> {
+        let mut query = self
+            .conn
+            .prepare("SELECT data FROM surveys WHERE waypoint_symbol = ?1")?;
+        let rows = query.query_map([waypoint_symbol], |r| {
+            let data: String = r.get(0)?;
+            serde_json::from_str::<Survey>(&data)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+        })?;
+        let mut surveys: Vec<Survey> = rows
+            .collect::<rusqlite::Result<Vec<Survey>>>()?
+            .into_iter()
+            .filter(|s| seconds_until(&s.expiration).map_or(false, |r| r > 0))
+            .collect();
+        surveys.sort_by_key(|s| std::cmp::Reverse(s.size));
+        Ok(surveys)
+    }
+
+    /// Drops a survey the server no longer accepts
+    pub fn forget_survey(&self, signature: &str) -> rusqlite::Result<()> {
+        self.conn
+            .execute("DELETE FROM surveys WHERE signature = ?1", [signature])?;
+        Ok(())
+    }
+
+    pub fn store_transaction(&self, entry: &LedgerEntry) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO transactions (timestamp, ship_symbol, kind, trade_symbol, units, amount)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                entry.timestamp,
+                entry.ship_symbol,
+                enum_text(&entry.kind),
+                entry.trade_symbol,
+                entry.units,
+                entry.amount
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Row count of every table, for a quick look at what's stored
+    pub fn counts(&self) -> rusqlite::Result<Vec<(&'static str, i64)>> {
+        TABLES
+            .iter()
+            .map(|t| {
+                let count =
+                    self.conn
+                        .query_row(&format!("SELECT COUNT(*) FROM {}", t), [], |r| r.get(0))?;
+                Ok((*t, count))
+            })
+            .collect()
+    }
+
+    /// The latest transactions, newest first
+    pub fn recent_transactions(&self, limit: usize) -> rusqlite::Result<Vec<LedgerEntry>> {
+        let mut query = self.conn.prepare(
+            "SELECT timestamp, ship_symbol, kind, trade_symbol, units, amount
+            FROM transactions ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = query.query_map([limit as i64], |r| {
+            let kind: String = r.get(2)?;
+            Ok(LedgerEntry {
+                timestamp: r.get(0)?,
+                ship_symbol: r.get(1)?,
+                kind: serde_json::from_value(serde_json::Value::String(kind)).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e))
+                })?,
+                trade_symbol: r.get(3)?,
+                units: r.get(4)?,
+                amount: r.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Every recorded price for a good, best paying market first
+    pub fn prices(&self, trade_symbol: &str) -> rusqlite::Result<Vec<Price>> {
+        let mut query = self.conn.prepare(
+            "SELECT market_symbol, purchase_price, sell_price, updated_at
+            FROM market_goods WHERE trade_symbol = ?1 ORDER BY sell_price DESC",
+        )?;
+        let rows = query.query_map([trade_symbol], |r| {
+            Ok(Price {
+                market_symbol: r.get(0)?,
+                purchase_price: r.get(1)?,
+                sell_price: r.get(2)?,
+                updated_at: r.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+fn insert_systems(tx: &Transaction<'_>, systems: &[System]) -> rusqlite::Result<()> {
+    let mut insert =
+        tx.prepare("INSERT OR REPLACE INTO systems VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+    let time = now();
+    for s in systems {
+        insert.execute(params![
+            s.symbol,
+            s.sector_symbol,
+            enum_text(&s.r#type),
+            s.x,
+            s.y,
+            json(s)?,
+            time
+        ])?;
+    }
+    Ok(())
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// What `DatabaseView::render_ship` last read, so it isn't queried every frame
+#[derive(Debug)]
+struct ShipRecords {
+    /// Ship, waypoint and goods in the hold the records were read for
+    key: (String, String, Vec<String>),
+    surveys: rusqlite::Result<Vec<Survey>>,
+    /// Best recorded price for each good in the hold
+    prices: Vec<(String, rusqlite::Result<Option<Price>>)>,
+}
+
+impl ShipRecords {
+    fn read(db: &Database, key: (String, String, Vec<String>)) -> Self {
+        ShipRecords {
+            surveys: db.surveys_at(&key.1),
+            prices: key
+                .2
+                .iter()
+                .map(|good| {
+                    let best = db.prices(good).map(|p| p.into_iter().next());
+                    (good.clone(), best)
+                })
+                .collect(),
+            key,
+        }
+    }
+}
+
+/// Browses what's in the database
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct DatabaseView {
+    /// Good to look up recorded prices for
+    pub trade_symbol: String,
+    #[serde(skip)]
+    ship_records: Option<ShipRecords>,
+}
+
+impl DatabaseView {
+    /// What's stored that matters to one ship: surveys where it is and the best
+    /// recorded price for each good in its hold. Read again when the ship moves or its
+    /// hold changes, or on request
+    pub fn render_ship(&mut self, ui: &mut Ui, db: Option<&Database>, ship: &Ship) {
+        let db = match db {
+            Some(db) => db,
+            None => return,
+        };
+
+        let key = (
+            ship.symbol.clone(),
+            ship.nav.waypoint_symbol.clone(),
+            ship.cargo
+                .inventory
+                .iter()
+                .map(|i| i.symbol.clone())
+                .collect(),
+        );
+        let reload = ui.small_button("Reload records").clicked();
+        let records = match &mut self.ship_records {
+            Some(records) if records.key == key && !reload => records,
+            records => records.insert(ShipRecords::read(db, key)),
+        };
+
+        match &records.surveys {
+            Ok(surveys) if surveys.len() == 0 => {}
+            Ok(surveys) => {
+                ui.label(format!("Surveys at {}", ship.nav.waypoint_symbol));
+                for s in surveys {
+                    let deposits: Vec<&str> =
+                        s.deposits.iter().map(|d| d.symbol.as_str()).collect();
+                    ui.label(format!(
+                        "\t{:?}, {} left: {}",
+                        s.size,
+                        format_countdown(seconds_until(&s.expiration).unwrap_or(0)),
+                        deposits.join(", ")
+                    ));
+                }
+            }
+            Err(e) => {
+                ui.label(format!("Could not read surveys: {}", e));
+            }
+        }
+
+        if records.prices.len() > 0 {
+            ui.label("Best recorded prices");
+            for (good, best) in &records.prices {
+                match best {
+                    Ok(Some(p)) => ui.label(format!(
+                        "\t{}: {} at {}",
+                        good, p.sell_price, p.market_symbol
+                    )),
+                    Ok(None) => ui.label(format!("\t{}: no recorded prices", good)),
+                    Err(e) => ui.label(format!("\t{}: {}", good, e)),
+                };
+            }
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut Ui, db: Option<&Database>) {
+        let db = match db {
+            Some(db) => db,
+            None => {
+                ui.label("No database open");
+                return;
+            }
+        };
+        ui.label(format!(
+            "{} (schema version {})",
+            db.path,
+            db.schema_version().unwrap_or(0)
+        ));
+
+        match db.counts() {
+            Ok(counts) => {
+                egui::Grid::new("database_counts").show(ui, |ui| {
+                    for (table, count) in counts {
+                        ui.label(table);
+                        ui.label(count.to_string());
+                        ui.end_row();
+                    }
+                });
+            }
+            Err(e) => {
+                ui.label(format!("Could not read tables: {}", e));
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Prices for");
+            ui.text_edit_singleline(&mut self.trade_symbol);
+        });
+        if self.trade_symbol.len() > 0 {
+            match db.prices(&self.trade_symbol.to_uppercase()) {
+                Ok(prices) if prices.len() == 0 => {
+                    ui.label("No recorded prices");
+                }
+                Ok(prices) => {
+                    egui::Grid::new("database_prices")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Market");
+                            ui.label("Buy");
+                            ui.label("Sell");
+                            ui.label("Age");
+                            ui.end_row();
+                            let time = now();
+                            for p in prices {
+                                ui.label(p.market_symbol);
+                                ui.label(p.purchase_price.to_string());
+                                ui.label(p.sell_price.to_string());
+                                ui.label(format!("{}s", time - p.updated_at));
+                                ui.end_row();
+                            }
+                        });
+                }
+                Err(e) => {
+                    ui.label(format!("Could not read prices: {}", e));
+                }
+            }
+        }
+
+        ui.separator();
+        ui.label("Recent transactions");
+        match db.recent_transactions(20) {
+            Ok(entries) => {
+                for e in entries {
+                    ui.label(format!(
+                        "{} {:?} {} x{}: {}",
+                        e.ship_symbol, e.kind, e.trade_symbol, e.units, e.amount
+                    ));
+                }
+            }
+            Err(e) => {
+                ui.label(format!("Could not read transactions: {}", e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_memory() -> Database {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        Database {
+            conn,
+            path: ":memory:".to_owned(),
+        }
+    }
+
+    fn ship(symbol: &str) -> Ship {
+        let mut ship = Ship::default();
+        ship.symbol = symbol.to_owned();
+        ship
+    }
+
+    fn survey(signature: &str, size: survey::Size, expiration: &str) -> Survey {
+        Survey {
+            signature: signature.to_owned(),
+            symbol: "X1-A-1".to_owned(),
+            size,
+            expiration: expiration.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn migrates_a_fresh_database() {
+        let db = in_memory();
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+        let counts = db.counts().unwrap();
+        assert_eq!(counts.len(), TABLES.len());
+        assert!(counts.iter().all(|(_, count)| *count == 0));
+    }
+
+    #[test]
+    fn migrating_again_keeps_the_schema_and_data() {
+        let mut db = in_memory();
+        db.store_ships([&ship("A")]).unwrap();
+        migrate(&mut db.conn).unwrap();
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+        assert!(db.counts().unwrap().contains(&("ships", 1)));
+    }
+
+    #[test]
+    fn reopens_an_existing_file() {
+        let path =
+            std::env::temp_dir().join(format!("fleet_db_test_{}.sqlite", std::process::id()));
+        let path = path.to_str().unwrap();
+        {
+            let db = Database::open(path).unwrap();
+            db.store_ships([&ship("A")]).unwrap();
+        }
+        let db = Database::open(path).unwrap();
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+        assert!(db.counts().unwrap().contains(&("ships", 1)));
+        drop(db);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn a_full_fetch_drops_ships_no_longer_owned() {
+        let db = in_memory();
+        db.store_ships([&ship("A"), &ship("B")]).unwrap();
+        db.store_ships([&ship("B")]).unwrap();
+        assert!(db.counts().unwrap().contains(&("ships", 1)));
+    }
+
+    #[test]
+    fn a_market_update_keeps_prices_only_for_markets_still_known() {
+        let db = in_memory();
+        let market = |symbol: &str, priced: bool| Market {
+            symbol: symbol.to_owned(),
+            trade_goods: priced.then(|| {
+                vec![MarketTradeGood {
+                    symbol: "IRON_ORE".to_owned(),
+                    sell_price: 10,
+                    ..Default::default()
+                }]
+            }),
+            ..Default::default()
+        };
+        db.store_markets(&[market("X1-A-1", true), market("X1-A-2", true)])
+            .unwrap();
+        // No ship at X1-A-1 this time, and X1-A-2 is gone
+        db.store_markets(&[market("X1-A-1", false)]).unwrap();
+
+        assert!(db.counts().unwrap().contains(&("markets", 1)));
+        let prices = db.prices("IRON_ORE").unwrap();
+        assert_eq!(prices.len(), 1);
+        assert_eq!(prices[0].market_symbol, "X1-A-1");
+    }
+
+    #[test]
+    fn only_unexpired_surveys_are_read_back() {
+        let db = in_memory();
+        db.store_surveys(&[
+            survey("OLD", survey::Size::Large, "2000-01-01T00:00:00Z"),
+            survey("SMALL", survey::Size::Small, "2999-01-01T00:00:00Z"),
+            survey("LARGE", survey::Size::Large, "2999-01-01T00:00:00Z"),
+        ])
+        .unwrap();
+        let signatures: Vec<String> = db
+            .surveys_at("X1-A-1")
+            .unwrap()
+            .into_iter()
+            .map(|s| s.signature)
+            .collect();
+        assert_eq!(signatures, vec!["LARGE", "SMALL"]);
+
+        db.forget_survey("LARGE").unwrap();
+        assert_eq!(db.surveys_at("X1-A-1").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+
+use spacedust::models::Contract;
+
+use crate::history::now;
+
+// Structured events for anything watching from outside, see the control API's /events.
+// Events are numbered so a reader can pick up after the last one it saw, and only the
+// most recent are kept. Numbering starts from the clock, so ids keep growing across
+// restarts and a reader resuming with an id from an earlier run doesn't skip anything
+
+const MAX_EVENTS: usize = 1000;
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    ShipArrived {
+        ship: String,
+        waypoint: String,
+    },
+    Extracted {
+        ship: String,
+        trade_symbol: String,
+        units: i32,
+    },
+    CargoSold {
+        ship: String,
+        trade_symbol: String,
+        units: i32,
+        credits: i64,
+    },
+    ContractUpdated {
+        contract: Contract,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl Event {
+    /// Name of the event type, as in its JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ShipArrived { .. } => "ship_arrived",
+            Event::Extracted { .. } => "extracted",
+            Event::CargoSold { .. } => "cargo_sold",
+            Event::ContractUpdated { .. } => "contract_updated",
+            Event::Error { .. } => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EventRecord {
+    pub id: u64,
+    pub time: i64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Ids handed out per second before numbering would catch up with the next launch
+const IDS_PER_SECOND: u64 = 1000;
+
+#[derive(Debug)]
+pub struct EventLog {
+    last_id: u64,
+    recent: VecDeque<EventRecord>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self {
+            last_id: now() as u64 * IDS_PER_SECOND,
+            recent: VecDeque::new(),
+        }
+    }
+}
+
+impl EventLog {
+    pub fn push(&mut self, event: Event) {
+        self.last_id += 1;
+        self.recent.push_back(EventRecord {
+            id: self.last_id,
+            time: now(),
+            event,
+        });
+        if self.recent.len() > MAX_EVENTS {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Id of the newest event, or the one before the first event before there are any
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Whether the newest event is this error, or one it wraps, e.g. a fleet_ops failure
+    /// passed up through the automation with the ship's name in front
+    pub fn repeats_last_error(&self, message: &str) -> bool {
+        match self.recent.back() {
+            Some(EventRecord {
+                event: Event::Error { message: last },
+                ..
+            }) => message.ends_with(last.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Every event we still have that came after `id`
+    pub fn since(&self, id: u64) -> Vec<EventRecord> {
+        self.recent.iter().filter(|e| e.id > id).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(message: &str) -> Event {
+        Event::Error {
+            message: message.to_owned(),
+        }
+    }
+
+    fn arrived(ship: &str) -> Event {
+        Event::ShipArrived {
+            ship: ship.to_owned(),
+            waypoint: "X1-A-1".to_owned(),
+        }
+    }
+
+    fn ids(records: &[EventRecord]) -> Vec<u64> {
+        records.iter().map(|r| r.id).collect()
+    }
+
+    #[test]
+    fn numbers_events_on_from_the_clock() {
+        let mut log = EventLog::default();
+        let first = log.last_id();
+        assert!(first >= now() as u64 * IDS_PER_SECOND - IDS_PER_SECOND);
+        log.push(arrived("SHIP-1"));
+        log.push(arrived("SHIP-2"));
+        assert_eq!(log.last_id(), first + 2);
+    }
+
+    #[test]
+    fn returns_only_events_after_an_id() {
+        let mut log = EventLog::default();
+        let start = log.last_id();
+        assert!(log.since(start).is_empty());
+        for ship in ["SHIP-1", "SHIP-2", "SHIP-3"] {
+            log.push(arrived(ship));
+        }
+        assert_eq!(
+            ids(&log.since(start)),
+            vec![start + 1, start + 2, start + 3]
+        );
+        assert_eq!(ids(&log.since(start + 2)), vec![start + 3]);
+        assert!(log.since(start + 3).is_empty());
+        assert_eq!(log.since(0).len(), 3);
+    }
+
+    #[test]
+    fn keeps_only_the_most_recent_events() {
+        let mut log = EventLog::default();
+        let start = log.last_id();
+        for _ in 0..MAX_EVENTS + 10 {
+            log.push(arrived("SHIP-1"));
+        }
+        let kept = log.since(0);
+        assert_eq!(kept.len(), MAX_EVENTS);
+        assert_eq!(kept[0].id, start + 11);
+        assert_eq!(kept[MAX_EVENTS - 1].id, log.last_id());
+    }
+
+    #[test]
+    fn recognises_the_last_error_passed_up() {
+        let mut log = EventLog::default();
+        assert!(!log.repeats_last_error("no fuel"));
+
+        log.push(error("no fuel"));
+        assert!(log.repeats_last_error("no fuel"));
+        assert!(log.repeats_last_error("SHIP-1: no fuel"));
+        assert!(!log.repeats_last_error("no fuel left"));
+        assert!(!log.repeats_last_error("cargo full"));
+
+        // Only the newest event counts
+        log.push(arrived("SHIP-1"));
+        assert!(!log.repeats_last_error("no fuel"));
+    }
+}
@@ -0,0 +1,242 @@
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::fleet_ops;
+use crate::history::now;
+use crate::navigation::{distance, find_waypoint};
+use crate::spacetraders::ShipWithNav;
+use crate::AppData;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum ScanKind {
+    Systems,
+    Waypoints,
+    Ships,
+}
+
+/// A waypoint seen by one of our scans
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DiscoveredWaypoint {
+    pub waypoint: ScannedWaypoint,
+    pub discovered_by: String,
+    pub timestamp: i64,
+}
+
+/// Someone else's ship seen by one of our scans
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DiscoveredShip {
+    pub ship: ScannedShip,
+    pub discovered_by: String,
+    pub timestamp: i64,
+}
+
+/// A ship charting and scanning its way around a system
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Explorer {
+    pub ship_symbol: String,
+    /// Last waypoint every scan was run at, so we don't scan it twice
+    pub scanned_at: String,
+    #[serde(skip)]
+    pub pending_scans: Vec<ScanKind>,
+    #[serde(skip)]
+    pub cooldown_until: i64,
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Exploration {
+    pub explorers: Vec<Explorer>,
+    pub waypoints: Vec<DiscoveredWaypoint>,
+    pub ships: Vec<DiscoveredShip>,
+}
+
+impl Exploration {
+    pub fn is_exploring(&self, ship_symbol: &str) -> bool {
+        self.explorers.iter().any(|e| e.ship_symbol == ship_symbol)
+    }
+
+    pub fn set_exploring(&mut self, ship_symbol: &str, exploring: bool) {
+        self.explorers.retain(|e| e.ship_symbol != ship_symbol);
+        if exploring {
+            self.explorers.push(Explorer {
+                ship_symbol: ship_symbol.to_owned(),
+                scanned_at: String::new(),
+                pending_scans: vec![],
+                cooldown_until: 0,
+            });
+        }
+    }
+
+    /// Keeps the latest sighting of each waypoint
+    pub fn record_waypoint(&mut self, discovery: DiscoveredWaypoint) {
+        self.waypoints
+            .retain(|w| w.waypoint.symbol != discovery.waypoint.symbol);
+        self.waypoints.push(discovery);
+    }
+
+    /// Keeps the latest sighting of each ship
+    pub fn record_ship(&mut self, discovery: DiscoveredShip) {
+        self.ships
+            .retain(|s| s.ship.symbol != discovery.ship.symbol);
+        self.ships.push(discovery);
+    }
+
+    pub fn render(&self, ui: &mut Ui) {
+        let time = now();
+
+        ui.label(format!("{} explorers", self.explorers.len()));
+        for explorer in &self.explorers {
+            let status = if explorer.cooldown_until > time {
+                format!("cooling down {}s", explorer.cooldown_until - time)
+            } else {
+                "active".to_owned()
+            };
+            ui.label(format!("\t{}: {}", explorer.ship_symbol, status));
+        }
+
+        egui::CollapsingHeader::new(format!("Discovered waypoints ({})", self.waypoints.len()))
+            .id_source("discovered_waypoints")
+            .show(ui, |ui| {
+                for w in &self.waypoints {
+                    ui.label(format!(
+                        "{} {:?} by {}, {}s ago",
+                        w.waypoint.symbol,
+                        w.waypoint.r#type,
+                        w.discovered_by,
+                        time - w.timestamp
+                    ));
+                }
+            });
+
+        egui::CollapsingHeader::new(format!("Discovered ships ({})", self.ships.len()))
+            .id_source("discovered_ships")
+            .show(ui, |ui| {
+                for s in &self.ships {
+                    ui.label(format!(
+                        "{} ({:?}) at {} by {}, {}s ago",
+                        s.ship.symbol,
+                        s.ship.registration.role,
+                        s.ship.nav.waypoint_symbol,
+                        s.discovered_by,
+                        time - s.timestamp
+                    ));
+                }
+            });
+    }
+}
+
+pub fn is_uncharted(waypoint: &Waypoint) -> bool {
+    waypoint
+        .traits
+        .iter()
+        .any(|t| t.symbol == waypoint_trait::Symbol::Uncharted)
+}
+
+pub fn has_sensors(ship: &ShipWithNav) -> bool {
+    ship.ship.mounts.iter().any(|m| {
+        matches!(
+            m.symbol,
+            ship_mount::Symbol::MountSensorArrayI
+                | ship_mount::Symbol::MountSensorArrayIi
+                | ship_mount::Symbol::MountSensorArrayIii
+        )
+    })
+}
+
+/// Closest uncharted waypoint in the ship's system, other than the one it's at
+fn nearest_uncharted(ship: &ShipWithNav, waypoints: &[Waypoint]) -> Option<String> {
+    let here = find_waypoint(waypoints, &ship.ship.nav.waypoint_symbol)?;
+    waypoints
+        .iter()
+        .filter(|w| w.system_symbol == ship.ship.nav.system_symbol)
+        .filter(|w| w.symbol != here.symbol && is_uncharted(w))
+        .min_by(|a, b| distance(here, a).total_cmp(&distance(here, b)))
+        .map(|w| w.symbol.clone())
+}
+
+/// Advances one explorer by a single action: chart where we are, run any scans the
+/// sensors allow, then move on. Returns a cooldown if the action started one, or None
+/// once there's nothing left to do in the system
+fn step_explorer(data: &mut AppData, explorer: &mut Explorer) -> Result<Option<i64>, String> {
+    let ship = match data
+        .ships
+        .iter()
+        .find(|s| s.ship.symbol == explorer.ship_symbol)
+    {
+        Some(s) => s.clone(),
+        None => return Ok(None),
+    };
+
+    if let ShipNavStatus::InTransit = ship.ship.nav.status {
+        return Ok(Some(0));
+    }
+
+    let here = ship.ship.nav.waypoint_symbol.clone();
+    if find_waypoint(&data.waypoints, &here).map(is_uncharted) == Some(true) {
+        fleet_ops::chart(data, &ship.ship.symbol)?;
+        return Ok(Some(0));
+    }
+
+    if has_sensors(&ship) && explorer.scanned_at != here {
+        if explorer.pending_scans.len() == 0 {
+            explorer.pending_scans = vec![ScanKind::Systems, ScanKind::Waypoints, ScanKind::Ships];
+        }
+
+        // A scan that fails stays first in line for the next round
+        let cooldown = match explorer.pending_scans[0] {
+            ScanKind::Systems => fleet_ops::scan_systems(data, &ship.ship.symbol)?,
+            ScanKind::Waypoints => fleet_ops::scan_waypoints(data, &ship.ship.symbol)?,
+            ScanKind::Ships => fleet_ops::scan_ships(data, &ship.ship.symbol)?,
+        };
+        explorer.pending_scans.remove(0);
+        if explorer.pending_scans.len() == 0 {
+            explorer.scanned_at = here;
+        }
+        return Ok(Some(cooldown));
+    }
+
+    match nearest_uncharted(&ship, &data.waypoints) {
+        Some(target) => {
+            if let ShipNavStatus::Docked = ship.ship.nav.status {
+                fleet_ops::orbit(data, &ship.ship.symbol)?;
+            }
+            fleet_ops::navigate(data, &ship.ship.symbol, &target)?;
+            Ok(Some(0))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Runs one round of exploration for every exploring ship
+pub fn tick(data: &mut AppData) {
+    let time = now();
+    let mut explorers = std::mem::take(&mut data.exploration.explorers);
+    let mut finished: Vec<String> = vec![];
+
+    for explorer in explorers.iter_mut() {
+        if explorer.cooldown_until > time {
+            continue;
+        }
+
+        match step_explorer(data, explorer) {
+            Ok(Some(cooldown)) => explorer.cooldown_until = time + cooldown,
+            Ok(None) => finished.push(explorer.ship_symbol.clone()),
+            Err(e) => data.report_error(e),
+        }
+    }
+
+    explorers.retain(|e| {
+        if finished.contains(&e.ship_symbol) {
+            data.log.push(format!(
+                "{}: nothing left to chart in this system",
+                e.ship_symbol
+            ));
+            return false;
+        }
+        true
+    });
+
+    // Keep any explorers that were added during the round
+    explorers.append(&mut data.exploration.explorers);
+    data.exploration.explorers = explorers;
+}
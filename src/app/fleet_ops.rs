@@ -0,0 +1,663 @@
+use pollster::FutureExt;
+use spacedust::apis::contracts_api::*;
+use spacedust::apis::fleet_api::*;
+use spacedust::apis::systems_api::*;
+use spacedust::models::*;
+
+use crate::cargo::{receive, validate_transfer};
+use crate::contracts::payment_per_unit;
+use crate::events::Event;
+use crate::exploration::{DiscoveredShip, DiscoveredWaypoint};
+use crate::history::now;
+use crate::ledger::{LedgerEntry, TransactionKind};
+use crate::navigation::JumpGateInfo;
+use crate::spacetraders::ShipAction;
+use crate::AppData;
+
+// Ship operations used by the automation. Each one calls the API, copies the
+// returned state back onto our copy of the ship and logs failures
+
+/// Whether ships can be given orders. Every call here blocks until the server answers,
+/// which a browser can't do without returning to its event loop, so the web build only
+/// follows the fleet
+pub const ORDERS_AVAILABLE: bool = cfg!(not(target_arch = "wasm32"));
+
+fn ship_index(data: &AppData, ship_symbol: &str) -> Result<usize, String> {
+    data.ships
+        .iter()
+        .position(|s| s.ship.symbol == ship_symbol)
+        .ok_or_else(|| format!("Unknown ship: {}", ship_symbol))
+}
+
+fn fail<T>(data: &mut AppData, message: String) -> Result<T, String> {
+    data.report_error(message.clone());
+    Err(message)
+}
+
+pub fn navigate(data: &mut AppData, ship_symbol: &str, waypoint: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = navigate_ship_request::NavigateShipRequest::new(waypoint.to_owned());
+    match navigate_ship(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            let ship = &mut data.ships[i];
+            ship.ship.nav = r.data.nav;
+            ship.ship.fuel = r.data.fuel;
+            ship.destination = waypoint.to_owned();
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!("{}: could not navigate to {}", ship_symbol, waypoint),
+        ),
+    }
+}
+
+pub fn dock(data: &mut AppData, ship_symbol: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    match dock_ship(&data.conf, ship_symbol, 0.0).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.nav = r.data.nav;
+            Ok(())
+        }
+        Err(_) => fail(data, format!("{}: could not dock", ship_symbol)),
+    }
+}
+
+pub fn orbit(data: &mut AppData, ship_symbol: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    match orbit_ship(&data.conf, ship_symbol, 0.0).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.nav = r.data.nav;
+            Ok(())
+        }
+        Err(_) => fail(data, format!("{}: could not orbit", ship_symbol)),
+    }
+}
+
+/// Extracts once at the ship's waypoint, using the best stored survey of it if there is
+/// one, and returns the cooldown in seconds before the next extraction
+pub fn extract(data: &mut AppData, ship_symbol: &str) -> Result<i64, String> {
+    let i = ship_index(data, ship_symbol)?;
+    let survey = best_survey(data, &data.ships[i].ship.nav.waypoint_symbol.clone());
+    let req = extract_resources_request::ExtractResourcesRequest {
+        survey: survey.clone().map(Box::new),
+    };
+    match extract_resources(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.log.push(format!(
+                "{}: extracted {} {}",
+                ship_symbol, r.data.extraction.r#yield.units, r.data.extraction.r#yield.symbol
+            ));
+            data.emit(Event::Extracted {
+                ship: ship_symbol.to_owned(),
+                trade_symbol: r.data.extraction.r#yield.symbol.clone(),
+                units: r.data.extraction.r#yield.units,
+            });
+            data.ships[i].ship.cargo = r.data.cargo;
+            Ok(r.data.cooldown.remaining_seconds as i64)
+        }
+        Err(_) => {
+            // Most likely exhausted, the next try goes without it
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(survey) = &survey {
+                data.store(|db, _| db.forget_survey(&survey.signature));
+            }
+            fail(data, format!("{}: could not extract", ship_symbol))
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn best_survey(data: &AppData, waypoint_symbol: &str) -> Option<Survey> {
+    let db = data.db.as_ref()?;
+    db.surveys_at(waypoint_symbol).ok()?.into_iter().next()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn best_survey(_data: &AppData, _waypoint_symbol: &str) -> Option<Survey> {
+    None
+}
+
+/// Surveys the ship's waypoint and stores the surveys for extraction, returning the
+/// cooldown in seconds
+pub fn survey(data: &mut AppData, ship_symbol: &str) -> Result<i64, String> {
+    match create_survey(&data.conf, ship_symbol, 0).block_on() {
+        Ok(r) => {
+            let surveys = r.data.surveys;
+            data.log.push(format!(
+                "{}: created {} surveys",
+                ship_symbol,
+                surveys.len()
+            ));
+            #[cfg(not(target_arch = "wasm32"))]
+            data.store(|db, _| db.store_surveys(&surveys));
+            Ok(r.data.cooldown.remaining_seconds as i64)
+        }
+        Err(_) => fail(data, format!("{}: could not survey", ship_symbol)),
+    }
+}
+
+pub fn sell(
+    data: &mut AppData,
+    ship_symbol: &str,
+    trade_symbol: &str,
+    units: i32,
+) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = sell_cargo_request::SellCargoRequest::new(trade_symbol.to_owned(), units);
+    match sell_cargo(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.cargo = r.data.cargo;
+            data.update_agent(*r.data.agent);
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::Sale,
+                trade_symbol,
+                units,
+                r.data.transaction.total_price as i64,
+            ));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!("{}: could not sell {} {}", ship_symbol, units, trade_symbol),
+        ),
+    }
+}
+
+pub fn purchase(
+    data: &mut AppData,
+    ship_symbol: &str,
+    trade_symbol: &str,
+    units: i32,
+) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = purchase_cargo_request::PurchaseCargoRequest::new(trade_symbol.to_owned(), units);
+    match purchase_cargo(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.cargo = r.data.cargo;
+            data.update_agent(*r.data.agent);
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::Purchase,
+                trade_symbol,
+                units,
+                -(r.data.transaction.total_price as i64),
+            ));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!("{}: could not buy {} {}", ship_symbol, units, trade_symbol),
+        ),
+    }
+}
+
+/// Throws cargo overboard, for goods nobody nearby will buy
+pub fn jettison_cargo(
+    data: &mut AppData,
+    ship_symbol: &str,
+    trade_symbol: &str,
+    units: i32,
+) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = jettison_request::JettisonRequest::new(trade_symbol.to_owned(), units);
+    match jettison(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.cargo = r.data.cargo;
+            data.log.push(format!(
+                "{}: jettisoned {} {}",
+                ship_symbol, units, trade_symbol
+            ));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!(
+                "{}: could not jettison {} {}",
+                ship_symbol, units, trade_symbol
+            ),
+        ),
+    }
+}
+
+pub fn refuel(data: &mut AppData, ship_symbol: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    match refuel_ship(&data.conf, ship_symbol, 0).block_on() {
+        Ok(r) => {
+            let units = r.data.fuel.current - data.ships[i].ship.fuel.current;
+            data.ships[i].ship.fuel = r.data.fuel;
+            data.update_agent(*r.data.agent);
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::Refuel,
+                "FUEL",
+                units,
+                -(r.data.transaction.total_price as i64),
+            ));
+            Ok(())
+        }
+        Err(_) => fail(data, format!("{}: could not refuel", ship_symbol)),
+    }
+}
+
+pub fn deliver(
+    data: &mut AppData,
+    ship_symbol: &str,
+    contract_id: &str,
+    trade_symbol: &str,
+    units: i32,
+) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let c = match data.contracts.iter().position(|c| c.id == contract_id) {
+        Some(c) => c,
+        None => return fail(data, format!("Unknown contract: {}", contract_id)),
+    };
+
+    let per_unit = payment_per_unit(&data.contracts[c]);
+    let req = deliver_contract_request::DeliverContractRequest {
+        ship_symbol: ship_symbol.to_owned(),
+        trade_symbol: trade_symbol.to_owned(),
+        units,
+    };
+    match deliver_contract(&data.conf, contract_id, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.cargo = r.data.cargo;
+            data.contracts[c] = *r.data.contract;
+            data.emit(Event::ContractUpdated {
+                contract: data.contracts[c].clone(),
+            });
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::ContractDelivery,
+                trade_symbol,
+                units,
+                (units as f64 * per_unit).round() as i64,
+            ));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!(
+                "{}: could not deliver {} {} to {}",
+                ship_symbol, units, trade_symbol, contract_id
+            ),
+        ),
+    }
+}
+
+pub fn accept(data: &mut AppData, contract_id: &str) -> Result<(), String> {
+    let c = match data.contracts.iter().position(|c| c.id == contract_id) {
+        Some(c) => c,
+        None => return fail(data, format!("Unknown contract: {}", contract_id)),
+    };
+
+    match accept_contract(&data.conf, contract_id, 0).block_on() {
+        Ok(r) => {
+            data.contracts[c] = *r.data.contract;
+            data.emit(Event::ContractUpdated {
+                contract: data.contracts[c].clone(),
+            });
+            data.update_agent(*r.data.agent);
+            data.log.push(format!("Accepted contract {}", contract_id));
+            Ok(())
+        }
+        Err(_) => fail(data, format!("Could not accept contract {}", contract_id)),
+    }
+}
+
+/// Negotiates a new contract with the faction at the ship's waypoint
+pub fn negotiate(data: &mut AppData, ship_symbol: &str) -> Result<(), String> {
+    match negotiate_contract(&data.conf, ship_symbol, 0).block_on() {
+        Ok(r) => {
+            let contract = *r.data.contract;
+            data.log.push(format!(
+                "{}: negotiated contract {}",
+                ship_symbol, contract.id
+            ));
+            data.emit(Event::ContractUpdated {
+                contract: contract.clone(),
+            });
+            data.contracts.push(contract);
+            Ok(())
+        }
+        Err(e) => fail(
+            data,
+            format!("{}: could not negotiate a contract: {}", ship_symbol, e),
+        ),
+    }
+}
+
+pub fn fulfill(data: &mut AppData, contract_id: &str) -> Result<(), String> {
+    let c = match data.contracts.iter().position(|c| c.id == contract_id) {
+        Some(c) => c,
+        None => return fail(data, format!("Unknown contract: {}", contract_id)),
+    };
+
+    match fulfill_contract(&data.conf, contract_id, 0).block_on() {
+        Ok(r) => {
+            data.contracts[c] = *r.data.contract;
+            data.emit(Event::ContractUpdated {
+                contract: data.contracts[c].clone(),
+            });
+            data.update_agent(*r.data.agent);
+            data.log.push(format!("Fulfilled contract {}", contract_id));
+            Ok(())
+        }
+        Err(_) => fail(data, format!("Could not fulfil contract {}", contract_id)),
+    }
+}
+
+/// Jumps to another system, returning the cooldown in seconds before the next jump
+pub fn jump(data: &mut AppData, ship_symbol: &str, system: &str) -> Result<i64, String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = jump_ship_request::JumpShipRequest::new(system.to_owned());
+    match jump_ship(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].destination = r.data.nav.waypoint_symbol.clone();
+            data.ships[i].ship.nav = r.data.nav;
+            data.log
+                .push(format!("{}: jumped to {}", ship_symbol, system));
+            Ok(r.data.cooldown.remaining_seconds as i64)
+        }
+        Err(_) => fail(
+            data,
+            format!("{}: could not jump to {}", ship_symbol, system),
+        ),
+    }
+}
+
+pub fn warp(data: &mut AppData, ship_symbol: &str, waypoint: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = warp_ship_request::WarpShipRequest::new(waypoint.to_owned());
+    match warp_ship(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            let ship = &mut data.ships[i];
+            ship.ship.nav = r.data.nav;
+            ship.ship.fuel = r.data.fuel;
+            ship.destination = waypoint.to_owned();
+            data.log
+                .push(format!("{}: warping to {}", ship_symbol, waypoint));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!("{}: could not warp to {}", ship_symbol, waypoint),
+        ),
+    }
+}
+
+/// Refreshes our copy of a ship's installed mounts
+pub fn refresh_mounts(data: &mut AppData, ship_symbol: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    match get_mounts(&data.conf, ship_symbol).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.mounts = r.data;
+            Ok(())
+        }
+        Err(_) => fail(data, format!("{}: could not get mounts", ship_symbol)),
+    }
+}
+
+/// Carries out something picked for a ship in the UI
+pub fn perform(data: &mut AppData, ship_symbol: &str, action: ShipAction) -> Result<(), String> {
+    match action {
+        ShipAction::Sell(trade_symbol, units) => sell(data, ship_symbol, &trade_symbol, units),
+        ShipAction::Navigate(waypoint) => navigate(data, ship_symbol, &waypoint),
+        ShipAction::Dock => dock(data, ship_symbol),
+        ShipAction::Refuel => refuel(data, ship_symbol),
+        ShipAction::Extract => extract(data, ship_symbol).map(|_| ()),
+        ShipAction::Survey => survey(data, ship_symbol).map(|_| ()),
+        ShipAction::Deliver(contract_id, trade_symbol, units) => {
+            deliver(data, ship_symbol, &contract_id, &trade_symbol, units)
+        }
+    }
+}
+
+pub fn install(data: &mut AppData, ship_symbol: &str, mount_symbol: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = install_mount_request::InstallMountRequest::new(mount_symbol.to_owned());
+    match install_mount(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.mounts = r.data.mounts;
+            data.ships[i].ship.cargo = r.data.cargo;
+            data.update_agent(*r.data.agent);
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::ShipModification,
+                mount_symbol,
+                1,
+                -(r.data.transaction.total_price as i64),
+            ));
+            data.log
+                .push(format!("{}: installed {}", ship_symbol, mount_symbol));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!("{}: could not install {}", ship_symbol, mount_symbol),
+        ),
+    }
+}
+
+pub fn uninstall(data: &mut AppData, ship_symbol: &str, mount_symbol: &str) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let req = remove_mount_request::RemoveMountRequest::new(mount_symbol.to_owned());
+    match remove_mount(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.mounts = r.data.mounts;
+            data.ships[i].ship.cargo = r.data.cargo;
+            data.update_agent(*r.data.agent);
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::ShipModification,
+                mount_symbol,
+                1,
+                -(r.data.transaction.total_price as i64),
+            ));
+            data.log
+                .push(format!("{}: removed {}", ship_symbol, mount_symbol));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!("{}: could not remove {}", ship_symbol, mount_symbol),
+        ),
+    }
+}
+
+/// Moves cargo to another ship at the same waypoint. The API only returns the sender's
+/// hold, so the receiver's is updated locally
+pub fn transfer(
+    data: &mut AppData,
+    ship_symbol: &str,
+    to: &str,
+    trade_symbol: &str,
+    units: i32,
+) -> Result<(), String> {
+    let i = ship_index(data, ship_symbol)?;
+    let j = ship_index(data, to)?;
+    if let Err(e) = validate_transfer(
+        &data.ships[i].ship,
+        &data.ships[j].ship,
+        trade_symbol,
+        units,
+    ) {
+        return fail(data, format!("{}: {}", ship_symbol, e));
+    }
+    let item = data.ships[i]
+        .ship
+        .cargo
+        .inventory
+        .iter()
+        .find(|c| c.symbol == trade_symbol)
+        .cloned();
+
+    let req = transfer_cargo_request::TransferCargoRequest::new(
+        trade_symbol.to_owned(),
+        units,
+        to.to_owned(),
+    );
+    match transfer_cargo(&data.conf, ship_symbol, Some(req)).block_on() {
+        Ok(r) => {
+            data.ships[i].ship.cargo = r.data.cargo;
+            if let Some(item) = item {
+                receive(&mut data.ships[j].ship, &item, units);
+            }
+            data.log.push(format!(
+                "{}: transferred {} {} to {}",
+                ship_symbol, units, trade_symbol, to
+            ));
+            Ok(())
+        }
+        Err(_) => fail(
+            data,
+            format!(
+                "{}: could not transfer {} to {}",
+                ship_symbol, trade_symbol, to
+            ),
+        ),
+    }
+}
+
+/// Charts the waypoint the ship is at, replacing our copy with the charted one
+pub fn chart(data: &mut AppData, ship_symbol: &str) -> Result<(), String> {
+    match create_chart(&data.conf, ship_symbol, 0).block_on() {
+        Ok(r) => {
+            let waypoint = *r.data.waypoint;
+            data.log
+                .push(format!("{}: charted {}", ship_symbol, waypoint.symbol));
+            match data
+                .waypoints
+                .iter()
+                .position(|w| w.symbol == waypoint.symbol)
+            {
+                Some(i) => data.waypoints[i] = waypoint,
+                None => data.waypoints.push(waypoint),
+            }
+            Ok(())
+        }
+        Err(_) => fail(data, format!("{}: could not create chart", ship_symbol)),
+    }
+}
+
+/// Scans for nearby systems, returning the sensor cooldown in seconds
+pub fn scan_systems(data: &mut AppData, ship_symbol: &str) -> Result<i64, String> {
+    match create_ship_system_scan(&data.conf, ship_symbol, 0).block_on() {
+        Ok(r) => {
+            let unknown = r
+                .data
+                .systems
+                .iter()
+                .filter(|s| data.catalogue.get(&s.symbol).is_none())
+                .count();
+            data.log.push(format!(
+                "{}: scanned {} systems, {} not in the catalogue",
+                ship_symbol,
+                r.data.systems.len(),
+                unknown
+            ));
+            Ok(r.data.cooldown.remaining_seconds as i64)
+        }
+        Err(_) => fail(data, format!("{}: could not scan systems", ship_symbol)),
+    }
+}
+
+/// Scans for nearby waypoints and records them as discoveries, returning the sensor
+/// cooldown in seconds
+pub fn scan_waypoints(data: &mut AppData, ship_symbol: &str) -> Result<i64, String> {
+    match create_ship_waypoint_scan(&data.conf, ship_symbol, 0).block_on() {
+        Ok(r) => {
+            data.log.push(format!(
+                "{}: scanned {} waypoints",
+                ship_symbol,
+                r.data.waypoints.len()
+            ));
+            let time = now();
+            for waypoint in r.data.waypoints {
+                data.exploration.record_waypoint(DiscoveredWaypoint {
+                    waypoint,
+                    discovered_by: ship_symbol.to_owned(),
+                    timestamp: time,
+                });
+            }
+            Ok(r.data.cooldown.remaining_seconds as i64)
+        }
+        Err(_) => fail(data, format!("{}: could not scan waypoints", ship_symbol)),
+    }
+}
+
+/// Scans for nearby ships and records them as discoveries, returning the sensor cooldown
+/// in seconds
+pub fn scan_ships(data: &mut AppData, ship_symbol: &str) -> Result<i64, String> {
+    match create_ship_ship_scan(&data.conf, ship_symbol, 0).block_on() {
+        Ok(r) => {
+            data.log.push(format!(
+                "{}: scanned {} ships",
+                ship_symbol,
+                r.data.ships.len()
+            ));
+            let time = now();
+            for ship in r.data.ships {
+                data.exploration.record_ship(DiscoveredShip {
+                    ship,
+                    discovered_by: ship_symbol.to_owned(),
+                    timestamp: time,
+                });
+            }
+            Ok(r.data.cooldown.remaining_seconds as i64)
+        }
+        Err(_) => fail(data, format!("{}: could not scan ships", ship_symbol)),
+    }
+}
+
+/// Replaces the waypoints we know for a system with a fresh copy, and looks up its jump
+/// gate if we haven't seen it yet
+pub fn fetch_system(data: &mut AppData, system: &str) -> Result<(), String> {
+    let waypoints = match get_system_waypoints(&data.conf, system, None, None).block_on() {
+        Ok(w) => w.data,
+        Err(_) => {
+            return fail(
+                data,
+                format!("Failed to fetch waypoints for system: {}", system),
+            )
+        }
+    };
+
+    data.waypoints.retain(|w| w.system_symbol != system);
+    data.waypoints.extend(waypoints);
+    data.log
+        .push(format!("Fetched waypoints for system: {}", system));
+
+    fetch_jump_gates(data);
+    Ok(())
+}
+
+/// Looks up every jump gate waypoint we know of but don't have gate details for yet
+pub fn fetch_jump_gates(data: &mut AppData) {
+    let missing: Vec<(String, String)> = data
+        .waypoints
+        .iter()
+        .filter(|w| w.r#type == WaypointType::JumpGate)
+        .filter(|w| {
+            !data
+                .jump_gates
+                .iter()
+                .any(|g| g.waypoint_symbol == w.symbol)
+        })
+        .map(|w| (w.system_symbol.clone(), w.symbol.clone()))
+        .collect();
+
+    for (system, waypoint) in missing {
+        match get_jump_gate(&data.conf, &system, &waypoint).block_on() {
+            Ok(g) => data.jump_gates.push(JumpGateInfo {
+                system_symbol: system,
+                waypoint_symbol: waypoint,
+                gate: *g.data,
+            }),
+            Err(_) => data
+                .log
+                .push(format!("Failed to get jump gate: {}", waypoint)),
+        }
+    }
+}
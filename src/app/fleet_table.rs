@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+use spacedust::models::ShipNavStatus;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::contracts::{format_countdown, seconds_until};
+use crate::groups::ShipRole;
+use crate::history::now;
+use crate::AppData;
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter, serde::Deserialize, serde::Serialize)]
+pub enum Column {
+    Symbol,
+    Role,
+    Status,
+    Location,
+    Destination,
+    Eta,
+    Fuel,
+    Cargo,
+    Cooldown,
+    Automation,
+}
+
+impl Column {
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Symbol => "Ship",
+            Column::Role => "Role",
+            Column::Status => "Status",
+            Column::Location => "Location",
+            Column::Destination => "Destination",
+            Column::Eta => "ETA",
+            Column::Fuel => "Fuel",
+            Column::Cargo => "Cargo",
+            Column::Cooldown => "Cooldown",
+            Column::Automation => "Automation",
+        }
+    }
+}
+
+/// One ship, boiled down to what the table shows
+#[derive(Debug, Clone)]
+pub struct FleetRow {
+    pub symbol: String,
+    pub role: Option<ShipRole>,
+    pub status: String,
+    pub system: String,
+    pub location: String,
+    pub destination: String,
+    /// Seconds until arrival, if in transit
+    pub eta: Option<i64>,
+    pub fuel: Option<f32>,
+    pub cargo: Option<f32>,
+    /// Seconds until the ship's automation can act again
+    pub cooldown: i64,
+    pub automation: String,
+}
+
+fn ratio(current: i32, capacity: i32) -> Option<f32> {
+    if capacity > 0 {
+        Some(current as f32 / capacity as f32)
+    } else {
+        None
+    }
+}
+
+/// Everything that can drive a ship on its own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Automation {
+    Contract,
+    Route,
+    Exploration,
+    Script,
+    Behavior,
+    Swarm,
+}
+
+/// Each automation driving a ship, described, with when it can act again
+fn automations(data: &AppData, ship_symbol: &str) -> Vec<(Automation, String, i64)> {
+    let mut automations = vec![];
+
+    if let Some(job) = data.contract_runner.job_for_ship(ship_symbol) {
+        automations.push((
+            Automation::Contract,
+            format!("Contract {}", job.trade_symbol),
+            job.cooldown_until,
+        ));
+    }
+    if let Some(route) = data.routes.route_for(ship_symbol) {
+        automations.push((
+            Automation::Route,
+            format!("Route to {}", route.destination),
+            route.cooldown_until,
+        ));
+    }
+    if let Some(explorer) = data
+        .exploration
+        .explorers
+        .iter()
+        .find(|e| e.ship_symbol == ship_symbol)
+    {
+        automations.push((
+            Automation::Exploration,
+            "Exploring".to_owned(),
+            explorer.cooldown_until,
+        ));
+    }
+    if let Some(script) = data
+        .scripts
+        .assignments
+        .iter()
+        .find(|a| a.ship_symbol == ship_symbol)
+    {
+        automations.push((
+            Automation::Script,
+            format!("Script {}", script.script),
+            script.cooldown_until,
+        ));
+    }
+    if let Some(behavior) = data.behaviors.assignment_for(ship_symbol) {
+        automations.push((
+            Automation::Behavior,
+            format!("{} ({})", behavior.behavior, behavior.state),
+            behavior.cooldown_until,
+        ));
+    }
+    if let Some(swarm) = data.swarms.swarm_of(ship_symbol) {
+        let role = if swarm.hauler == ship_symbol {
+            "hauler"
+        } else {
+            "drone"
+        };
+        automations.push((
+            Automation::Swarm,
+            format!("Swarm {} {}", swarm.name, role),
+            swarm.cooldown_until(ship_symbol),
+        ));
+    }
+
+    automations
+}
+
+/// Whatever is driving each ship, and how long until it can act again
+fn automation_of(data: &AppData, ship_symbol: &str, time: i64) -> (String, i64) {
+    let automations = automations(data, ship_symbol);
+    let names: Vec<String> = automations.iter().map(|(_, n, _)| n.clone()).collect();
+    let cooldown_until = automations.iter().map(|(_, _, c)| *c).max().unwrap_or(0);
+    (names.join(", "), (cooldown_until - time).max(0))
+}
+
+/// What else is driving a ship, if anything, leaving out `except`. Anything handing
+/// out ships checks this so two automations never fight over one
+pub fn automated_by(
+    data: &AppData,
+    ship_symbol: &str,
+    except: Option<Automation>,
+) -> Option<String> {
+    automations(data, ship_symbol)
+        .into_iter()
+        .find(|(a, _, _)| Some(*a) != except)
+        .map(|(_, name, _)| name)
+}
+
+/// `automated_by` for every ship in the fleet that has something driving it
+pub fn automated_ships(data: &AppData, except: Option<Automation>) -> HashMap<String, String> {
+    data.ships
+        .iter()
+        .filter_map(|s| {
+            automated_by(data, &s.ship.symbol, except).map(|a| (s.ship.symbol.clone(), a))
+        })
+        .collect()
+}
+
+pub fn fleet_rows(data: &AppData) -> Vec<FleetRow> {
+    let time = now();
+    data.ships
+        .iter()
+        .map(|s| {
+            let ship = &s.ship;
+            let in_transit = matches!(ship.nav.status, ShipNavStatus::InTransit);
+            let (automation, cooldown) = automation_of(data, &ship.symbol, time);
+            FleetRow {
+                symbol: ship.symbol.clone(),
+                role: data.groups.role_of(&ship.symbol),
+                status: format!("{:?}", ship.nav.status),
+                system: ship.nav.system_symbol.clone(),
+                location: ship.nav.waypoint_symbol.clone(),
+                destination: if in_transit {
+                    ship.nav.route.destination.symbol.clone()
+                } else {
+                    String::new()
+                },
+                eta: if in_transit {
+                    seconds_until(&ship.nav.route.arrival)
+                } else {
+                    None
+                },
+                fuel: ratio(ship.fuel.current, ship.fuel.capacity),
+                cargo: ratio(ship.cargo.units, ship.cargo.capacity),
+                cooldown,
+                automation,
+            }
+        })
+        .collect()
+}
+
+fn percent(value: Option<f32>) -> String {
+    match value {
+        Some(v) => format!("{:.0}%", v * 100.0),
+        None => "-".to_owned(),
+    }
+}
+
+fn sort_rows(rows: &mut [FleetRow], column: Column) {
+    match column {
+        Column::Symbol => rows.sort_by(|a, b| a.symbol.cmp(&b.symbol)),
+        Column::Role => rows.sort_by_key(|r| r.role.map(|r| format!("{:?}", r))),
+        Column::Status => rows.sort_by(|a, b| a.status.cmp(&b.status)),
+        Column::Location => rows.sort_by(|a, b| a.location.cmp(&b.location)),
+        Column::Destination => rows.sort_by(|a, b| a.destination.cmp(&b.destination)),
+        Column::Eta => rows.sort_by_key(|r| r.eta),
+        Column::Fuel => rows.sort_by(|a, b| a.fuel.partial_cmp(&b.fuel).unwrap()),
+        Column::Cargo => rows.sort_by(|a, b| a.cargo.partial_cmp(&b.cargo).unwrap()),
+        Column::Cooldown => rows.sort_by_key(|r| r.cooldown),
+        Column::Automation => rows.sort_by(|a, b| a.automation.cmp(&b.automation)),
+    }
+}
+
+/// Picks one of `options`, or None for no filter
+fn filter_combo(ui: &mut Ui, label: &str, value: &mut Option<String>, options: Vec<String>) {
+    egui::ComboBox::from_label(label)
+        .selected_text(value.clone().unwrap_or("Any".to_owned()))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(value, None, "Any");
+            for o in options {
+                ui.selectable_value(value, Some(o.clone()), o);
+            }
+        });
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FleetTable {
+    pub sort: Column,
+    pub ascending: bool,
+    pub system: Option<String>,
+    pub status: Option<String>,
+    pub role: Option<String>,
+    /// Ship shown in the detail pane
+    pub selected: Option<String>,
+}
+
+impl Default for FleetTable {
+    fn default() -> Self {
+        Self {
+            sort: Column::Symbol,
+            ascending: true,
+            system: None,
+            status: None,
+            role: None,
+            selected: None,
+        }
+    }
+}
+
+impl FleetTable {
+    pub fn render(&mut self, ui: &mut Ui, mut rows: Vec<FleetRow>) {
+        let options = |f: &dyn Fn(&FleetRow) -> Option<String>| {
+            let mut o: Vec<String> = rows.iter().filter_map(f).collect();
+            o.sort();
+            o.dedup();
+            o
+        };
+        let systems = options(&|r| Some(r.system.clone()));
+        let statuses = options(&|r| Some(r.status.clone()));
+        let roles = options(&|r| r.role.map(|r| format!("{:?}", r)));
+
+        ui.horizontal(|ui| {
+            filter_combo(ui, "System", &mut self.system, systems);
+            filter_combo(ui, "Status", &mut self.status, statuses);
+            filter_combo(ui, "Role", &mut self.role, roles);
+        });
+
+        rows.retain(|r| {
+            self.system.as_ref().map_or(true, |s| &r.system == s)
+                && self.status.as_ref().map_or(true, |s| &r.status == s)
+                && self.role.as_ref().map_or(true, |s| {
+                    r.role.map(|r| format!("{:?}", r)).as_ref() == Some(s)
+                })
+        });
+        sort_rows(&mut rows, self.sort);
+        if !self.ascending {
+            rows.reverse();
+        }
+
+        egui::Grid::new("fleet_table").striped(true).show(ui, |ui| {
+            for column in Column::iter() {
+                let title = if column == self.sort {
+                    format!(
+                        "{} {}",
+                        column.title(),
+                        if self.ascending { "^" } else { "v" }
+                    )
+                } else {
+                    column.title().to_owned()
+                };
+                if ui.button(title).clicked() {
+                    if column == self.sort {
+                        self.ascending = !self.ascending;
+                    } else {
+                        self.sort = column;
+                        self.ascending = true;
+                    }
+                }
+            }
+            ui.end_row();
+
+            for row in &rows {
+                let selected = self.selected.as_ref() == Some(&row.symbol);
+                if ui.selectable_label(selected, &row.symbol).clicked() {
+                    self.selected = if selected {
+                        None
+                    } else {
+                        Some(row.symbol.clone())
+                    };
+                }
+                ui.label(match row.role {
+                    Some(r) => format!("{:?}", r),
+                    None => "-".to_owned(),
+                });
+                ui.label(&row.status);
+                ui.label(&row.location);
+                ui.label(&row.destination);
+                ui.label(row.eta.map(format_countdown).unwrap_or_default());
+                ui.label(percent(row.fuel));
+                ui.label(percent(row.cargo));
+                ui.label(if row.cooldown > 0 {
+                    format!("{}s", row.cooldown)
+                } else {
+                    String::new()
+                });
+                ui.label(&row.automation);
+                ui.end_row();
+            }
+        });
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+use spacedust::models::*;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::fleet_ops;
+use crate::fleet_table::automated_by;
+use crate::navigation::plan_route;
+use crate::AppData;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, serde::Deserialize, serde::Serialize,
+)]
+pub enum ShipRole {
+    Miner,
+    Hauler,
+    Explorer,
+    Trader,
+    Other,
+}
+
+/// Commands issued to every ship in a group at once
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter, serde::Deserialize, serde::Serialize)]
+pub enum GroupCommand {
+    DockAll,
+    OrbitAll,
+    ReturnToHeadquarters,
+    SellAllCargo,
+}
+
+impl GroupCommand {
+    pub fn label(&self) -> &'static str {
+        match self {
+            GroupCommand::DockAll => "Dock all",
+            GroupCommand::OrbitAll => "Orbit all",
+            GroupCommand::ReturnToHeadquarters => "Return to HQ",
+            GroupCommand::SellAllCargo => "Sell all cargo",
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FleetGroups {
+    pub groups: Vec<String>,
+    /// Ship symbol to group name. Ships not in here are ungrouped
+    pub membership: HashMap<String, String>,
+    pub roles: HashMap<String, ShipRole>,
+    /// Issued from the UI, carried out by the message handler
+    #[serde(skip)]
+    pub pending: Vec<(String, GroupCommand)>,
+    #[serde(skip)]
+    pub new_group: String,
+}
+
+impl FleetGroups {
+    pub fn add_group(&mut self, name: &str) {
+        if name.len() > 0 && !self.groups.iter().any(|g| g == name) {
+            self.groups.push(name.to_owned());
+        }
+    }
+
+    /// Removes a group, leaving its ships ungrouped
+    pub fn remove_group(&mut self, name: &str) {
+        self.groups.retain(|g| g != name);
+        self.membership.retain(|_, g| g != name);
+    }
+
+    pub fn group_of(&self, ship_symbol: &str) -> Option<&str> {
+        self.membership.get(ship_symbol).map(|g| g.as_str())
+    }
+
+    pub fn set_group(&mut self, ship_symbol: &str, group: Option<&str>) {
+        match group {
+            Some(g) => self.membership.insert(ship_symbol.to_owned(), g.to_owned()),
+            None => self.membership.remove(ship_symbol),
+        };
+    }
+
+    pub fn role_of(&self, ship_symbol: &str) -> Option<ShipRole> {
+        self.roles.get(ship_symbol).copied()
+    }
+
+    pub fn set_role(&mut self, ship_symbol: &str, role: Option<ShipRole>) {
+        match role {
+            Some(r) => self.roles.insert(ship_symbol.to_owned(), r),
+            None => self.roles.remove(ship_symbol),
+        };
+    }
+
+    pub fn members<'a>(&'a self, group: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.membership
+            .iter()
+            .filter(move |(_, g)| *g == group)
+            .map(|(s, _)| s)
+    }
+
+    /// Creating groups, shown above the group list
+    pub fn render_new_group(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.new_group);
+            if ui.button("New group").clicked() {
+                let name = std::mem::take(&mut self.new_group);
+                self.add_group(name.trim());
+            }
+        });
+    }
+
+    /// Bulk command buttons for one group
+    pub fn render_commands(&mut self, ui: &mut Ui, group: &str) {
+        ui.horizontal(|ui| {
+            for command in GroupCommand::iter() {
+                if ui.button(command.label()).clicked() {
+                    self.pending.push((group.to_owned(), command));
+                }
+            }
+            if ui.button("Remove group").clicked() {
+                self.remove_group(group);
+            }
+        });
+    }
+
+    /// Group and role pickers for one ship
+    pub fn render_ship(&mut self, ui: &mut Ui, ship_symbol: &str) {
+        ui.horizontal(|ui| {
+            let mut group = self.group_of(ship_symbol).map(|g| g.to_owned());
+            egui::ComboBox::from_id_source(format!("group_{}", ship_symbol))
+                .selected_text(group.clone().unwrap_or("Ungrouped".to_owned()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut group, None, "Ungrouped");
+                    for g in &self.groups {
+                        ui.selectable_value(&mut group, Some(g.clone()), g);
+                    }
+                });
+            if group.as_deref() != self.group_of(ship_symbol) {
+                self.set_group(ship_symbol, group.as_deref());
+            }
+
+            let mut role = self.role_of(ship_symbol);
+            egui::ComboBox::from_id_source(format!("role_{}", ship_symbol))
+                .selected_text(match role {
+                    Some(r) => format!("{:?}", r),
+                    None => "No role".to_owned(),
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut role, None, "No role");
+                    for r in ShipRole::iter() {
+                        ui.selectable_value(&mut role, Some(r), format!("{:?}", r));
+                    }
+                });
+            if role != self.role_of(ship_symbol) {
+                self.set_role(ship_symbol, role);
+            }
+        });
+    }
+}
+
+fn run_command(data: &mut AppData, ship_symbol: &str, command: GroupCommand) -> Result<(), String> {
+    let ship = match data.ships.iter().find(|s| s.ship.symbol == ship_symbol) {
+        Some(s) => s.clone(),
+        None => return Ok(()),
+    };
+    if let Some(automation) = automated_by(data, ship_symbol, None) {
+        return Err(format!(
+            "{}: {}, skipped {}",
+            ship_symbol,
+            automation,
+            command.label()
+        ));
+    }
+    let status = ship.ship.nav.status.clone();
+    if let ShipNavStatus::InTransit = status {
+        return Err(format!(
+            "{}: in transit, skipped {}",
+            ship_symbol,
+            command.label()
+        ));
+    }
+
+    match command {
+        GroupCommand::DockAll => {
+            if !matches!(status, ShipNavStatus::Docked) {
+                fleet_ops::dock(data, ship_symbol)?;
+            }
+        }
+        GroupCommand::OrbitAll => {
+            if let ShipNavStatus::Docked = status {
+                fleet_ops::orbit(data, ship_symbol)?;
+            }
+        }
+        GroupCommand::ReturnToHeadquarters => {
+            let headquarters = match &data.agent {
+                Some(a) => a.headquarters.clone(),
+                None => return Err("Agent not fetched yet".to_owned()),
+            };
+            if ship.ship.nav.waypoint_symbol == headquarters {
+                return Ok(());
+            }
+            match plan_route(
+                &ship,
+                &headquarters,
+                &data.waypoints,
+                &data.jump_gates,
+                &data.catalogue,
+            ) {
+                Some(steps) => data.routes.start(ship_symbol, &headquarters, steps),
+                None => return Err(format!("{}: no route to {}", ship_symbol, headquarters)),
+            }
+        }
+        GroupCommand::SellAllCargo => {
+            if !matches!(status, ShipNavStatus::Docked) {
+                fleet_ops::dock(data, ship_symbol)?;
+            }
+            for item in &ship.ship.cargo.inventory {
+                fleet_ops::sell(data, ship_symbol, &item.symbol, item.units)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Carries out every group command issued since the last round
+pub fn tick(data: &mut AppData) {
+    let pending = std::mem::take(&mut data.groups.pending);
+
+    for (group, command) in pending {
+        let members: Vec<String> = data.groups.members(&group).cloned().collect();
+        data.log.push(format!(
+            "{}: {} ({} ships)",
+            group,
+            command.label(),
+            members.len()
+        ));
+
+        for ship_symbol in members {
+            if let Err(e) = run_command(data, &ship_symbol, command) {
+                data.report_error(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use egui::plot::{Bar, BarChart, Legend, Line, Plot, PlotPoints};
+use egui::Ui;
+
+use crate::spacetraders::{ShipWithNav, ShipyardShipWithWaypoint};
+
+pub const SECONDS_PER_HOUR: f64 = 3600.0;
+/// Oldest credit samples are dropped past this, transactions record one each
+const MAX_SAMPLES: usize = 10_000;
+
+/// Seconds since the unix epoch, used to timestamp everything we record
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// SystemTime isn't available in the browser, so ask JavaScript instead
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> i64 {
+    (js_sys::Date::now() / 1000.0) as i64
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+pub enum SpendingCategory {
+    Fuel,
+    Ships,
+    Goods,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CreditSample {
+    pub timestamp: i64,
+    pub agent: String,
+    pub credits: i64,
+    pub fleet_value: i64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Spending {
+    pub timestamp: i64,
+    pub category: SpendingCategory,
+    pub amount: i64,
+}
+
+/// Credits and fleet value over time. Samples are tagged with the agent symbol so
+/// runs from different resets can be compared side by side
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CreditHistory {
+    pub samples: Vec<CreditSample>,
+    pub spending: Vec<Spending>,
+}
+
+impl CreditHistory {
+    pub fn record_credits(&mut self, agent: &str, credits: i64, fleet_value: i64) {
+        // Polling produces the same value most of the time; only keep changes
+        if let Some(last) = self.samples.iter().rev().find(|s| s.agent == agent) {
+            if last.credits == credits && last.fleet_value == fleet_value {
+                return;
+            }
+        }
+
+        self.samples.push(CreditSample {
+            timestamp: now(),
+            agent: agent.to_owned(),
+            credits,
+            fleet_value,
+        });
+        if self.samples.len() > MAX_SAMPLES {
+            let excess = self.samples.len() - MAX_SAMPLES;
+            self.samples.drain(..excess);
+        }
+    }
+
+    pub fn record_spending(&mut self, category: SpendingCategory, amount: i64) {
+        self.spending.push(Spending {
+            timestamp: now(),
+            category,
+            amount,
+        });
+    }
+
+    fn agents(&self) -> Vec<String> {
+        let mut agents: Vec<String> = vec![];
+        for s in &self.samples {
+            if !agents.contains(&s.agent) {
+                agents.push(s.agent.clone());
+            }
+        }
+        agents
+    }
+
+    /// Samples for one agent, oldest first
+    fn series(&self, agent: &str) -> Vec<&CreditSample> {
+        self.samples.iter().filter(|s| s.agent == agent).collect()
+    }
+
+    /// Net change in credits for each hour since the first sample
+    fn income_per_hour(samples: &[&CreditSample]) -> Vec<[f64; 2]> {
+        let start = match samples.first() {
+            Some(s) => s.timestamp,
+            None => return vec![],
+        };
+
+        let mut buckets: BTreeMap<i64, i64> = BTreeMap::new();
+        for pair in samples.windows(2) {
+            let hour = (pair[1].timestamp - start) / SECONDS_PER_HOUR as i64;
+            *buckets.entry(hour).or_insert(0) += pair[1].credits - pair[0].credits;
+        }
+
+        buckets
+            .iter()
+            .map(|(hour, income)| [*hour as f64, *income as f64])
+            .collect()
+    }
+
+    pub fn render(&self, ui: &mut Ui) {
+        let agents = self.agents();
+
+        if agents.len() == 0 {
+            ui.label("No credit history recorded yet");
+            return;
+        }
+
+        ui.label("Credits / fleet value");
+        Plot::new("credits_plot")
+            .height(150.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                for agent in &agents {
+                    let series = self.series(agent);
+                    let start = series[0].timestamp;
+                    let hours = |s: &CreditSample| (s.timestamp - start) as f64 / SECONDS_PER_HOUR;
+
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(
+                            series
+                                .iter()
+                                .map(|s| [hours(s), s.credits as f64])
+                                .collect(),
+                        ))
+                        .name(format!("{} credits", agent)),
+                    );
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(
+                            series
+                                .iter()
+                                .map(|s| [hours(s), (s.credits + s.fleet_value) as f64])
+                                .collect(),
+                        ))
+                        .name(format!("{} net worth", agent)),
+                    );
+                }
+            });
+
+        ui.label("Income per hour");
+        Plot::new("income_plot")
+            .height(150.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                for agent in &agents {
+                    let series = self.series(agent);
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(Self::income_per_hour(&series))).name(agent),
+                    );
+                }
+            });
+
+        ui.label("Spending by category");
+        let mut totals: BTreeMap<SpendingCategory, i64> = BTreeMap::new();
+        for s in &self.spending {
+            *totals.entry(s.category).or_insert(0) += s.amount;
+        }
+        Plot::new("spending_plot")
+            .height(150.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                for (i, (category, total)) in totals.iter().enumerate() {
+                    plot_ui.bar_chart(
+                        BarChart::new(vec![Bar::new(i as f64, *total as f64)])
+                            .name(format!("{:?}", category)),
+                    );
+                }
+            });
+    }
+}
+
+/// Rough fleet value: what each ship would cost to buy again at a known shipyard
+pub fn estimate_fleet_value(
+    ships: &[ShipWithNav],
+    shipyard_ships: &Option<Vec<ShipyardShipWithWaypoint>>,
+) -> i64 {
+    let shipyard_ships = match shipyard_ships {
+        Some(s) => s,
+        None => return 0,
+    };
+
+    ships
+        .iter()
+        .filter_map(|s| {
+            shipyard_ships
+                .iter()
+                .find(|y| y.ship.frame.symbol == s.ship.frame.symbol)
+                .map(|y| y.ship.purchase_price as i64)
+        })
+        .sum()
+}
@@ -0,0 +1,175 @@
+use egui::{Color32, Ui};
+use spacedust::models::*;
+
+/// Component condition (out of 100) below which we flag it
+pub const DEGRADED_CONDITION: i32 = 80;
+/// Condition below which the component is close to failing
+pub const CRITICAL_CONDITION: i32 = 30;
+/// Crew morale (out of 100) below which we flag it
+pub const LOW_MORALE: i32 = 50;
+
+/// Power drawn by everything installed, against what the reactor puts out
+pub fn power_usage(ship: &Ship) -> (i32, i32) {
+    let used = ship
+        .modules
+        .iter()
+        .filter_map(|m| m.requirements.power)
+        .chain(ship.mounts.iter().filter_map(|m| m.requirements.power))
+        .chain(ship.engine.requirements.power)
+        .sum();
+    (used, ship.reactor.power_output)
+}
+
+/// Crew needed by everything installed, against the crew quarters available
+pub fn crew_usage(ship: &Ship) -> (i32, i32) {
+    let used = ship
+        .modules
+        .iter()
+        .filter_map(|m| m.requirements.crew)
+        .chain(ship.mounts.iter().filter_map(|m| m.requirements.crew))
+        .chain(ship.engine.requirements.crew)
+        .chain(ship.reactor.requirements.crew)
+        .chain(ship.frame.requirements.crew)
+        .sum();
+    (used, ship.crew.capacity)
+}
+
+/// Frame, reactor and engine conditions, where the server reports them
+pub fn conditions(ship: &Ship) -> Vec<(&'static str, i32)> {
+    [
+        ("Frame", ship.frame.condition),
+        ("Reactor", ship.reactor.condition),
+        ("Engine", ship.engine.condition),
+    ]
+    .into_iter()
+    .filter_map(|(name, c)| c.map(|c| (name, c)))
+    .collect()
+}
+
+fn condition_label(ui: &mut Ui, condition: Option<i32>) {
+    match condition {
+        Some(c) if c < CRITICAL_CONDITION => {
+            ui.colored_label(Color32::RED, format!("\tCondition: {} (critical)", c));
+        }
+        Some(c) if c < DEGRADED_CONDITION => {
+            ui.colored_label(Color32::YELLOW, format!("\tCondition: {} (degraded)", c));
+        }
+        Some(c) => {
+            ui.label(format!("\tCondition: {}", c));
+        }
+        None => {
+            ui.label("\tCondition: unknown");
+        }
+    }
+}
+
+fn requirements_label(ui: &mut Ui, requirements: &ShipRequirements) {
+    let mut parts = vec![];
+    if let Some(p) = requirements.power {
+        parts.push(format!("power {}", p));
+    }
+    if let Some(c) = requirements.crew {
+        parts.push(format!("crew {}", c));
+    }
+    if let Some(s) = requirements.slots {
+        parts.push(format!("slots {}", s));
+    }
+    if parts.len() > 0 {
+        ui.label(format!("\t\tRequires {}", parts.join(", ")));
+    }
+}
+
+/// Everything the API tells us about a ship's build and crew
+pub fn render_ship_details(ui: &mut Ui, ship: &Ship) {
+    for (name, condition) in conditions(ship) {
+        if condition < DEGRADED_CONDITION {
+            ui.colored_label(
+                Color32::YELLOW,
+                format!(
+                    "Warning: {} condition is {}",
+                    name.to_lowercase(),
+                    condition
+                ),
+            );
+        }
+    }
+    if ship.crew.morale < LOW_MORALE {
+        ui.colored_label(
+            Color32::YELLOW,
+            format!("Warning: crew morale is {}", ship.crew.morale),
+        );
+    }
+    let (power_used, power_output) = power_usage(ship);
+    if power_used > power_output {
+        ui.colored_label(
+            Color32::RED,
+            format!(
+                "Warning: drawing {} power from a {} reactor",
+                power_used, power_output
+            ),
+        );
+    }
+
+    ui.label(format!("Registration: {}", ship.registration.name));
+    ui.label(format!("\tFaction: {}", ship.registration.faction_symbol));
+    ui.label(format!("\tRole: {:?}", ship.registration.role));
+
+    ui.label(format!("Frame: {}", ship.frame.name));
+    condition_label(ui, ship.frame.condition);
+    ui.label(format!(
+        "\tModule slots: {}, mounting points: {}, fuel capacity: {}",
+        ship.frame.module_slots, ship.frame.mounting_points, ship.frame.fuel_capacity
+    ));
+
+    ui.label(format!("Reactor: {}", ship.reactor.name));
+    condition_label(ui, ship.reactor.condition);
+    ui.label(format!(
+        "\tPower output: {} ({} in use)",
+        ship.reactor.power_output, power_used
+    ));
+
+    ui.label(format!("Engine: {}", ship.engine.name));
+    condition_label(ui, ship.engine.condition);
+    ui.label(format!("\tSpeed: {}", ship.engine.speed));
+
+    ui.label(format!("Modules ({}):", ship.modules.len()));
+    for module in &ship.modules {
+        ui.label(format!("\t{}", module.name));
+        if let Some(c) = module.capacity {
+            ui.label(format!("\t\tCapacity: {}", c));
+        }
+        if let Some(r) = module.range {
+            ui.label(format!("\t\tRange: {}", r));
+        }
+        requirements_label(ui, &module.requirements);
+    }
+
+    ui.label(format!("Mounts ({}):", ship.mounts.len()));
+    for mount in &ship.mounts {
+        ui.label(format!("\t{}", mount.name));
+        if let Some(s) = mount.strength {
+            ui.label(format!("\t\tStrength: {}", s));
+        }
+        if let Some(d) = &mount.deposits {
+            let deposits: Vec<String> = d.iter().map(|d| format!("{:?}", d)).collect();
+            ui.label(format!("\t\tDeposits: {}", deposits.join(", ")));
+        }
+        requirements_label(ui, &mount.requirements);
+    }
+
+    let (crew_used, _) = crew_usage(ship);
+    ui.label("Crew:");
+    ui.label(format!(
+        "\t{} aboard, {} required, {} capacity ({} needed by installed parts)",
+        ship.crew.current, ship.crew.required, ship.crew.capacity, crew_used
+    ));
+    if ship.crew.morale < LOW_MORALE {
+        ui.colored_label(Color32::YELLOW, format!("\tMorale: {}", ship.crew.morale));
+    } else {
+        ui.label(format!("\tMorale: {}", ship.crew.morale));
+    }
+    ui.label(format!(
+        "\tRotation: {:?}, wages: {}",
+        ship.crew.rotation, ship.crew.wages
+    ));
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use egui::Ui;
+use strum_macros::EnumIter;
+
+use crate::history::{now, SpendingCategory, SECONDS_PER_HOUR};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum TransactionKind {
+    Sale,
+    Purchase,
+    Refuel,
+    ContractDelivery,
+    ShipPurchase,
+    ShipModification,
+    Repair,
+    Scrap,
+}
+
+impl TransactionKind {
+    /// Which spending chart bucket a cost of this kind belongs to
+    pub fn spending_category(&self) -> Option<SpendingCategory> {
+        match self {
+            TransactionKind::Purchase => Some(SpendingCategory::Goods),
+            TransactionKind::Refuel => Some(SpendingCategory::Fuel),
+            TransactionKind::ShipPurchase
+            | TransactionKind::ShipModification
+            | TransactionKind::Repair => Some(SpendingCategory::Ships),
+            TransactionKind::Sale | TransactionKind::ContractDelivery | TransactionKind::Scrap => {
+                None
+            }
+        }
+    }
+}
+
+/// A single credit movement attributed to a ship. Revenue is positive, costs are negative
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct LedgerEntry {
+    pub timestamp: i64,
+    pub ship_symbol: String,
+    pub kind: TransactionKind,
+    pub trade_symbol: String,
+    pub units: i32,
+    pub amount: i64,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        ship_symbol: &str,
+        kind: TransactionKind,
+        trade_symbol: &str,
+        units: i32,
+        amount: i64,
+    ) -> Self {
+        LedgerEntry {
+            timestamp: now(),
+            ship_symbol: ship_symbol.to_owned(),
+            kind,
+            trade_symbol: trade_symbol.to_owned(),
+            units,
+            amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, serde::Deserialize, serde::Serialize)]
+pub enum LedgerWindow {
+    LastHour,
+    Last6Hours,
+    Last24Hours,
+    All,
+}
+
+impl LedgerWindow {
+    fn seconds(&self) -> Option<i64> {
+        match self {
+            LedgerWindow::LastHour => Some(3600),
+            LedgerWindow::Last6Hours => Some(6 * 3600),
+            LedgerWindow::Last24Hours => Some(24 * 3600),
+            LedgerWindow::All => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ShipProfit {
+    pub revenue: i64,
+    pub costs: i64,
+}
+
+impl ShipProfit {
+    pub fn net(&self) -> i64 {
+        self.revenue - self.costs
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Ledger {
+    pub entries: Vec<LedgerEntry>,
+    window: LedgerWindow,
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            window: LedgerWindow::Last24Hours,
+        }
+    }
+}
+
+impl Ledger {
+    pub fn record(&mut self, entry: LedgerEntry) {
+        self.entries.push(entry);
+    }
+
+    fn window_start(&self) -> i64 {
+        match self.window.seconds() {
+            Some(s) => now() - s,
+            None => self.entries.first().map(|e| e.timestamp).unwrap_or(0),
+        }
+    }
+
+    pub fn profit_by_ship(&self) -> BTreeMap<String, ShipProfit> {
+        let start = self.window_start();
+        let mut totals: BTreeMap<String, ShipProfit> = BTreeMap::new();
+
+        for e in self.entries.iter().filter(|e| e.timestamp >= start) {
+            let profit = totals.entry(e.ship_symbol.clone()).or_default();
+            if e.amount >= 0 {
+                profit.revenue += e.amount;
+            } else {
+                profit.costs -= e.amount;
+            }
+        }
+        totals
+    }
+
+    /// Most recent entries for one ship, newest first
+    pub fn render_ship(&self, ui: &mut Ui, ship_symbol: &str) {
+        egui::CollapsingHeader::new("Ledger")
+            .id_source(format!("ledger_{}", ship_symbol))
+            .show(ui, |ui| {
+                let mut entries = self
+                    .entries
+                    .iter()
+                    .rev()
+                    .filter(|e| e.ship_symbol == ship_symbol)
+                    .peekable();
+
+                if entries.peek().is_none() {
+                    ui.label("No transactions recorded");
+                }
+
+                egui::Grid::new(format!("ledger_{}", ship_symbol))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for e in entries.take(50) {
+                            ui.label(format!("{:?}", e.kind));
+                            ui.label(&e.trade_symbol);
+                            ui.label(format!("{}", e.units));
+                            ui.label(format!("{:+}", e.amount));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    pub fn render(&mut self, ui: &mut Ui) {
+        egui::ComboBox::from_label("Time window")
+            .selected_text(format!("{:?}", self.window))
+            .show_ui(ui, |ui| {
+                for w in <LedgerWindow as strum::IntoEnumIterator>::iter() {
+                    ui.selectable_value(&mut self.window, w, format!("{:?}", w));
+                }
+            });
+
+        let hours = ((now() - self.window_start()) as f64 / SECONDS_PER_HOUR).max(1.0 / 60.0);
+        let totals = self.profit_by_ship();
+
+        if totals.len() == 0 {
+            ui.label("No transactions in this time window");
+            return;
+        }
+
+        egui::Grid::new("profit_and_loss")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Ship");
+                ui.strong("Revenue");
+                ui.strong("Costs");
+                ui.strong("Net");
+                ui.strong("Net / hour");
+                ui.end_row();
+
+                for (ship, profit) in &totals {
+                    ui.label(ship);
+                    ui.label(format!("{}", profit.revenue));
+                    ui.label(format!("{}", profit.costs));
+                    ui.label(format!("{}", profit.net()));
+                    ui.label(format!("{:.0}", profit.net() as f64 / hours));
+                    ui.end_row();
+                }
+            });
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Ui};
+use pollster::FutureExt;
+use spacedust::apis::configuration::Configuration;
+use spacedust::models::*;
+
+use crate::inspector::{conditions, DEGRADED_CONDITION};
+use crate::ledger::{LedgerEntry, TransactionKind};
+use crate::AppData;
+
+// The repair and scrap endpoints are newer than our API client, so they're called
+// directly. Servers without them just answer with an error
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Service {
+    Repair,
+    Scrap,
+}
+
+impl Service {
+    fn path(&self) -> &'static str {
+        match self {
+            Service::Repair => "repair",
+            Service::Scrap => "scrap",
+        }
+    }
+}
+
+/// A maintenance step picked in the UI, carried out after the frame is drawn
+#[derive(Debug, Clone)]
+pub enum MaintenanceAction {
+    Estimate(String),
+    Perform(String, Service),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Estimate {
+    pub repair: Option<i64>,
+    pub scrap: Option<i64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Maintenance {
+    /// Components below this condition raise the fleet-wide alert
+    pub threshold: i32,
+    #[serde(skip)]
+    pub estimates: HashMap<String, Estimate>,
+    /// Ship whose scrap button has been armed
+    #[serde(skip)]
+    pub confirm_scrap: Option<String>,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self {
+            threshold: DEGRADED_CONDITION,
+            estimates: HashMap::new(),
+            confirm_scrap: None,
+        }
+    }
+}
+
+/// Every component below the threshold, as (ship, component, condition)
+pub fn worn_components(ships: &[Ship], threshold: i32) -> Vec<(String, &'static str, i32)> {
+    ships
+        .iter()
+        .flat_map(|s| {
+            conditions(s)
+                .into_iter()
+                .filter(|(_, c)| *c < threshold)
+                .map(|(name, c)| (s.symbol.clone(), name, c))
+        })
+        .collect()
+}
+
+async fn call(
+    conf: &Configuration,
+    ship_symbol: &str,
+    service: Service,
+    perform: bool,
+) -> Result<serde_json::Value, String> {
+    let url = format!(
+        "{}/my/ships/{}/{}",
+        conf.base_path,
+        ship_symbol,
+        service.path()
+    );
+    let mut request = if perform {
+        conf.client.post(url)
+    } else {
+        conf.client.get(url)
+    };
+    if let Some(token) = &conf.bearer_access_token {
+        request = request.bearer_auth(token);
+    }
+
+    let text = match request.send().await {
+        Ok(r) => r.text().await.map_err(|e| e.to_string())?,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut body: serde_json::Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+
+    match body.get_mut("data") {
+        Some(data) => Ok(data.take()),
+        None => Err(body["error"]["message"]
+            .as_str()
+            .unwrap_or("unexpected response")
+            .to_owned()),
+    }
+}
+
+fn total_price(data: &serde_json::Value) -> Option<i64> {
+    data["transaction"]["totalPrice"].as_i64()
+}
+
+/// Asks the shipyard what repairing and scrapping a ship would come to
+pub fn estimate(data: &mut AppData, ship_symbol: &str) {
+    let mut estimate = Estimate::default();
+    for service in [Service::Repair, Service::Scrap] {
+        match call(&data.conf, ship_symbol, service, false).block_on() {
+            Ok(r) => match service {
+                Service::Repair => estimate.repair = total_price(&r),
+                Service::Scrap => estimate.scrap = total_price(&r),
+            },
+            Err(e) => data.report_error(format!(
+                "{}: no {} estimate: {}",
+                ship_symbol,
+                service.path(),
+                e
+            )),
+        }
+    }
+    data.maintenance
+        .estimates
+        .insert(ship_symbol.to_owned(), estimate);
+}
+
+/// Repairs a ship in place, or scraps it and drops it from the fleet
+pub fn perform(data: &mut AppData, ship_symbol: &str, service: Service) -> Result<(), String> {
+    let mut r = match call(&data.conf, ship_symbol, service, true).block_on() {
+        Ok(r) => r,
+        Err(e) => {
+            let message = format!("{}: could not {}: {}", ship_symbol, service.path(), e);
+            data.report_error(message.clone());
+            return Err(message);
+        }
+    };
+
+    if let Ok(agent) = serde_json::from_value::<Agent>(r["agent"].take()) {
+        data.update_agent(agent);
+    }
+    let price = total_price(&r).unwrap_or(0);
+    data.maintenance.estimates.remove(ship_symbol);
+
+    match service {
+        Service::Repair => {
+            if let Ok(ship) = serde_json::from_value::<Ship>(r["ship"].take()) {
+                if let Some(s) = data.ships.iter_mut().find(|s| s.ship.symbol == ship_symbol) {
+                    s.ship = ship;
+                }
+            }
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::Repair,
+                "REPAIR",
+                1,
+                -price,
+            ));
+            data.log
+                .push(format!("{}: repaired for {}", ship_symbol, price));
+        }
+        Service::Scrap => {
+            data.record_transaction(LedgerEntry::new(
+                ship_symbol,
+                TransactionKind::Scrap,
+                "SCRAP",
+                1,
+                price,
+            ));
+            data.ships.retain(|s| s.ship.symbol != ship_symbol);
+            data.forget_ship(ship_symbol);
+            data.log
+                .push(format!("{}: scrapped for {}", ship_symbol, price));
+        }
+    }
+    Ok(())
+}
+
+impl Maintenance {
+    /// Fleet-wide alert for worn components, with the threshold that triggers it
+    pub fn render_alert(&mut self, ui: &mut Ui, ships: &[Ship]) {
+        ui.horizontal(|ui| {
+            ui.label("Maintenance alert below");
+            ui.add(egui::DragValue::new(&mut self.threshold).clamp_range(0..=100));
+        });
+
+        let worn = worn_components(ships, self.threshold);
+        if worn.len() == 0 {
+            return;
+        }
+        ui.colored_label(
+            Color32::YELLOW,
+            format!("{} components need maintenance:", worn.len()),
+        );
+        for (ship, component, condition) in worn {
+            ui.colored_label(
+                Color32::YELLOW,
+                format!("\t{} {}: {}", ship, component.to_lowercase(), condition),
+            );
+        }
+    }
+
+    /// Repair and scrap controls for one ship. `at_shipyard` is the result of
+    /// `check_at_shipyard` for it
+    pub fn render_ship(
+        &mut self,
+        ui: &mut Ui,
+        ship: &Ship,
+        at_shipyard: Result<(), String>,
+    ) -> Option<MaintenanceAction> {
+        let mut action = None;
+
+        egui::CollapsingHeader::new("Maintenance")
+            .id_source(format!("maintenance_{}", ship.symbol))
+            .show(ui, |ui| {
+                if let Err(e) = at_shipyard {
+                    ui.label(format!("{} to repair or scrap", e));
+                    return;
+                }
+
+                if ui.button("Get estimates").clicked() {
+                    action = Some(MaintenanceAction::Estimate(ship.symbol.clone()));
+                }
+                let estimate = match self.estimates.get(&ship.symbol) {
+                    Some(e) => e.clone(),
+                    None => return,
+                };
+
+                if let Some(cost) = estimate.repair {
+                    if ui.button(format!("Repair for {}", cost)).clicked() {
+                        action = Some(MaintenanceAction::Perform(
+                            ship.symbol.clone(),
+                            Service::Repair,
+                        ));
+                    }
+                }
+                if let Some(value) = estimate.scrap {
+                    let armed = self.confirm_scrap.as_ref() == Some(&ship.symbol);
+                    if !armed && ui.button(format!("Scrap for {}", value)).clicked() {
+                        self.confirm_scrap = Some(ship.symbol.clone());
+                    }
+                    if armed {
+                        ui.colored_label(Color32::RED, "Scrapping can't be undone");
+                        ui.horizontal(|ui| {
+                            if ui.button("Confirm scrap").clicked() {
+                                action = Some(MaintenanceAction::Perform(
+                                    ship.symbol.clone(),
+                                    Service::Scrap,
+                                ));
+                                self.confirm_scrap = None;
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.confirm_scrap = None;
+                            }
+                        });
+                    }
+                }
+            });
+
+        action
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+
+use egui::plot::{Legend, Plot, PlotPoint, PlotPoints, Points, Text};
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::catalogue::SystemCatalogue;
+use crate::exploration::Exploration;
+use crate::spacetraders::ShipWithNav;
+
+const SYSTEM_TYPES: [SystemType; 10] = [
+    SystemType::NeutronStar,
+    SystemType::RedStar,
+    SystemType::OrangeStar,
+    SystemType::BlueStar,
+    SystemType::YoungStar,
+    SystemType::WhiteDwarf,
+    SystemType::BlackHole,
+    SystemType::Hypergiant,
+    SystemType::Nebula,
+    SystemType::Unstable,
+];
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct MapView {
+    /// System shown in the system view
+    pub system: String,
+    pub galaxy: bool,
+    /// Galaxy view filters, empty or None shows everything
+    pub sector: String,
+    pub faction: String,
+    pub system_type: Option<SystemType>,
+}
+
+impl MapView {
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        catalogue: &SystemCatalogue,
+        waypoints: &[Waypoint],
+        ships: &[ShipWithNav],
+        exploration: &Exploration,
+    ) {
+        let mut systems: Vec<&str> = waypoints
+            .iter()
+            .map(|w| w.system_symbol.as_str())
+            .chain(
+                exploration
+                    .waypoints
+                    .iter()
+                    .map(|w| w.waypoint.system_symbol.as_str()),
+            )
+            .collect();
+        systems.sort();
+        systems.dedup();
+
+        if self.system.len() == 0 {
+            if let Some(s) = ships.first() {
+                self.system = s.ship.nav.system_symbol.clone();
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.galaxy, false, "System");
+            ui.selectable_value(&mut self.galaxy, true, "Galaxy");
+
+            if !self.galaxy {
+                egui::ComboBox::from_id_source("map_system")
+                    .selected_text(&self.system)
+                    .show_ui(ui, |ui| {
+                        for s in systems {
+                            ui.selectable_value(&mut self.system, s.to_owned(), s);
+                        }
+                    });
+            }
+        });
+
+        if self.galaxy {
+            ui.horizontal(|ui| {
+                ui.label("Sector");
+                ui.add(egui::TextEdit::singleline(&mut self.sector).desired_width(60.0));
+                ui.label("Faction");
+                ui.add(egui::TextEdit::singleline(&mut self.faction).desired_width(80.0));
+                egui::ComboBox::from_label("Type")
+                    .selected_text(match self.system_type {
+                        Some(t) => format!("{:?}", t),
+                        None => "Any".to_owned(),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.system_type, None, "Any");
+                        for t in SYSTEM_TYPES {
+                            ui.selectable_value(&mut self.system_type, Some(t), format!("{:?}", t));
+                        }
+                    });
+            });
+            let shown = self.filter(catalogue);
+            render_galaxy(ui, catalogue, &shown, ships);
+        } else {
+            render_system(ui, &self.system, waypoints, ships, exploration);
+        }
+    }
+
+    /// Systems that pass every filter set for the galaxy view
+    fn filter<'a>(&self, catalogue: &'a SystemCatalogue) -> Vec<&'a System> {
+        let mut shown: Vec<&System> = catalogue.systems().iter().collect();
+        let mut keep = |matching: Vec<&System>| {
+            let symbols: HashSet<&str> = matching.iter().map(|s| s.symbol.as_str()).collect();
+            shown.retain(|s| symbols.contains(s.symbol.as_str()));
+        };
+
+        if self.sector.len() > 0 {
+            keep(catalogue.in_sector(&self.sector));
+        }
+        if self.faction.len() > 0 {
+            keep(catalogue.with_faction(&self.faction));
+        }
+        if let Some(t) = self.system_type {
+            keep(catalogue.of_type(t));
+        }
+        shown
+    }
+}
+
+fn render_galaxy(
+    ui: &mut Ui,
+    catalogue: &SystemCatalogue,
+    shown: &[&System],
+    ships: &[ShipWithNav],
+) {
+    if catalogue.is_empty() {
+        ui.label("System catalogue is empty");
+        return;
+    }
+
+    let fleet: Vec<[f64; 2]> = ships
+        .iter()
+        .filter_map(|s| catalogue.get(&s.ship.nav.system_symbol))
+        .map(|s| [s.x as f64, s.y as f64])
+        .collect();
+
+    Plot::new("galaxy_map")
+        .data_aspect(1.0)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            plot_ui.points(
+                Points::new(PlotPoints::new(
+                    shown.iter().map(|s| [s.x as f64, s.y as f64]).collect(),
+                ))
+                .radius(1.5)
+                .name("Systems"),
+            );
+            plot_ui.points(
+                Points::new(PlotPoints::new(fleet))
+                    .radius(4.0)
+                    .name("Fleet"),
+            );
+        });
+}
+
+fn render_system(
+    ui: &mut Ui,
+    system: &str,
+    waypoints: &[Waypoint],
+    ships: &[ShipWithNav],
+    exploration: &Exploration,
+) {
+    let waypoints: Vec<&Waypoint> = waypoints
+        .iter()
+        .filter(|w| w.system_symbol == system)
+        .collect();
+
+    // Scanned waypoints we haven't fetched properly yet
+    let discovered: Vec<&ScannedWaypoint> = exploration
+        .waypoints
+        .iter()
+        .map(|w| &w.waypoint)
+        .filter(|w| w.system_symbol == system)
+        .filter(|w| !waypoints.iter().any(|known| known.symbol == w.symbol))
+        .collect();
+
+    // Other agents' ships, placed at whichever waypoint we last saw them
+    let sightings: Vec<([f64; 2], &str)> = exploration
+        .ships
+        .iter()
+        .filter(|s| s.ship.nav.system_symbol == system)
+        .filter_map(|s| {
+            let at = &s.ship.nav.waypoint_symbol;
+            waypoints
+                .iter()
+                .find(|w| &w.symbol == at)
+                .map(|w| [w.x as f64, w.y as f64])
+                .or_else(|| {
+                    discovered
+                        .iter()
+                        .find(|w| &w.symbol == at)
+                        .map(|w| [w.x as f64, w.y as f64])
+                })
+                .map(|p| (p, s.ship.symbol.as_str()))
+        })
+        .collect();
+
+    if waypoints.len() == 0 && discovered.len() == 0 {
+        ui.label("No waypoints known for this system");
+        return;
+    }
+
+    let fleet: Vec<(&ShipWithNav, &Waypoint)> = ships
+        .iter()
+        .filter_map(|s| {
+            waypoints
+                .iter()
+                .find(|w| w.symbol == s.ship.nav.waypoint_symbol)
+                .map(|w| (s, *w))
+        })
+        .collect();
+
+    Plot::new("system_map")
+        .data_aspect(1.0)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            plot_ui.points(
+                Points::new(PlotPoints::new(
+                    waypoints.iter().map(|w| [w.x as f64, w.y as f64]).collect(),
+                ))
+                .radius(3.0)
+                .name("Waypoints"),
+            );
+            for w in &waypoints {
+                plot_ui.text(Text::new(
+                    PlotPoint::new(w.x as f64, w.y as f64 + 2.0),
+                    format!("{:?}", w.r#type),
+                ));
+            }
+
+            plot_ui.points(
+                Points::new(PlotPoints::new(
+                    fleet
+                        .iter()
+                        .map(|(_, w)| [w.x as f64, w.y as f64])
+                        .collect(),
+                ))
+                .radius(5.0)
+                .name("Fleet"),
+            );
+            for (s, w) in &fleet {
+                plot_ui.text(Text::new(
+                    PlotPoint::new(w.x as f64, w.y as f64 - 2.0),
+                    &s.ship.symbol,
+                ));
+            }
+
+            plot_ui.points(
+                Points::new(PlotPoints::new(
+                    discovered
+                        .iter()
+                        .map(|w| [w.x as f64, w.y as f64])
+                        .collect(),
+                ))
+                .radius(3.0)
+                .name("Discovered waypoints"),
+            );
+            for w in &discovered {
+                plot_ui.text(Text::new(
+                    PlotPoint::new(w.x as f64, w.y as f64 + 2.0),
+                    format!("{:?}", w.r#type),
+                ));
+            }
+
+            plot_ui.points(
+                Points::new(PlotPoints::new(sightings.iter().map(|(p, _)| *p).collect()))
+                    .radius(4.0)
+                    .name("Other ships"),
+            );
+            for (p, symbol) in &sightings {
+                plot_ui.text(Text::new(PlotPoint::new(p[0], p[1] - 4.0), *symbol));
+            }
+        });
+}
@@ -0,0 +1,200 @@
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::inspector::{crew_usage, power_usage};
+use crate::navigation::find_waypoint;
+use crate::AppData;
+
+/// A mount change picked in the UI, carried out after the frame is drawn
+#[derive(Debug, Clone)]
+pub enum MountAction {
+    Refresh(String),
+    Install(String, String),
+    Remove(String, String),
+}
+
+pub fn is_mount(trade_symbol: &str) -> bool {
+    trade_symbol.starts_with("MOUNT_")
+}
+
+/// The trade symbol for an installed mount, as it appears in cargo and the API
+pub fn symbol_of(mount: &ShipMount) -> String {
+    match serde_json::to_value(&mount.symbol) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => format!("{:?}", mount.symbol),
+    }
+}
+
+pub fn mounts_in_cargo(ship: &Ship) -> Vec<&ShipCargoItem> {
+    ship.cargo
+        .inventory
+        .iter()
+        .filter(|c| is_mount(&c.symbol))
+        .collect()
+}
+
+/// What a mount needs, from any copy of it we've seen on our ships or in a shipyard
+pub fn mount_requirements(data: &AppData, mount_symbol: &str) -> Option<ShipRequirements> {
+    let shipyard_mounts = data
+        .shipyard_ships
+        .iter()
+        .flatten()
+        .flat_map(|s| s.ship.mounts.iter());
+    data.ships
+        .iter()
+        .flat_map(|s| s.ship.mounts.iter())
+        .chain(shipyard_mounts)
+        .find(|m| symbol_of(m) == mount_symbol)
+        .map(|m| *m.requirements.clone())
+}
+
+/// Mounts can only be changed while docked at a shipyard
+pub fn check_at_shipyard(data: &AppData, ship: &Ship) -> Result<(), String> {
+    if !matches!(ship.nav.status, ShipNavStatus::Docked) {
+        return Err("Ship must be docked".to_owned());
+    }
+    let at_shipyard = find_waypoint(&data.waypoints, &ship.nav.waypoint_symbol)
+        .map(|w| {
+            w.traits
+                .iter()
+                .any(|t| t.symbol == waypoint_trait::Symbol::Shipyard)
+        })
+        .unwrap_or(false);
+    if !at_shipyard {
+        return Err("Ship must be at a shipyard".to_owned());
+    }
+    Ok(())
+}
+
+pub fn validate_install(data: &AppData, ship: &Ship, mount_symbol: &str) -> Result<(), String> {
+    check_at_shipyard(data, ship)?;
+
+    if !ship
+        .cargo
+        .inventory
+        .iter()
+        .any(|c| c.symbol == mount_symbol)
+    {
+        return Err(format!("No {} in cargo", mount_symbol));
+    }
+    if ship.mounts.len() as i32 >= ship.frame.mounting_points {
+        return Err(format!(
+            "All {} mounting points are in use",
+            ship.frame.mounting_points
+        ));
+    }
+
+    let requirements = match mount_requirements(data, mount_symbol) {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    let (power_used, power_output) = power_usage(ship);
+    let power = requirements.power.unwrap_or(0);
+    if power_used + power > power_output {
+        return Err(format!(
+            "Needs {} power, only {} of {} free",
+            power,
+            power_output - power_used,
+            power_output
+        ));
+    }
+    let (crew_used, crew_capacity) = crew_usage(ship);
+    let crew = requirements.crew.unwrap_or(0);
+    if crew_used + crew > crew_capacity {
+        return Err(format!(
+            "Needs {} crew, only {} of {} free",
+            crew,
+            crew_capacity - crew_used,
+            crew_capacity
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_remove(data: &AppData, ship: &Ship, mount_symbol: &str) -> Result<(), String> {
+    check_at_shipyard(data, ship)?;
+
+    if !ship.mounts.iter().any(|m| symbol_of(m) == mount_symbol) {
+        return Err(format!("{} is not installed", mount_symbol));
+    }
+    if ship.cargo.units >= ship.cargo.capacity {
+        return Err("No cargo space for the removed mount".to_owned());
+    }
+    Ok(())
+}
+
+fn requirements_text(requirements: Option<ShipRequirements>) -> String {
+    match requirements {
+        Some(r) => format!(
+            "power {}, crew {}",
+            r.power.unwrap_or(0),
+            r.crew.unwrap_or(0)
+        ),
+        None => "requirements unknown".to_owned(),
+    }
+}
+
+/// Installed mounts and mounts in cargo, with install/remove buttons where allowed
+pub fn render_mounts(ui: &mut Ui, data: &AppData, ship: &Ship) -> Option<MountAction> {
+    let mut action = None;
+
+    egui::CollapsingHeader::new("Mount management")
+        .id_source(format!("mounts_{}", ship.symbol))
+        .show(ui, |ui| {
+            let (power_used, power_output) = power_usage(ship);
+            let (crew_used, crew_capacity) = crew_usage(ship);
+            ui.label(format!(
+                "Mounting points: {} of {} used",
+                ship.mounts.len(),
+                ship.frame.mounting_points
+            ));
+            ui.label(format!("Power: {} of {} used", power_used, power_output));
+            ui.label(format!("Crew: {} of {} used", crew_used, crew_capacity));
+
+            ui.label("Installed:");
+            for mount in &ship.mounts {
+                let symbol = symbol_of(mount);
+                ui.horizontal(|ui| {
+                    ui.label(format!("\t{}", mount.name));
+                    let check = validate_remove(data, ship, &symbol);
+                    let button = ui.add_enabled(check.is_ok(), egui::Button::new("Remove"));
+                    if let Err(e) = check {
+                        button.on_disabled_hover_text(e);
+                    } else if button.clicked() {
+                        action = Some(MountAction::Remove(ship.symbol.clone(), symbol.clone()));
+                    }
+                });
+            }
+
+            let cargo = mounts_in_cargo(ship);
+            if cargo.len() > 0 {
+                ui.label("In cargo:");
+            }
+            for item in cargo {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "\t{} x{} ({})",
+                        item.name,
+                        item.units,
+                        requirements_text(mount_requirements(data, &item.symbol))
+                    ));
+                    let check = validate_install(data, ship, &item.symbol);
+                    let button = ui.add_enabled(check.is_ok(), egui::Button::new("Install"));
+                    if let Err(e) = check {
+                        button.on_disabled_hover_text(e);
+                    } else if button.clicked() {
+                        action = Some(MountAction::Install(
+                            ship.symbol.clone(),
+                            item.symbol.clone(),
+                        ));
+                    }
+                });
+            }
+
+            if ui.button("Refresh mounts").clicked() {
+                action = Some(MountAction::Refresh(ship.symbol.clone()));
+            }
+        });
+
+    action
+}
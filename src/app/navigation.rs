@@ -0,0 +1,490 @@
+use std::collections::{HashMap, VecDeque};
+
+use spacedust::models::*;
+
+use crate::catalogue::SystemCatalogue;
+use crate::spacetraders::ShipWithNav;
+
+/// Travel time multiplier for the CRUISE flight mode
+const CRUISE_MULTIPLIER: f64 = 25.0;
+/// Fixed overhead the server adds to every in-system navigation
+const NAVIGATION_OVERHEAD_SECONDS: f64 = 15.0;
+/// Travel time multiplier for warping between systems
+const WARP_MULTIPLIER: f64 = 50.0;
+/// Closest systems considered when listing where a ship can warp
+const WARP_CANDIDATES: usize = 50;
+
+pub fn find_waypoint<'a>(waypoints: &'a [Waypoint], symbol: &str) -> Option<&'a Waypoint> {
+    waypoints.iter().find(|w| w.symbol == symbol)
+}
+
+pub fn distance(a: &Waypoint, b: &Waypoint) -> f64 {
+    let dx = (a.x - b.x) as f64;
+    let dy = (a.y - b.y) as f64;
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Estimated seconds to cruise `distance` units with the given engine speed
+pub fn travel_seconds(engine_speed: f64, distance: f64) -> i64 {
+    let speed = engine_speed.max(1.0);
+    (distance.max(1.0).round() * (CRUISE_MULTIPLIER / speed) + NAVIGATION_OVERHEAD_SECONDS).round()
+        as i64
+}
+
+/// Estimated seconds for a ship to get from where it is (or is heading) to a waypoint.
+/// Returns None if either end isn't a known waypoint
+pub fn ship_travel_seconds(
+    ship: &ShipWithNav,
+    destination: &str,
+    waypoints: &[Waypoint],
+) -> Option<i64> {
+    if ship.ship.nav.waypoint_symbol == destination {
+        return Some(0);
+    }
+
+    let from = find_waypoint(waypoints, &ship.ship.nav.waypoint_symbol)?;
+    let to = find_waypoint(waypoints, destination)?;
+
+    Some(travel_seconds(
+        ship.ship.engine.speed as f64,
+        distance(from, to),
+    ))
+}
+
+/// A jump gate we've looked up, with the system it sits in
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct JumpGateInfo {
+    pub system_symbol: String,
+    pub waypoint_symbol: String,
+    pub gate: JumpGate,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum RouteStep {
+    /// Fly to a waypoint in the current system
+    Navigate(String),
+    /// Jump through the gate the ship is at to another system
+    Jump(String),
+    /// Warp straight to a waypoint in another system
+    Warp(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WarpEstimate {
+    pub distance: f64,
+    pub fuel: i32,
+    pub seconds: i64,
+}
+
+/// Range of the ship's warp drive, if it has one
+pub fn warp_range(ship: &ShipWithNav) -> Option<i32> {
+    ship.ship
+        .modules
+        .iter()
+        .filter(|m| {
+            matches!(
+                m.symbol,
+                ship_module::Symbol::ModuleWarpDriveI
+                    | ship_module::Symbol::ModuleWarpDriveIi
+                    | ship_module::Symbol::ModuleWarpDriveIii
+            )
+        })
+        .filter_map(|m| m.range)
+        .max()
+}
+
+pub fn system_distance(a: &System, b: &System) -> f64 {
+    let dx = (a.x - b.x) as f64;
+    let dy = (a.y - b.y) as f64;
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Estimated cost of warping between two systems. Warping burns about one unit of fuel
+/// per unit of distance
+pub fn warp_estimate(ship: &ShipWithNav, from: &System, to: &System) -> WarpEstimate {
+    let distance = system_distance(from, to);
+    let speed = (ship.ship.engine.speed as f64).max(1.0);
+
+    WarpEstimate {
+        distance,
+        fuel: distance.round() as i32,
+        seconds: (distance.max(1.0).round() * (WARP_MULTIPLIER / speed)
+            + NAVIGATION_OVERHEAD_SECONDS)
+            .round() as i64,
+    }
+}
+
+/// Whether a ship can warp to a system right now: it needs a warp drive with enough range
+/// and enough fuel in the tank
+pub fn can_warp_to(ship: &ShipWithNav, to: &str, catalogue: &SystemCatalogue) -> bool {
+    let range = match warp_range(ship) {
+        Some(r) => r,
+        None => return false,
+    };
+
+    match (
+        catalogue.get(&ship.ship.nav.system_symbol),
+        catalogue.get(to),
+    ) {
+        (Some(from), Some(to)) => {
+            let estimate = warp_estimate(ship, from, to);
+            estimate.distance <= range as f64 && estimate.fuel <= ship.ship.fuel.current
+        }
+        _ => false,
+    }
+}
+
+/// Systems a ship could warp to right now, closest first. Only the nearest few are
+/// looked at, the catalogue holds thousands and this runs every frame
+pub fn warp_targets(ship: &ShipWithNav, catalogue: &SystemCatalogue) -> Vec<String> {
+    let here = match catalogue.get(&ship.ship.nav.system_symbol) {
+        Some(s) => s,
+        None => return vec![],
+    };
+
+    catalogue
+        .nearest(here.x, here.y, WARP_CANDIDATES)
+        .into_iter()
+        .filter(|s| s.symbol != here.symbol && can_warp_to(ship, &s.symbol, catalogue))
+        .map(|s| s.symbol.clone())
+        .collect()
+}
+
+/// Where a warp into a system should aim for: its jump gate if it has one, otherwise
+/// whichever waypoint the catalogue lists first
+pub fn warp_arrival(system: &System) -> Option<String> {
+    system
+        .waypoints
+        .iter()
+        .find(|w| w.r#type == WaypointType::JumpGate)
+        .or_else(|| system.waypoints.first())
+        .map(|w| w.symbol.clone())
+}
+
+pub fn system_of(waypoint_symbol: &str) -> &str {
+    waypoint_symbol
+        .rsplit_once('-')
+        .map(|(system, _)| system)
+        .unwrap_or(waypoint_symbol)
+}
+
+/// The system a route target is in: its own system for a waypoint we know, otherwise
+/// the target is taken to be a system
+pub fn target_system<'a>(destination: &'a str, waypoints: &[Waypoint]) -> &'a str {
+    if waypoints.iter().any(|w| w.symbol == destination) {
+        system_of(destination)
+    } else {
+        destination
+    }
+}
+
+/// Shortest chain of systems from one system to another using the jump gates we know about
+pub fn find_system_path(from: &str, to: &str, jump_gates: &[JumpGateInfo]) -> Option<Vec<String>> {
+    let mut previous: HashMap<String, String> = HashMap::new();
+    let mut queue: VecDeque<String> = VecDeque::new();
+    queue.push_back(from.to_owned());
+
+    while let Some(system) = queue.pop_front() {
+        if system == to {
+            let mut path = vec![system];
+            while let Some(p) = previous.get(path.last().unwrap()) {
+                path.push(p.clone());
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for gate in jump_gates.iter().filter(|g| g.system_symbol == system) {
+            for next in &gate.gate.connected_systems {
+                if next.symbol != from && !previous.contains_key(&next.symbol) {
+                    previous.insert(next.symbol.clone(), system.clone());
+                    queue.push_back(next.symbol.clone());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Steps that take a ship to `destination`, which is either a waypoint or a system symbol.
+/// Known jump gates are preferred; ships with a warp drive fall back to warping when no
+/// gate connection is known. Returns None if there's no way to get there
+pub fn plan_route(
+    ship: &ShipWithNav,
+    destination: &str,
+    waypoints: &[Waypoint],
+    jump_gates: &[JumpGateInfo],
+    catalogue: &SystemCatalogue,
+) -> Option<Vec<RouteStep>> {
+    let from_system = ship.ship.nav.system_symbol.as_str();
+    let is_waypoint = waypoints.iter().any(|w| w.symbol == destination);
+    let to_system = target_system(destination, waypoints);
+
+    let mut steps = vec![];
+
+    if from_system != to_system {
+        let gate = jump_gates.iter().find(|g| g.system_symbol == from_system);
+        let path = find_system_path(from_system, to_system, jump_gates);
+
+        match (gate, path) {
+            (Some(gate), Some(path)) => {
+                if ship.ship.nav.waypoint_symbol != gate.waypoint_symbol {
+                    steps.push(RouteStep::Navigate(gate.waypoint_symbol.clone()));
+                }
+                // Jumping lands the ship at the gate of the next system, so the hops chain
+                for system in path.into_iter().skip(1) {
+                    steps.push(RouteStep::Jump(system));
+                }
+            }
+            _ => {
+                if !can_warp_to(ship, to_system, catalogue) {
+                    return None;
+                }
+                let arrival = if is_waypoint {
+                    destination.to_owned()
+                } else {
+                    warp_arrival(catalogue.get(to_system)?)?
+                };
+                steps.push(RouteStep::Warp(arrival));
+                return Some(steps);
+            }
+        }
+    }
+
+    if is_waypoint && ship.ship.nav.waypoint_symbol != destination {
+        steps.push(RouteStep::Navigate(destination.to_owned()));
+    }
+
+    Some(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ship_at(waypoint: &str) -> ShipWithNav {
+        let mut ship = Ship::default();
+        ship.symbol = "SHIP-1".to_owned();
+        ship.nav.waypoint_symbol = waypoint.to_owned();
+        ship.nav.system_symbol = system_of(waypoint).to_owned();
+        ship.engine.speed = 10.0;
+        ShipWithNav {
+            ship,
+            destination: waypoint.to_owned(),
+            route_target: String::new(),
+            actions: vec![],
+        }
+    }
+
+    fn waypoint(symbol: &str) -> Waypoint {
+        Waypoint {
+            symbol: symbol.to_owned(),
+            system_symbol: system_of(symbol).to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn gate(waypoint_symbol: &str, connected: &[&str]) -> JumpGateInfo {
+        JumpGateInfo {
+            system_symbol: system_of(waypoint_symbol).to_owned(),
+            waypoint_symbol: waypoint_symbol.to_owned(),
+            gate: JumpGate {
+                connected_systems: connected
+                    .iter()
+                    .map(|s| ConnectedSystem {
+                        symbol: s.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A - B - C in a line, with D only reachable from C
+    fn gates() -> Vec<JumpGateInfo> {
+        vec![
+            gate("X1-A-GATE", &["X1-B"]),
+            gate("X1-B-GATE", &["X1-A", "X1-C"]),
+            gate("X1-C-GATE", &["X1-B", "X1-D"]),
+        ]
+    }
+
+    fn system(symbol: &str, x: i32, y: i32) -> System {
+        System {
+            symbol: symbol.to_owned(),
+            x,
+            y,
+            waypoints: vec![
+                SystemWaypoint {
+                    symbol: format!("{}-1", symbol),
+                    r#type: WaypointType::Planet,
+                    ..Default::default()
+                },
+                SystemWaypoint {
+                    symbol: format!("{}-GATE", symbol),
+                    r#type: WaypointType::JumpGate,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    /// A at the origin, B 30 units away and C 300
+    fn catalogue() -> SystemCatalogue {
+        let mut catalogue = SystemCatalogue::default();
+        catalogue.add(vec![
+            system("X1-A", 0, 0),
+            system("X1-B", 18, 24),
+            system("X1-C", 300, 0),
+        ]);
+        catalogue
+    }
+
+    fn with_warp_drive(mut ship: ShipWithNav, range: i32, fuel: i32) -> ShipWithNav {
+        ship.ship.modules.push(ShipModule {
+            symbol: ship_module::Symbol::ModuleWarpDriveI,
+            range: Some(range),
+            ..Default::default()
+        });
+        ship.ship.fuel.current = fuel;
+        ship.ship.fuel.capacity = fuel;
+        ship
+    }
+
+    #[test]
+    fn a_system_target_is_its_own_system() {
+        let waypoints = vec![waypoint("X1-B-1")];
+        assert_eq!(target_system("X1-B-1", &waypoints), "X1-B");
+        assert_eq!(target_system("X1-B", &waypoints), "X1-B");
+        // A warp target picked from the catalogue is found there, not under "X1"
+        let catalogue = catalogue();
+        let ship = with_warp_drive(ship_at("X1-A-1"), 100, 100);
+        let to = target_system("X1-B", &waypoints);
+        assert!(can_warp_to(&ship, to, &catalogue));
+    }
+
+    #[test]
+    fn estimates_warp_distance_fuel_and_time() {
+        let ship = ship_at("X1-A-1");
+        let estimate = warp_estimate(&ship, &system("X1-A", 0, 0), &system("X1-B", 18, 24));
+        assert_eq!(estimate.distance, 30.0);
+        assert_eq!(estimate.fuel, 30);
+        // 30 units at speed 10, plus the navigation overhead
+        assert_eq!(estimate.seconds, 165);
+    }
+
+    #[test]
+    fn warp_needs_a_drive_range_and_fuel() {
+        let catalogue = catalogue();
+        let ship = ship_at("X1-A-1");
+        assert!(!can_warp_to(&ship, "X1-B", &catalogue));
+
+        let ship = with_warp_drive(ship_at("X1-A-1"), 100, 100);
+        assert!(can_warp_to(&ship, "X1-B", &catalogue));
+        // Out of range
+        assert!(!can_warp_to(&ship, "X1-C", &catalogue));
+        // Not in the catalogue
+        assert!(!can_warp_to(&ship, "X1-Z", &catalogue));
+
+        let ship = with_warp_drive(ship_at("X1-A-1"), 100, 20);
+        assert!(!can_warp_to(&ship, "X1-B", &catalogue));
+    }
+
+    #[test]
+    fn lists_warp_targets_in_range() {
+        let ship = with_warp_drive(ship_at("X1-A-1"), 100, 100);
+        assert_eq!(warp_targets(&ship, &catalogue()), vec!["X1-B".to_owned()]);
+    }
+
+    #[test]
+    fn plans_a_warp_without_a_gate_connection() {
+        let ship = with_warp_drive(ship_at("X1-A-1"), 100, 100);
+        let steps = plan_route(&ship, "X1-B", &[], &[], &catalogue());
+        assert_eq!(steps, Some(vec![RouteStep::Warp("X1-B-GATE".to_owned())]));
+
+        let waypoints = vec![waypoint("X1-B-1")];
+        let steps = plan_route(&ship, "X1-B-1", &waypoints, &[], &catalogue());
+        assert_eq!(steps, Some(vec![RouteStep::Warp("X1-B-1".to_owned())]));
+    }
+
+    #[test]
+    fn finds_shortest_gate_path() {
+        let path = find_system_path("X1-A", "X1-D", &gates());
+        assert_eq!(
+            path,
+            Some(vec![
+                "X1-A".to_owned(),
+                "X1-B".to_owned(),
+                "X1-C".to_owned(),
+                "X1-D".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn path_to_own_system_is_just_that_system() {
+        assert_eq!(
+            find_system_path("X1-A", "X1-A", &gates()),
+            Some(vec!["X1-A".to_owned()])
+        );
+    }
+
+    #[test]
+    fn no_path_without_a_connection() {
+        assert_eq!(find_system_path("X1-D", "X1-A", &gates()), None);
+        assert_eq!(find_system_path("X1-A", "X1-Z", &gates()), None);
+    }
+
+    #[test]
+    fn plans_navigation_within_a_system() {
+        let ship = ship_at("X1-A-1");
+        let waypoints = vec![waypoint("X1-A-1"), waypoint("X1-A-2")];
+        let steps = plan_route(
+            &ship,
+            "X1-A-2",
+            &waypoints,
+            &gates(),
+            &SystemCatalogue::default(),
+        );
+        assert_eq!(steps, Some(vec![RouteStep::Navigate("X1-A-2".to_owned())]));
+    }
+
+    #[test]
+    fn plans_jumps_through_known_gates() {
+        let ship = ship_at("X1-A-1");
+        let waypoints = vec![waypoint("X1-A-1"), waypoint("X1-C-7")];
+        let steps = plan_route(
+            &ship,
+            "X1-C-7",
+            &waypoints,
+            &gates(),
+            &SystemCatalogue::default(),
+        );
+        assert_eq!(
+            steps,
+            Some(vec![
+                RouteStep::Navigate("X1-A-GATE".to_owned()),
+                RouteStep::Jump("X1-B".to_owned()),
+                RouteStep::Jump("X1-C".to_owned()),
+                RouteStep::Navigate("X1-C-7".to_owned()),
+            ])
+        );
+    }
+
+    #[test]
+    fn plans_jumps_to_a_system_from_the_gate() {
+        let ship = ship_at("X1-A-GATE");
+        let steps = plan_route(&ship, "X1-B", &[], &gates(), &SystemCatalogue::default());
+        assert_eq!(steps, Some(vec![RouteStep::Jump("X1-B".to_owned())]));
+    }
+
+    #[test]
+    fn no_route_without_gates_or_warp_drive() {
+        let ship = ship_at("X1-D-1");
+        let steps = plan_route(&ship, "X1-A", &[], &gates(), &SystemCatalogue::default());
+        assert_eq!(steps, None);
+    }
+}
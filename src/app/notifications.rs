@@ -0,0 +1,519 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use egui::{Context, Ui};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::contracts::{format_countdown, seconds_until};
+use crate::events::Event;
+use crate::history::now;
+use crate::AppData;
+
+// Rules turn events and changes in the fleet into notifications. Each one is kept in the
+// history and shown as a toast for a few seconds, and a rule can also raise a desktop
+// notification, with a sound if wanted, for when nobody is watching the window. Turning
+// a rule off only silences it, what it found still goes in the history
+
+const TOAST_SECONDS: i64 = 8;
+const MAX_TOASTS: usize = 5;
+const MAX_HISTORY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, serde::Deserialize, serde::Serialize)]
+pub enum RuleKind {
+    ShipArrived,
+    CargoFull,
+    ContractDeadline,
+    CreditsThreshold,
+    AutomationError,
+}
+
+impl RuleKind {
+    fn name(&self) -> &'static str {
+        match self {
+            RuleKind::ShipArrived => "Ship arrived",
+            RuleKind::CargoFull => "Cargo full",
+            RuleKind::ContractDeadline => "Contract deadline",
+            RuleKind::CreditsThreshold => "Credits threshold",
+            RuleKind::AutomationError => "Automation error",
+        }
+    }
+
+    /// What a rule's threshold means, for rules that have one
+    fn threshold_unit(&self) -> Option<&'static str> {
+        match self {
+            RuleKind::ContractDeadline => Some("hours before"),
+            RuleKind::CreditsThreshold => Some("credits"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Rule {
+    pub kind: RuleKind,
+    pub enabled: bool,
+    pub desktop: bool,
+    pub sound: bool,
+    pub threshold: i64,
+}
+
+impl Rule {
+    fn new(kind: RuleKind) -> Self {
+        Rule {
+            kind,
+            enabled: true,
+            desktop: matches!(kind, RuleKind::ContractDeadline | RuleKind::AutomationError),
+            sound: false,
+            threshold: match kind {
+                RuleKind::ContractDeadline => 24,
+                RuleKind::CreditsThreshold => 1_000_000,
+                _ => 0,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Notification {
+    pub time: i64,
+    pub kind: RuleKind,
+    pub text: String,
+    #[serde(skip)]
+    pub dismissed: bool,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Notifications {
+    pub rules: Vec<Rule>,
+    /// Oldest first
+    pub history: Vec<Notification>,
+    /// Last event from the event log we've looked at
+    #[serde(skip)]
+    last_event_id: u64,
+    #[serde(skip)]
+    full_ships: HashSet<String>,
+    #[serde(skip)]
+    warned_contracts: HashSet<String>,
+    /// Whether credits were at or over the threshold last time we looked
+    #[serde(skip)]
+    above_threshold: Option<bool>,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            rules: RuleKind::iter().map(Rule::new).collect(),
+            history: vec![],
+            last_event_id: 0,
+            full_ships: HashSet::new(),
+            warned_contracts: HashSet::new(),
+            above_threshold: None,
+        }
+    }
+}
+
+impl Notifications {
+    /// The rule for a kind, falling back to the default for kinds added since the save
+    fn rule(&self, kind: RuleKind) -> Rule {
+        self.rules
+            .iter()
+            .find(|r| r.kind == kind)
+            .cloned()
+            .unwrap_or_else(|| Rule::new(kind))
+    }
+
+    /// Everything worth telling the user about since the last check, muted or not.
+    /// Conditions are remembered so each one is only reported when it starts
+    fn check(&mut self, data: &AppData) -> Vec<(RuleKind, String)> {
+        let mut found = vec![];
+
+        for record in data.events.since(self.last_event_id) {
+            self.last_event_id = record.id;
+            match record.event {
+                Event::ShipArrived { ship, waypoint } => found.push((
+                    RuleKind::ShipArrived,
+                    format!("{} arrived at {}", ship, waypoint),
+                )),
+                Event::Error { message } => found.push((RuleKind::AutomationError, message)),
+                _ => {}
+            }
+        }
+
+        for s in &data.ships {
+            let cargo = &s.ship.cargo;
+            if cargo.capacity > 0 && cargo.units >= cargo.capacity {
+                if self.full_ships.insert(s.ship.symbol.clone()) {
+                    found.push((
+                        RuleKind::CargoFull,
+                        format!("{}'s cargo hold is full", s.ship.symbol),
+                    ));
+                }
+            } else {
+                self.full_ships.remove(&s.ship.symbol);
+            }
+        }
+
+        let warning_seconds = self.rule(RuleKind::ContractDeadline).threshold * 3600;
+        for c in data.contracts.iter().filter(|c| c.accepted && !c.fulfilled) {
+            let remaining = match seconds_until(&c.terms.deadline) {
+                Some(r) => r,
+                None => continue,
+            };
+            if remaining <= warning_seconds && self.warned_contracts.insert(c.id.clone()) {
+                let text = if remaining > 0 {
+                    format!(
+                        "Contract {} is due in {}",
+                        c.id,
+                        format_countdown(remaining)
+                    )
+                } else {
+                    format!("Contract {} is past its deadline", c.id)
+                };
+                found.push((RuleKind::ContractDeadline, text));
+            }
+        }
+
+        if let Some(agent) = &data.agent {
+            let threshold = self.rule(RuleKind::CreditsThreshold).threshold;
+            let above = agent.credits as i64 >= threshold;
+            if self.above_threshold.map_or(false, |was| was != above) {
+                let text = if above {
+                    format!("Credits reached {} ({})", threshold, agent.credits)
+                } else {
+                    format!("Credits fell below {} ({})", threshold, agent.credits)
+                };
+                found.push((RuleKind::CreditsThreshold, text));
+            }
+            self.above_threshold = Some(above);
+        }
+
+        found
+    }
+
+    /// Records a notification and, unless its rule is muted, shows it as a toast and on
+    /// the desktop if the rule asks for that
+    fn raise(&mut self, kind: RuleKind, text: String) -> Result<(), String> {
+        let rule = self.rule(kind);
+        self.history.push(Notification {
+            time: now(),
+            kind,
+            text: text.clone(),
+            // Muted ones never show as a toast
+            dismissed: !rule.enabled,
+        });
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if rule.enabled && rule.desktop {
+            show_desktop(kind, &text, rule.sound)?;
+        }
+        Ok(())
+    }
+
+    /// Recent notifications in the bottom right corner until they expire or are closed
+    pub fn render_toasts(&mut self, ctx: &Context) {
+        let time = now();
+        let mut showing = false;
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+            .show(ctx, |ui| {
+                for n in self
+                    .history
+                    .iter_mut()
+                    .rev()
+                    .filter(|n| !n.dismissed && time - n.time < TOAST_SECONDS)
+                    .take(MAX_TOASTS)
+                {
+                    showing = true;
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.strong(n.kind.name());
+                            ui.label(&n.text);
+                            if ui.small_button("x").clicked() {
+                                n.dismissed = true;
+                            }
+                        });
+                    });
+                }
+            });
+        // Keep drawing so toasts go away on time
+        if showing {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut Ui) {
+        egui::Grid::new("notification_rules").show(ui, |ui| {
+            ui.label("Rule");
+            ui.label("On");
+            ui.label("Desktop");
+            ui.label("Sound");
+            ui.label("Threshold");
+            ui.end_row();
+
+            for kind in RuleKind::iter() {
+                if !self.rules.iter().any(|r| r.kind == kind) {
+                    self.rules.push(Rule::new(kind));
+                }
+                let rule = self.rules.iter_mut().find(|r| r.kind == kind).unwrap();
+                ui.label(kind.name());
+                ui.checkbox(&mut rule.enabled, "");
+                ui.add_enabled(rule.enabled, egui::Checkbox::new(&mut rule.desktop, ""));
+                ui.add_enabled(
+                    rule.enabled && rule.desktop,
+                    egui::Checkbox::new(&mut rule.sound, ""),
+                );
+                match kind.threshold_unit() {
+                    Some(unit) => {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut rule.threshold).clamp_range(0..=i64::MAX),
+                            );
+                            ui.label(unit);
+                        });
+                    }
+                    None => {
+                        ui.label("");
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if cfg!(target_arch = "wasm32") {
+            ui.label("Desktop notifications are only shown by the desktop app");
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("History");
+            if ui.button("Clear").clicked() {
+                self.history.clear();
+            }
+        });
+        let time = now();
+        for n in self.history.iter().rev() {
+            ui.label(format!(
+                "{} ago, {}: {}",
+                format_countdown((time - n.time).max(1)),
+                n.kind.name(),
+                n.text
+            ));
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn show_desktop(kind: RuleKind, text: &str, sound: bool) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    const SOUND_NAME: &str = "Ping";
+    #[cfg(target_os = "windows")]
+    const SOUND_NAME: &str = "Default";
+    #[cfg(all(unix, not(target_os = "macos")))]
+    const SOUND_NAME: &str = "message-new-instant";
+
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .appname("Cyan Fleet Control")
+        .summary(kind.name())
+        .body(text);
+    if sound {
+        notification.sound_name(SOUND_NAME);
+    }
+    notification.show().map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Checks the rules against what's happened since last time
+pub fn tick(data: &mut AppData) {
+    let mut notifications = std::mem::take(&mut data.notifications);
+    for (kind, text) in notifications.check(data) {
+        if let Err(e) = notifications.raise(kind, text) {
+            // Not an automation error, or a broken notifier would report itself forever
+            data.log.push(format!("Desktop notification failed: {}", e));
+        }
+    }
+    data.notifications = notifications;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spacedust::models::*;
+
+    use crate::spacetraders::ShipWithNav;
+
+    /// Rules as they come, but never on the desktop of whoever runs the tests
+    fn quiet() -> Notifications {
+        let mut notifications = Notifications::default();
+        for rule in &mut notifications.rules {
+            rule.desktop = false;
+        }
+        notifications
+    }
+
+    fn kinds(found: &[(RuleKind, String)]) -> Vec<RuleKind> {
+        found.iter().map(|(kind, _)| *kind).collect()
+    }
+
+    fn ship(symbol: &str, units: i32) -> ShipWithNav {
+        let mut ship = Ship::default();
+        ship.symbol = symbol.to_owned();
+        ship.cargo.capacity = 30;
+        ship.cargo.units = units;
+        ShipWithNav {
+            ship,
+            destination: String::new(),
+            route_target: String::new(),
+            actions: vec![],
+        }
+    }
+
+    fn contract(id: &str, hours_left: i64) -> Contract {
+        let deadline = chrono::Utc::now() + chrono::Duration::hours(hours_left);
+        Contract {
+            id: id.to_owned(),
+            accepted: true,
+            terms: Box::new(ContractTerms {
+                deadline: deadline.to_rfc3339(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn with_credits(data: &mut AppData, credits: i64) {
+        data.agent = Some(Agent {
+            credits,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn notifies_arrivals_and_errors_once() {
+        let mut data = AppData::default();
+        let mut notifications = quiet();
+        data.emit(Event::ShipArrived {
+            ship: "SHIP-1".to_owned(),
+            waypoint: "X1-A-1".to_owned(),
+        });
+        data.emit(Event::Extracted {
+            ship: "SHIP-1".to_owned(),
+            trade_symbol: "IRON_ORE".to_owned(),
+            units: 5,
+        });
+        data.report_error("SHIP-1: no fuel".to_owned());
+
+        let found = notifications.check(&data);
+        assert_eq!(
+            kinds(&found),
+            vec![RuleKind::ShipArrived, RuleKind::AutomationError]
+        );
+        assert_eq!(found[1].1, "SHIP-1: no fuel");
+        assert!(notifications.check(&data).is_empty());
+    }
+
+    #[test]
+    fn notifies_a_full_hold_each_time_it_fills() {
+        let mut data = AppData::default();
+        let mut notifications = quiet();
+        data.ships = vec![ship("SHIP-1", 30), ship("SHIP-2", 10)];
+        assert_eq!(
+            kinds(&notifications.check(&data)),
+            vec![RuleKind::CargoFull]
+        );
+        assert!(notifications.check(&data).is_empty());
+
+        data.ships[0].ship.cargo.units = 0;
+        assert!(notifications.check(&data).is_empty());
+        data.ships[0].ship.cargo.units = 30;
+        assert_eq!(
+            kinds(&notifications.check(&data)),
+            vec![RuleKind::CargoFull]
+        );
+    }
+
+    #[test]
+    fn warns_once_about_contracts_near_or_past_their_deadline() {
+        let mut data = AppData::default();
+        let mut notifications = quiet();
+        let mut unaccepted = contract("UNACCEPTED", 1);
+        unaccepted.accepted = false;
+        data.contracts = vec![
+            contract("SOON", 2),
+            contract("LATER", 48),
+            contract("LATE", -1),
+            unaccepted,
+        ];
+
+        let found = notifications.check(&data);
+        assert_eq!(
+            kinds(&found),
+            vec![RuleKind::ContractDeadline, RuleKind::ContractDeadline]
+        );
+        assert!(found[0].1.starts_with("Contract SOON is due in "));
+        assert_eq!(found[1].1, "Contract LATE is past its deadline");
+        assert!(notifications.check(&data).is_empty());
+    }
+
+    #[test]
+    fn notifies_only_when_credits_cross_the_threshold() {
+        let mut data = AppData::default();
+        let mut notifications = quiet();
+        with_credits(&mut data, 2_000_000);
+        // Nothing to compare with yet
+        assert!(notifications.check(&data).is_empty());
+        assert!(notifications.check(&data).is_empty());
+
+        with_credits(&mut data, 500_000);
+        let found = notifications.check(&data);
+        assert_eq!(kinds(&found), vec![RuleKind::CreditsThreshold]);
+        assert!(found[0].1.starts_with("Credits fell below"));
+        with_credits(&mut data, 600_000);
+        assert!(notifications.check(&data).is_empty());
+
+        with_credits(&mut data, 1_000_000);
+        let found = notifications.check(&data);
+        assert!(found[0].1.starts_with("Credits reached"));
+    }
+
+    #[test]
+    fn muted_rules_still_go_in_the_history() {
+        let mut data = AppData::default();
+        data.notifications = quiet();
+        for rule in &mut data.notifications.rules {
+            rule.enabled = rule.kind != RuleKind::CargoFull;
+        }
+        data.ships = vec![ship("SHIP-1", 30)];
+        data.emit(Event::ShipArrived {
+            ship: "SHIP-1".to_owned(),
+            waypoint: "X1-A-1".to_owned(),
+        });
+
+        tick(&mut data);
+        let history: Vec<(RuleKind, bool)> = data
+            .notifications
+            .history
+            .iter()
+            .map(|n| (n.kind, n.dismissed))
+            .collect();
+        assert_eq!(
+            history,
+            vec![(RuleKind::ShipArrived, false), (RuleKind::CargoFull, true)]
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_latest_history() {
+        let mut notifications = quiet();
+        for i in 0..MAX_HISTORY + 5 {
+            notifications
+                .raise(RuleKind::ShipArrived, i.to_string())
+                .unwrap();
+        }
+        assert_eq!(notifications.history.len(), MAX_HISTORY);
+        assert_eq!(notifications.history[0].text, "5");
+    }
+}
@@ -0,0 +1,185 @@
+use eframe::Storage;
+use egui::{Color32, Ui};
+
+#[cfg(target_arch = "wasm32")]
+use crate::config::Config;
+use crate::AppData;
+
+// Saves are JSON with a version number, so an older save can be upgraded step by step
+// instead of failing to parse. Saves from before versioning are the whole AppState in
+// eframe's own format, under its app key, and count as version 0
+
+const SAVE_KEY: &str = "cyan_fleet_control";
+
+/// There's no config file in the browser, so settings are saved alongside everything else
+#[cfg(target_arch = "wasm32")]
+const CONFIG_KEY: &str = "cyan_fleet_control_config";
+
+/// Bump this when a change to AppData would stop older saves from loading, and add
+/// the step that upgrades them to UPGRADES
+pub const SAVE_VERSION: u32 = 1;
+
+/// UPGRADES[i] takes saved data from version i + 1 to i + 2
+const UPGRADES: &[fn(&mut serde_json::Value)] = &[];
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SavedState {
+    version: u32,
+    data: serde_json::Value,
+}
+
+/// The unversioned format, written by serializing AppState directly
+#[derive(serde::Deserialize)]
+struct LegacyState {
+    data: AppData,
+}
+
+pub fn save(storage: &mut dyn Storage, data: &AppData) -> Result<(), String> {
+    let saved = SavedState {
+        version: SAVE_VERSION,
+        data: serde_json::to_value(data).map_err(|e| e.to_string())?,
+    };
+    let json = serde_json::to_string(&saved).map_err(|e| e.to_string())?;
+    storage.set_string(SAVE_KEY, json);
+
+    #[cfg(target_arch = "wasm32")]
+    storage.set_string(
+        CONFIG_KEY,
+        toml::to_string(data.config()).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+/// The settings saved in the browser, if any
+#[cfg(target_arch = "wasm32")]
+pub fn load_config(storage: &dyn Storage) -> Result<Option<Config>, String> {
+    match storage.get_string(CONFIG_KEY) {
+        Some(text) => toml::from_str(&text).map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// The last saved AppData, upgraded to the current version, or None if nothing was saved
+pub fn load(storage: &dyn Storage) -> Result<Option<AppData>, String> {
+    let json = match storage.get_string(SAVE_KEY) {
+        Some(j) => j,
+        None => {
+            return Ok(eframe::get_value::<LegacyState>(storage, eframe::APP_KEY).map(|s| s.data))
+        }
+    };
+
+    let mut saved: SavedState = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+    if saved.version > SAVE_VERSION {
+        return Err(format!(
+            "save is from a newer version ({}, this build reads up to {})",
+            saved.version, SAVE_VERSION
+        ));
+    }
+    upgrade(&mut saved.data, saved.version, UPGRADES);
+    serde_json::from_value(saved.data)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Runs the upgrades that saved data from `version` hasn't had yet
+fn upgrade(data: &mut serde_json::Value, version: u32, upgrades: &[fn(&mut serde_json::Value)]) {
+    for upgrade in upgrades.iter().skip(version.saturating_sub(1) as usize) {
+        upgrade(data);
+    }
+}
+
+/// Marks a window's contents as restored from the last session
+pub fn stale_label(ui: &mut Ui, stale: bool) {
+    if stale {
+        ui.colored_label(
+            Color32::GRAY,
+            "Restored from last session, waiting for a refresh",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryStorage(HashMap<String, String>);
+
+    impl Storage for MemoryStorage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_owned(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    fn with_log(line: &str) -> AppData {
+        let mut data = AppData::default();
+        data.log.push(line.to_owned());
+        data
+    }
+
+    #[test]
+    fn nothing_saved() {
+        assert!(load(&MemoryStorage::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn loads_what_was_saved() {
+        let mut storage = MemoryStorage::default();
+        save(&mut storage, &with_log("saved")).unwrap();
+        let data = load(&storage).unwrap().unwrap();
+        assert_eq!(data.log, vec!["saved"]);
+    }
+
+    #[test]
+    fn loads_a_save_from_before_versioning() {
+        #[derive(serde::Serialize)]
+        struct Legacy {
+            data: AppData,
+        }
+        let mut storage = MemoryStorage::default();
+        let legacy = Legacy {
+            data: with_log("legacy"),
+        };
+        eframe::set_value(&mut storage, eframe::APP_KEY, &legacy);
+        let data = load(&storage).unwrap().unwrap();
+        assert_eq!(data.log, vec!["legacy"]);
+    }
+
+    #[test]
+    fn refuses_a_save_from_a_newer_version() {
+        let mut storage = MemoryStorage::default();
+        let saved = json!({ "version": SAVE_VERSION + 1, "data": {} });
+        storage.set_string(SAVE_KEY, saved.to_string());
+        assert!(load(&storage).is_err());
+    }
+
+    #[test]
+    fn upgrades_from_the_saved_version_on() {
+        let upgrades: &[fn(&mut serde_json::Value)] = &[
+            |data| data["steps"].as_array_mut().unwrap().push(json!(2)),
+            |data| data["steps"].as_array_mut().unwrap().push(json!(3)),
+        ];
+
+        let mut data = json!({ "steps": [1] });
+        upgrade(&mut data, 1, upgrades);
+        assert_eq!(data["steps"], json!([1, 2, 3]));
+
+        let mut data = json!({ "steps": [2] });
+        upgrade(&mut data, 2, upgrades);
+        assert_eq!(data["steps"], json!([2, 3]));
+
+        let mut data = json!({ "steps": [3] });
+        upgrade(&mut data, 3, upgrades);
+        assert_eq!(data["steps"], json!([3]));
+    }
+}
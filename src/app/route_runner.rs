@@ -0,0 +1,186 @@
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::fleet_ops;
+use crate::history::now;
+use crate::navigation::{system_of, RouteStep};
+use crate::AppData;
+
+/// Rounds in a row a route's next step can fail before the route is given up, so a step
+/// that can never succeed (no fuel, an unknown gate) doesn't report an error forever
+const MAX_FAILURES: u32 = 3;
+
+/// A multi-step trip a ship is working through, possibly across systems
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ShipRoute {
+    pub ship_symbol: String,
+    pub destination: String,
+    pub steps: Vec<RouteStep>,
+    #[serde(skip)]
+    pub cooldown_until: i64,
+    /// Rounds in a row the next step has failed
+    #[serde(skip)]
+    pub failures: u32,
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Routes {
+    pub routes: Vec<ShipRoute>,
+}
+
+impl Routes {
+    pub fn start(&mut self, ship_symbol: &str, destination: &str, steps: Vec<RouteStep>) {
+        self.cancel(ship_symbol);
+        self.routes.push(ShipRoute {
+            ship_symbol: ship_symbol.to_owned(),
+            destination: destination.to_owned(),
+            steps,
+            cooldown_until: 0,
+            failures: 0,
+        });
+    }
+
+    pub fn cancel(&mut self, ship_symbol: &str) {
+        self.routes.retain(|r| r.ship_symbol != ship_symbol);
+    }
+
+    pub fn route_for(&self, ship_symbol: &str) -> Option<&ShipRoute> {
+        self.routes.iter().find(|r| r.ship_symbol == ship_symbol)
+    }
+
+    /// Systems that routes are heading into, so their waypoints get fetched
+    pub fn target_systems(&self) -> Vec<String> {
+        self.routes
+            .iter()
+            .flat_map(|r| r.steps.iter())
+            .filter_map(|s| match s {
+                RouteStep::Jump(system) => Some(system.clone()),
+                RouteStep::Warp(w) => Some(system_of(w).to_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl ShipRoute {
+    pub fn render(&self, ui: &mut Ui) {
+        ui.label(format!("Route to {}:", self.destination));
+        for step in &self.steps {
+            match step {
+                RouteStep::Navigate(w) => ui.label(format!("\tNavigate to {}", w)),
+                RouteStep::Jump(s) => ui.label(format!("\tJump to {}", s)),
+                RouteStep::Warp(w) => ui.label(format!("\tWarp to {}", w)),
+            };
+        }
+    }
+}
+
+fn step_done(step: &RouteStep, ship: &Ship) -> bool {
+    match step {
+        RouteStep::Navigate(w) => &ship.nav.waypoint_symbol == w,
+        RouteStep::Jump(s) => &ship.nav.system_symbol == s,
+        RouteStep::Warp(w) => &ship.nav.waypoint_symbol == w,
+    }
+}
+
+/// Advances one route by a single action. Returns a cooldown, if the action started one
+fn step_route(data: &mut AppData, route: &mut ShipRoute) -> Result<Option<i64>, String> {
+    let ship = match data
+        .ships
+        .iter()
+        .find(|s| s.ship.symbol == route.ship_symbol)
+    {
+        Some(s) => s.ship.clone(),
+        None => return Ok(None),
+    };
+
+    if let ShipNavStatus::InTransit = ship.nav.status {
+        return Ok(None);
+    }
+
+    while route.steps.first().map(|s| step_done(s, &ship)) == Some(true) {
+        route.steps.remove(0);
+    }
+
+    let step = match route.steps.first() {
+        Some(s) => s.clone(),
+        None => return Ok(None),
+    };
+
+    if let ShipNavStatus::Docked = ship.nav.status {
+        fleet_ops::orbit(data, &ship.symbol)?;
+    }
+
+    match step {
+        RouteStep::Navigate(w) => {
+            fleet_ops::navigate(data, &ship.symbol, &w)?;
+            Ok(None)
+        }
+        RouteStep::Jump(system) => {
+            let cooldown = fleet_ops::jump(data, &ship.symbol, &system)?;
+            // Jumps are instant, a warp's step is done once the ship drops out
+            route.steps.remove(0);
+            fetch_if_unknown(data, &system);
+            Ok(Some(cooldown))
+        }
+        RouteStep::Warp(w) => {
+            fleet_ops::warp(data, &ship.symbol, &w)?;
+            fetch_if_unknown(data, system_of(&w));
+            Ok(None)
+        }
+    }
+}
+
+/// Loads the waypoints of a system we've just arrived in. A failure is logged by
+/// fleet_ops and doesn't hold up the route, the next waypoint fetch fills them in
+fn fetch_if_unknown(data: &mut AppData, system: &str) {
+    if !data.waypoints.iter().any(|w| w.system_symbol == system) {
+        let _ = fleet_ops::fetch_system(data, system);
+    }
+}
+
+/// Runs one round of route following for every ship with a route
+pub fn tick(data: &mut AppData) {
+    let time = now();
+    let mut routes = std::mem::take(&mut data.routes.routes);
+
+    for route in routes.iter_mut() {
+        if route.cooldown_until > time {
+            continue;
+        }
+
+        match step_route(data, route) {
+            Ok(cooldown) => {
+                route.failures = 0;
+                if let Some(cooldown) = cooldown {
+                    route.cooldown_until = time + cooldown;
+                }
+            }
+            Err(e) => {
+                route.failures += 1;
+                data.report_error(e);
+            }
+        }
+    }
+
+    routes.retain(|r| {
+        if r.failures >= MAX_FAILURES {
+            data.report_error(format!(
+                "{}: gave up the route to {} after its next step failed {} times in a row",
+                r.ship_symbol, r.destination, r.failures
+            ));
+            return false;
+        }
+        if r.steps.len() == 0 {
+            data.log
+                .push(format!("{}: arrived at {}", r.ship_symbol, r.destination));
+            return false;
+        }
+        true
+    });
+
+    // Routes started from the UI while we were busy go after ours
+    routes.append(&mut data.routes.routes);
+    data.routes.routes = routes;
+}
@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use egui::Ui;
+use rhai::{Dynamic, Engine, Scope, AST, INT};
+
+use crate::fleet_ops;
+use crate::history::now;
+use crate::AppData;
+
+/// Folder scripts are loaded from, relative to the working directory
+pub const SCRIPTS_DIR: &str = "scripts";
+/// Stops a runaway script from holding up the message handler
+const MAX_OPERATIONS: u64 = 100_000;
+/// Lines of output kept per script
+const MAX_LOG_LINES: usize = 200;
+
+/// A fleet operation requested by a script, carried out once the script returns
+#[derive(Debug, Clone)]
+enum ScriptCommand {
+    Navigate(String, String),
+    Dock(String),
+    Orbit(String),
+    Extract(String),
+    Sell(String, String, i32),
+    Refuel(String),
+    Deliver(String, String, String, i32),
+    Transfer(String, String, String, i32),
+}
+
+#[derive(Debug)]
+pub struct Script {
+    pub name: String,
+    modified: Option<SystemTime>,
+    ast: Option<AST>,
+    pub error: Option<String>,
+}
+
+/// Runs a script on a ship every round
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ScriptAssignment {
+    pub ship_symbol: String,
+    pub script: String,
+    #[serde(skip)]
+    pub cooldown_until: i64,
+}
+
+/// The engine plus the buffers its registered functions write into
+struct ScriptEngine {
+    engine: Engine,
+    commands: Arc<Mutex<Vec<ScriptCommand>>>,
+    output: Arc<Mutex<Vec<String>>>,
+}
+
+impl std::fmt::Debug for ScriptEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScriptEngine").finish()
+    }
+}
+
+impl ScriptEngine {
+    fn new() -> Self {
+        let commands: Arc<Mutex<Vec<ScriptCommand>>> = Arc::new(Mutex::new(vec![]));
+        let output: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(vec![]));
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let out = output.clone();
+        engine.on_print(move |s| out.lock().unwrap().push(s.to_owned()));
+        let out = output.clone();
+        engine.on_debug(move |s, _, _| out.lock().unwrap().push(s.to_owned()));
+
+        let c = commands.clone();
+        engine.register_fn("navigate", move |ship: &str, waypoint: &str| {
+            c.lock().unwrap().push(ScriptCommand::Navigate(
+                ship.to_owned(),
+                waypoint.to_owned(),
+            ))
+        });
+        let c = commands.clone();
+        engine.register_fn("dock", move |ship: &str| {
+            c.lock().unwrap().push(ScriptCommand::Dock(ship.to_owned()))
+        });
+        let c = commands.clone();
+        engine.register_fn("orbit", move |ship: &str| {
+            c.lock()
+                .unwrap()
+                .push(ScriptCommand::Orbit(ship.to_owned()))
+        });
+        let c = commands.clone();
+        engine.register_fn("extract", move |ship: &str| {
+            c.lock()
+                .unwrap()
+                .push(ScriptCommand::Extract(ship.to_owned()))
+        });
+        let c = commands.clone();
+        engine.register_fn("sell", move |ship: &str, trade: &str, units: INT| {
+            c.lock().unwrap().push(ScriptCommand::Sell(
+                ship.to_owned(),
+                trade.to_owned(),
+                units as i32,
+            ))
+        });
+        let c = commands.clone();
+        engine.register_fn("refuel", move |ship: &str| {
+            c.lock()
+                .unwrap()
+                .push(ScriptCommand::Refuel(ship.to_owned()))
+        });
+        let c = commands.clone();
+        engine.register_fn(
+            "deliver",
+            move |ship: &str, contract: &str, trade: &str, units: INT| {
+                c.lock().unwrap().push(ScriptCommand::Deliver(
+                    ship.to_owned(),
+                    contract.to_owned(),
+                    trade.to_owned(),
+                    units as i32,
+                ))
+            },
+        );
+        let c = commands.clone();
+        engine.register_fn(
+            "transfer",
+            move |ship: &str, to: &str, trade: &str, units: INT| {
+                c.lock().unwrap().push(ScriptCommand::Transfer(
+                    ship.to_owned(),
+                    to.to_owned(),
+                    trade.to_owned(),
+                    units as i32,
+                ))
+            },
+        );
+
+        Self {
+            engine,
+            commands,
+            output,
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ScriptRunner {
+    pub assignments: Vec<ScriptAssignment>,
+    #[serde(skip)]
+    pub scripts: Vec<Script>,
+    /// Output and errors per script, newest last
+    #[serde(skip)]
+    pub logs: HashMap<String, Vec<String>>,
+    #[serde(skip)]
+    engine: Option<ScriptEngine>,
+}
+
+impl ScriptRunner {
+    pub fn script_for(&self, ship_symbol: &str) -> Option<&str> {
+        self.assignments
+            .iter()
+            .find(|a| a.ship_symbol == ship_symbol)
+            .map(|a| a.script.as_str())
+    }
+
+    /// Attaches a script to a ship, or detaches it with None
+    pub fn assign(&mut self, ship_symbol: &str, script: Option<&str>) {
+        self.assignments.retain(|a| a.ship_symbol != ship_symbol);
+        if let Some(script) = script {
+            self.assignments.push(ScriptAssignment {
+                ship_symbol: ship_symbol.to_owned(),
+                script: script.to_owned(),
+                cooldown_until: 0,
+            });
+        }
+    }
+
+    fn log(&mut self, script: &str, line: String) {
+        let log = self.logs.entry(script.to_owned()).or_default();
+        log.push(line);
+        if log.len() > MAX_LOG_LINES {
+            log.remove(0);
+        }
+    }
+
+    /// Compiles any script in the folder that's new or changed since we last looked,
+    /// and forgets scripts that have been deleted
+    pub fn reload(&mut self) {
+        self.reload_from(Path::new(SCRIPTS_DIR));
+    }
+
+    fn reload_from(&mut self, dir: &Path) {
+        let engine = self.engine.get_or_insert_with(ScriptEngine::new);
+
+        let entries = match std::fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+
+        let mut seen: Vec<String> = vec![];
+        let mut messages: Vec<(String, String)> = vec![];
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map(|e| e == "rhai") != Some(true) {
+                continue;
+            }
+            let name = match path.file_stem() {
+                Some(n) => n.to_string_lossy().to_string(),
+                None => continue,
+            };
+            let modified = entry.metadata().and_then(|m| m.modified()).ok();
+            seen.push(name.clone());
+
+            let i = match self.scripts.iter().position(|s| s.name == name) {
+                Some(i) if self.scripts[i].modified == modified => continue,
+                Some(i) => i,
+                None => {
+                    self.scripts.push(Script {
+                        name: name.clone(),
+                        modified: None,
+                        ast: None,
+                        error: None,
+                    });
+                    self.scripts.len() - 1
+                }
+            };
+
+            let script = &mut self.scripts[i];
+            script.modified = modified;
+            let compiled = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| engine.engine.compile(source).map_err(|e| e.to_string()));
+            match compiled {
+                Ok(ast) => {
+                    script.ast = Some(ast);
+                    script.error = None;
+                    messages.push((name, "Loaded".to_owned()));
+                }
+                Err(e) => {
+                    script.ast = None;
+                    script.error = Some(e.clone());
+                    messages.push((name, format!("Failed to compile: {}", e)));
+                }
+            }
+        }
+
+        self.scripts.retain(|s| seen.contains(&s.name));
+        for (name, message) in messages {
+            self.log(&name, message);
+        }
+    }
+
+    /// `busy` holds ships something else is driving, see `fleet_table::automated_ships`
+    pub fn render(&mut self, ui: &mut Ui, ship_symbols: &[String], busy: &HashMap<String, String>) {
+        if ui.button("Reload").clicked() {
+            self.reload();
+        }
+        if self.scripts.len() == 0 {
+            ui.label(format!("No scripts found in {}/", SCRIPTS_DIR));
+        }
+
+        for ship_symbol in ship_symbols {
+            if let Some(automation) = busy.get(ship_symbol) {
+                ui.label(format!("{}: {}", ship_symbol, automation));
+                continue;
+            }
+            let current = self.script_for(ship_symbol).map(|s| s.to_owned());
+            let mut selected = current.clone();
+            egui::ComboBox::from_label(ship_symbol)
+                .selected_text(selected.clone().unwrap_or("None".to_owned()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut selected, None, "None");
+                    for script in &self.scripts {
+                        ui.selectable_value(&mut selected, Some(script.name.clone()), &script.name);
+                    }
+                });
+            if selected != current {
+                self.assign(ship_symbol, selected.as_deref());
+            }
+        }
+
+        ui.separator();
+        for script in &self.scripts {
+            egui::CollapsingHeader::new(&script.name)
+                .id_source(format!("script_{}", script.name))
+                .show(ui, |ui| {
+                    if let Some(e) = &script.error {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                    if let Some(log) = self.logs.get(&script.name) {
+                        for line in log {
+                            ui.label(line);
+                        }
+                    }
+                });
+        }
+    }
+}
+
+/// The parts of AppData a script can read, as script values
+fn build_scope(data: &AppData, ship_symbol: &str) -> Result<Scope<'static>, String> {
+    let ship = data
+        .ships
+        .iter()
+        .find(|s| s.ship.symbol == ship_symbol)
+        .ok_or_else(|| format!("Unknown ship: {}", ship_symbol))?;
+
+    let to_dynamic = |v: Result<Dynamic, Box<rhai::EvalAltResult>>| v.map_err(|e| e.to_string());
+
+    let mut scope = Scope::new();
+    scope.push_constant("ship", to_dynamic(rhai::serde::to_dynamic(&ship.ship))?);
+    scope.push_constant(
+        "ships",
+        to_dynamic(rhai::serde::to_dynamic(
+            data.ships.iter().map(|s| &s.ship).collect::<Vec<_>>(),
+        ))?,
+    );
+    scope.push_constant(
+        "waypoints",
+        to_dynamic(rhai::serde::to_dynamic(&data.waypoints))?,
+    );
+    scope.push_constant(
+        "markets",
+        to_dynamic(rhai::serde::to_dynamic(&data.markets))?,
+    );
+    scope.push_constant(
+        "contracts",
+        to_dynamic(rhai::serde::to_dynamic(&data.contracts))?,
+    );
+    scope.push_constant("agent", to_dynamic(rhai::serde::to_dynamic(&data.agent))?);
+    Ok(scope)
+}
+
+/// Carries out one command, returning a cooldown if it started one
+fn apply(data: &mut AppData, command: ScriptCommand) -> Result<Option<i64>, String> {
+    match command {
+        ScriptCommand::Navigate(ship, waypoint) => fleet_ops::navigate(data, &ship, &waypoint)?,
+        ScriptCommand::Dock(ship) => fleet_ops::dock(data, &ship)?,
+        ScriptCommand::Orbit(ship) => fleet_ops::orbit(data, &ship)?,
+        ScriptCommand::Extract(ship) => return fleet_ops::extract(data, &ship).map(Some),
+        ScriptCommand::Sell(ship, trade, units) => fleet_ops::sell(data, &ship, &trade, units)?,
+        ScriptCommand::Refuel(ship) => fleet_ops::refuel(data, &ship)?,
+        ScriptCommand::Deliver(ship, contract, trade, units) => {
+            fleet_ops::deliver(data, &ship, &contract, &trade, units)?
+        }
+        ScriptCommand::Transfer(ship, to, trade, units) => {
+            fleet_ops::transfer(data, &ship, &to, &trade, units)?
+        }
+    }
+    Ok(None)
+}
+
+/// Reloads changed scripts, then runs each assigned script once for its ship
+pub fn tick(data: &mut AppData) {
+    data.scripts.reload();
+
+    let time = now();
+    let mut assignments = std::mem::take(&mut data.scripts.assignments);
+
+    for assignment in assignments.iter_mut() {
+        if assignment.cooldown_until > time {
+            continue;
+        }
+
+        let ast = match data
+            .scripts
+            .scripts
+            .iter()
+            .find(|s| s.name == assignment.script)
+            .and_then(|s| s.ast.clone())
+        {
+            Some(ast) => ast,
+            None => continue,
+        };
+
+        let result = build_scope(data, &assignment.ship_symbol).and_then(|mut scope| {
+            let engine = data.scripts.engine.get_or_insert_with(ScriptEngine::new);
+            engine
+                .engine
+                .run_ast_with_scope(&mut scope, &ast)
+                .map_err(|e| e.to_string())
+        });
+
+        let (commands, output) = match &data.scripts.engine {
+            Some(engine) => (
+                std::mem::take(&mut *engine.commands.lock().unwrap()),
+                std::mem::take(&mut *engine.output.lock().unwrap()),
+            ),
+            None => (vec![], vec![]),
+        };
+
+        let name = assignment.script.clone();
+        for line in output {
+            data.scripts
+                .log(&name, format!("{}: {}", assignment.ship_symbol, line));
+        }
+        if let Err(e) = result {
+            data.scripts
+                .log(&name, format!("{}: error: {}", assignment.ship_symbol, e));
+            continue;
+        }
+
+        for command in commands {
+            match apply(data, command) {
+                Ok(Some(cooldown)) => assignment.cooldown_until = time + cooldown,
+                Ok(None) => {}
+                Err(e) => {
+                    data.scripts
+                        .log(&name, format!("{}: {}", assignment.ship_symbol, e));
+                    break;
+                }
+            }
+        }
+    }
+
+    // Keep any assignments that were made during the round
+    assignments.append(&mut data.scripts.assignments);
+    data.scripts.assignments = assignments;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty scripts folder for one test
+    fn scripts_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("cyan_scripts_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn script<'a>(runner: &'a ScriptRunner, name: &str) -> &'a Script {
+        runner.scripts.iter().find(|s| s.name == name).unwrap()
+    }
+
+    fn names(runner: &ScriptRunner) -> Vec<&str> {
+        let mut names: Vec<&str> = runner.scripts.iter().map(|s| s.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn assigns_one_script_per_ship() {
+        let mut runner = ScriptRunner::default();
+        runner.assign("SHIP-1", Some("mine"));
+        runner.assign("SHIP-2", Some("mine"));
+        runner.assign("SHIP-1", Some("trade"));
+        assert_eq!(runner.script_for("SHIP-1"), Some("trade"));
+        assert_eq!(runner.script_for("SHIP-2"), Some("mine"));
+        assert_eq!(runner.assignments.len(), 2);
+
+        runner.assign("SHIP-2", None);
+        assert_eq!(runner.script_for("SHIP-2"), None);
+        assert_eq!(runner.assignments.len(), 1);
+    }
+
+    #[test]
+    fn loads_new_scripts_and_reports_ones_that_do_not_compile() {
+        let dir = scripts_dir("new");
+        std::fs::write(dir.join("good.rhai"), "let x = 1;").unwrap();
+        std::fs::write(dir.join("bad.rhai"), "let = ;").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+        let mut runner = ScriptRunner::default();
+        runner.reload_from(&dir);
+        assert_eq!(names(&runner), vec!["bad", "good"]);
+        assert!(script(&runner, "good").ast.is_some());
+        assert!(script(&runner, "good").error.is_none());
+        assert!(script(&runner, "bad").ast.is_none());
+        assert!(script(&runner, "bad").error.is_some());
+        assert_eq!(runner.logs["good"], vec!["Loaded".to_owned()]);
+        assert!(runner.logs["bad"][0].starts_with("Failed to compile"));
+
+        // Nothing changed, so nothing is compiled again
+        runner.reload_from(&dir);
+        assert_eq!(runner.logs["good"].len(), 1);
+        assert_eq!(runner.logs["bad"].len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recompiles_changed_scripts() {
+        let dir = scripts_dir("changed");
+        std::fs::write(dir.join("fixed.rhai"), "let = ;").unwrap();
+        let mut runner = ScriptRunner::default();
+        runner.reload_from(&dir);
+        assert!(script(&runner, "fixed").error.is_some());
+
+        std::fs::write(dir.join("fixed.rhai"), "let x = 1;").unwrap();
+        // Rewrites can land within the file system's timestamp resolution, so make sure
+        // the time we saw differs from the file's
+        runner.scripts[0].modified = Some(SystemTime::UNIX_EPOCH);
+        runner.reload_from(&dir);
+        assert!(script(&runner, "fixed").ast.is_some());
+        assert!(script(&runner, "fixed").error.is_none());
+        assert_eq!(runner.logs["fixed"].last().unwrap(), "Loaded");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forgets_deleted_scripts() {
+        let dir = scripts_dir("deleted");
+        std::fs::write(dir.join("kept.rhai"), "let x = 1;").unwrap();
+        std::fs::write(dir.join("gone.rhai"), "let x = 2;").unwrap();
+        let mut runner = ScriptRunner::default();
+        runner.reload_from(&dir);
+        assert_eq!(names(&runner), vec!["gone", "kept"]);
+
+        std::fs::remove_file(dir.join("gone.rhai")).unwrap();
+        runner.reload_from(&dir);
+        assert_eq!(names(&runner), vec!["kept"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+use spacedust::models::*;
+
+use crate::behaviors::{nearest_market, travel_to, Outcome, ShipEvent};
+use crate::cargo::free_space;
+use crate::contract_runner::{buys, can_mine};
+use crate::fleet_ops;
+use crate::history::now;
+use crate::navigation::system_of;
+use crate::spacetraders::ShipWithNav;
+use crate::AppData;
+
+// Drones stay at the asteroid field extracting and hand their cargo to the hauler
+// whenever it's there. Only the hauler flies to market, so the drones never stop mining
+// for the trip
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum HaulerState {
+    /// Waiting at the field for the drones to fill it up
+    Collecting,
+    ToMarket(String),
+    Selling,
+    Returning,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Swarm {
+    pub name: String,
+    /// Asteroid field the drones work
+    pub field: String,
+    pub hauler: String,
+    pub drones: Vec<String>,
+    pub state: HaulerState,
+    /// When each drone can extract again
+    #[serde(skip)]
+    pub cooldowns: HashMap<String, i64>,
+}
+
+impl Swarm {
+    pub fn cooldown_until(&self, ship_symbol: &str) -> i64 {
+        self.cooldowns.get(ship_symbol).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SwarmForm {
+    pub name: String,
+    pub field: String,
+    pub hauler: String,
+    pub drones: Vec<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Swarms {
+    pub swarms: Vec<Swarm>,
+    #[serde(skip)]
+    pub form: SwarmForm,
+}
+
+impl Swarms {
+    /// The swarm a ship works in, as either its hauler or one of its drones
+    pub fn swarm_of(&self, ship_symbol: &str) -> Option<&Swarm> {
+        self.swarms
+            .iter()
+            .find(|s| s.hauler == ship_symbol || s.drones.iter().any(|d| d == ship_symbol))
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.swarms.retain(|s| s.name != name);
+    }
+
+    /// Drops a ship from its swarm. A swarm can't go on without its hauler, so losing
+    /// that ends the swarm
+    pub fn drop_ship(&mut self, ship_symbol: &str) {
+        self.swarms.retain(|s| s.hauler != ship_symbol);
+        for swarm in self.swarms.iter_mut() {
+            swarm.drones.retain(|d| d != ship_symbol);
+            swarm.cooldowns.remove(ship_symbol);
+        }
+    }
+
+    fn validate_form(&self) -> Result<(), String> {
+        let form = &self.form;
+        if form.name.len() == 0 {
+            return Err("Name the swarm".to_owned());
+        }
+        if self.swarms.iter().any(|s| s.name == form.name) {
+            return Err(format!("There is already a swarm called {}", form.name));
+        }
+        if form.field.len() == 0 {
+            return Err("Pick an asteroid field".to_owned());
+        }
+        if form.hauler.len() == 0 {
+            return Err("Pick a hauler".to_owned());
+        }
+        if form.drones.len() == 0 {
+            return Err("Pick at least one drone".to_owned());
+        }
+        Ok(())
+    }
+
+    /// `busy` holds ships something else is driving, see `fleet_table::automated_ships`
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        ships: &[ShipWithNav],
+        waypoints: &[Waypoint],
+        busy: &HashMap<String, String>,
+    ) {
+        let mut disband = None;
+        for swarm in &self.swarms {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} at {}", swarm.name, swarm.field));
+                if ui.button("Disband").clicked() {
+                    disband = Some(swarm.name.clone());
+                }
+            });
+            let state = match &swarm.state {
+                HaulerState::Collecting => "collecting".to_owned(),
+                HaulerState::ToMarket(m) => format!("taking cargo to {}", m),
+                HaulerState::Selling => "selling".to_owned(),
+                HaulerState::Returning => "returning".to_owned(),
+            };
+            let hauler = ships.iter().find(|s| s.ship.symbol == swarm.hauler);
+            match hauler {
+                Some(h) => ui.label(format!(
+                    "\tHauler {}: {}, cargo {}/{}",
+                    swarm.hauler, state, h.ship.cargo.units, h.ship.cargo.capacity
+                )),
+                None => ui.label(format!("\tHauler {}: not in fleet", swarm.hauler)),
+            };
+            for drone in &swarm.drones {
+                let cargo = ships
+                    .iter()
+                    .find(|s| &s.ship.symbol == drone)
+                    .map(|s| format!("cargo {}/{}", s.ship.cargo.units, s.ship.cargo.capacity))
+                    .unwrap_or("not in fleet".to_owned());
+                ui.label(format!("\tDrone {}: {}", drone, cargo));
+            }
+        }
+        if let Some(name) = disband {
+            self.remove(&name);
+        }
+
+        ui.separator();
+        ui.label("New swarm");
+        // Ships already in a swarm or doing something else can't join
+        let free: Vec<&ShipWithNav> = ships
+            .iter()
+            .filter(|s| self.swarm_of(&s.ship.symbol).is_none())
+            .filter(|s| !busy.contains_key(&s.ship.symbol))
+            .collect();
+        let form = &mut self.form;
+
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut form.name);
+        });
+        egui::ComboBox::from_label("Asteroid field")
+            .selected_text(&form.field)
+            .show_ui(ui, |ui| {
+                for w in waypoints
+                    .iter()
+                    .filter(|w| w.r#type == WaypointType::AsteroidField)
+                {
+                    ui.selectable_value(&mut form.field, w.symbol.clone(), &w.symbol);
+                }
+            });
+        egui::ComboBox::from_label("Hauler")
+            .selected_text(&form.hauler)
+            .show_ui(ui, |ui| {
+                for s in &free {
+                    ui.selectable_value(&mut form.hauler, s.ship.symbol.clone(), &s.ship.symbol);
+                }
+            });
+        ui.label("Drones:");
+        for s in free
+            .iter()
+            .filter(|s| can_mine(s) && s.ship.symbol != form.hauler)
+        {
+            let mut selected = form.drones.contains(&s.ship.symbol);
+            if ui.checkbox(&mut selected, &s.ship.symbol).changed() {
+                if selected {
+                    form.drones.push(s.ship.symbol.clone());
+                } else {
+                    form.drones.retain(|d| d != &s.ship.symbol);
+                }
+            }
+        }
+        form.drones.retain(|d| d != &form.hauler);
+
+        let check = self.validate_form();
+        let button = ui.add_enabled(check.is_ok(), egui::Button::new("Create swarm"));
+        if let Err(e) = check {
+            button.on_disabled_hover_text(e);
+        } else if button.clicked() {
+            let form = std::mem::take(&mut self.form);
+            self.swarms.push(Swarm {
+                name: form.name,
+                field: form.field,
+                hauler: form.hauler,
+                drones: form.drones,
+                state: HaulerState::Collecting,
+                cooldowns: HashMap::new(),
+            });
+        }
+    }
+}
+
+fn ship_of(data: &AppData, ship_symbol: &str) -> Option<Ship> {
+    data.ships
+        .iter()
+        .find(|s| s.ship.symbol == ship_symbol)
+        .map(|s| s.ship.clone())
+}
+
+fn in_transit(ship: &Ship) -> bool {
+    matches!(ship.nav.status, ShipNavStatus::InTransit)
+}
+
+/// What a market would pay for everything in the hold, if it buys any of it
+fn cargo_value(market: &Market, ship: &Ship) -> Option<i64> {
+    let goods = market.trade_goods.as_ref()?;
+    let value: i64 = ship
+        .cargo
+        .inventory
+        .iter()
+        .filter_map(|c| {
+            goods
+                .iter()
+                .find(|g| g.symbol == c.symbol)
+                .map(|g| g.sell_price as i64 * c.units as i64)
+        })
+        .sum();
+    if value > 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// The market in the field's system paying the most for the hauler's cargo, or the
+/// nearest one if we don't know any prices there yet
+fn best_market(data: &AppData, hauler: &Ship, field: &str) -> Option<String> {
+    let best = data
+        .markets
+        .iter()
+        .filter(|m| system_of(&m.symbol) == system_of(field))
+        .filter_map(|m| cargo_value(m, hauler).map(|v| (v, m)))
+        .max_by_key(|(v, _)| *v);
+    match best {
+        Some((_, m)) => Some(m.symbol.clone()),
+        None => nearest_market(data, field),
+    }
+}
+
+fn step_hauler(data: &mut AppData, swarm: &mut Swarm) -> Result<(), String> {
+    let hauler = match ship_of(data, &swarm.hauler) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+    if in_transit(&hauler) {
+        return Ok(());
+    }
+
+    match swarm.state.clone() {
+        HaulerState::Collecting => {
+            if hauler.nav.waypoint_symbol != swarm.field {
+                travel_to(data, &hauler, &swarm.field)?;
+                return Ok(());
+            }
+            // Transfers need both ships in orbit, which is where the drones mine
+            if let ShipNavStatus::Docked = hauler.nav.status {
+                fleet_ops::orbit(data, &hauler.symbol)?;
+            }
+            if free_space(&hauler) == 0 {
+                let market = match best_market(data, &hauler, &swarm.field) {
+                    Some(m) => m,
+                    None => return Err("no known market near the field".to_owned()),
+                };
+                data.log.push(format!(
+                    "{}: {} is full, taking cargo to {}",
+                    swarm.name, hauler.symbol, market
+                ));
+                swarm.state = HaulerState::ToMarket(market);
+            }
+        }
+        HaulerState::ToMarket(market) => {
+            if let Outcome::Event(ShipEvent::Arrived) = travel_to(data, &hauler, &market)? {
+                swarm.state = HaulerState::Selling;
+            }
+        }
+        HaulerState::Selling => {
+            if !matches!(hauler.nav.status, ShipNavStatus::Docked) {
+                fleet_ops::dock(data, &hauler.symbol)?;
+            }
+            let market = data
+                .markets
+                .iter()
+                .find(|m| m.symbol == hauler.nav.waypoint_symbol)
+                .cloned();
+            for item in &hauler.cargo.inventory {
+                match market.as_ref().map(|m| buys(m, &item.symbol)) {
+                    // Goods nobody here wants would only fill the hold on every trip
+                    Some(false) => {
+                        fleet_ops::jettison_cargo(data, &hauler.symbol, &item.symbol, item.units)?
+                    }
+                    // A failed sale keeps us selling until it goes through. Without the
+                    // market's data we can't tell, so keep trying until it's fetched
+                    _ => fleet_ops::sell(data, &hauler.symbol, &item.symbol, item.units)?,
+                }
+            }
+            swarm.state = HaulerState::Returning;
+        }
+        HaulerState::Returning => {
+            if let Outcome::Event(ShipEvent::Arrived) = travel_to(data, &hauler, &swarm.field)? {
+                swarm.state = HaulerState::Collecting;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Empties a drone into the hauler if it's waiting at the field, then extracts again
+fn step_drone(
+    data: &mut AppData,
+    swarm: &mut Swarm,
+    drone_symbol: &str,
+    time: i64,
+) -> Result<(), String> {
+    let drone = match ship_of(data, drone_symbol) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+    if in_transit(&drone) {
+        return Ok(());
+    }
+    if drone.nav.waypoint_symbol != swarm.field {
+        travel_to(data, &drone, &swarm.field)?;
+        return Ok(());
+    }
+    if let ShipNavStatus::Docked = drone.nav.status {
+        fleet_ops::orbit(data, &drone.symbol)?;
+    }
+
+    let hauler = ship_of(data, &swarm.hauler);
+    if let Some(hauler) = hauler {
+        let waiting = swarm.state == HaulerState::Collecting
+            && hauler.nav.waypoint_symbol == swarm.field
+            && matches!(hauler.nav.status, ShipNavStatus::InOrbit);
+        if waiting {
+            let mut space = free_space(&hauler);
+            for item in &drone.cargo.inventory {
+                let units = item.units.min(space);
+                if units == 0 {
+                    break;
+                }
+                fleet_ops::transfer(data, &drone.symbol, &hauler.symbol, &item.symbol, units)?;
+                space -= units;
+            }
+        }
+    }
+
+    if swarm.cooldown_until(drone_symbol) > time {
+        return Ok(());
+    }
+    // A full drone waits for the hauler to come back
+    match ship_of(data, drone_symbol) {
+        Some(drone) if free_space(&drone) > 0 => {
+            let cooldown = fleet_ops::extract(data, drone_symbol)?;
+            swarm
+                .cooldowns
+                .insert(drone_symbol.to_owned(), time + cooldown);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Runs one round of every swarm: the hauler first, so drones see where it is
+pub fn tick(data: &mut AppData) {
+    let time = now();
+    let mut swarms = std::mem::take(&mut data.swarms.swarms);
+
+    for swarm in swarms.iter_mut() {
+        if let Err(e) = step_hauler(data, swarm) {
+            data.report_error(format!("{}: {}", swarm.name, e));
+        }
+        for drone in swarm.drones.clone() {
+            if let Err(e) = step_drone(data, swarm, &drone, time) {
+                data.report_error(format!("{}: {}", swarm.name, e));
+            }
+        }
+    }
+
+    // Keep any swarms that were created during the round
+    swarms.append(&mut data.swarms.swarms);
+    data.swarms.swarms = swarms;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hauler(cargo: &[(&str, i32)]) -> Ship {
+        let mut ship = Ship::default();
+        ship.symbol = "HAULER".to_owned();
+        ship.cargo.inventory = cargo
+            .iter()
+            .map(|(symbol, units)| ShipCargoItem {
+                symbol: symbol.to_string(),
+                units: *units,
+                ..Default::default()
+            })
+            .collect();
+        ship
+    }
+
+    fn market(symbol: &str, prices: &[(&str, i32)]) -> Market {
+        Market {
+            symbol: symbol.to_owned(),
+            trade_goods: Some(
+                prices
+                    .iter()
+                    .map(|(good, price)| MarketTradeGood {
+                        symbol: good.to_string(),
+                        sell_price: *price,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn waypoint(symbol: &str, x: i32, marketplace: bool) -> Waypoint {
+        Waypoint {
+            symbol: symbol.to_owned(),
+            system_symbol: system_of(symbol).to_owned(),
+            x,
+            traits: if marketplace {
+                vec![WaypointTrait {
+                    symbol: waypoint_trait::Symbol::Marketplace,
+                    ..Default::default()
+                }]
+            } else {
+                vec![]
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn values_the_goods_a_market_buys() {
+        let hauler = hauler(&[("IRON_ORE", 10), ("QUARTZ_SAND", 5), ("ICE_WATER", 3)]);
+        let market = market("X1-A-M", &[("IRON_ORE", 20), ("QUARTZ_SAND", 4)]);
+        assert_eq!(cargo_value(&market, &hauler), Some(220));
+    }
+
+    #[test]
+    fn no_value_where_nothing_in_the_hold_is_bought() {
+        let ice = hauler(&[("ICE_WATER", 3)]);
+        assert_eq!(
+            cargo_value(&market("X1-A-M", &[("IRON_ORE", 20)]), &ice),
+            None
+        );
+        let unpriced = Market {
+            symbol: "X1-A-M".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(cargo_value(&unpriced, &ice), None);
+        assert_eq!(
+            cargo_value(&market("X1-A-M", &[("IRON_ORE", 20)]), &hauler(&[])),
+            None
+        );
+    }
+
+    #[test]
+    fn sells_where_the_hold_is_worth_most_in_the_field_system() {
+        let mut data = AppData::default();
+        data.markets = vec![
+            market("X1-A-CHEAP", &[("IRON_ORE", 10)]),
+            market("X1-A-DEAR", &[("IRON_ORE", 30)]),
+            market("X1-B-DEAREST", &[("IRON_ORE", 50)]),
+        ];
+        let hauler = hauler(&[("IRON_ORE", 10)]);
+        assert_eq!(
+            best_market(&data, &hauler, "X1-A-FIELD"),
+            Some("X1-A-DEAR".to_owned())
+        );
+    }
+
+    #[test]
+    fn sells_at_the_nearest_market_without_known_prices() {
+        let mut data = AppData::default();
+        data.waypoints = vec![
+            waypoint("X1-A-FIELD", 0, false),
+            waypoint("X1-A-FAR", 100, true),
+            waypoint("X1-A-NEAR", 10, true),
+            waypoint("X1-B-NEARER", 1, true),
+        ];
+        data.markets = vec![market("X1-B-NEARER", &[("IRON_ORE", 50)])];
+        let hauler = hauler(&[("IRON_ORE", 10)]);
+        assert_eq!(
+            best_market(&data, &hauler, "X1-A-FIELD"),
+            Some("X1-A-NEAR".to_owned())
+        );
+        data.waypoints.clear();
+        assert_eq!(best_market(&data, &hauler, "X1-A-FIELD"), None);
+    }
+}
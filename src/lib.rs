@@ -5,3 +5,30 @@ pub use app::AppState;
 pub use app::AppData;
 pub use app::api::spacetraders;
 pub use app::api::message_handler;
+pub use app::history;
+pub use app::inspector;
+pub use app::behaviors;
+pub use app::cargo;
+pub use app::catalogue;
+pub use app::config;
+pub use app::contract_runner;
+pub use app::contracts;
+#[cfg(not(target_arch = "wasm32"))]
+pub use app::control_api;
+#[cfg(not(target_arch = "wasm32"))]
+pub use app::database;
+pub use app::events;
+pub use app::exploration;
+pub use app::fleet_ops;
+pub use app::fleet_table;
+pub use app::groups;
+pub use app::ledger;
+pub use app::maintenance;
+pub use app::map;
+pub use app::mounts;
+pub use app::navigation;
+pub use app::notifications;
+pub use app::persistence;
+pub use app::route_runner;
+pub use app::scripting;
+pub use app::swarms;
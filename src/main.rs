@@ -1,38 +1,74 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use crossbeam_queue::SegQueue;
-use cyan_fleet_control::message_handler::Message;
+use cyan_fleet_control::config::Config;
 use cyan_fleet_control::message_handler::MessageHandler;
+use cyan_fleet_control::message_handler::Scheduler;
+use cyan_fleet_control::message_handler::IDLE_DELAY;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 #[tokio::main]
 async fn main() -> eframe::Result<()> {
-    // Log to stdout (if you run with `RUST_LOG=debug`).
-    tracing_subscriber::fmt::init();
+    let config = match Config::load(std::env::args().skip(1)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // Log to stdout at the configured level
+    let level = config
+        .log_level()
+        .parse::<tracing_subscriber::filter::LevelFilter>()
+        .unwrap_or(tracing_subscriber::filter::LevelFilter::INFO);
+    tracing_subscriber::fmt().with_max_level(level).init();
 
     let rt = tokio::runtime::Runtime::new().expect("Unable to create Runtime");
 
     // Enter the runtime so that `tokio::spawn` is available immediately.
     let _enter = rt.enter();
 
-    let state = std::sync::Arc::new(std::sync::Mutex::new(cyan_fleet_control::AppData::new()));
+    let api_port = config.api_port();
+    let state = std::sync::Arc::new(std::sync::Mutex::new(cyan_fleet_control::AppData::new(
+        config,
+    )));
+
+    let mut scheduler = Scheduler::default();
+    scheduler.requested = state.lock().unwrap().requests();
+    if let Some(port) = api_port {
+        rt.spawn(cyan_fleet_control::control_api::serve(
+            port,
+            state.clone(),
+            scheduler.requested.clone(),
+        ));
+    }
 
     let poll_state = state.clone();
 
     std::thread::spawn(move || {
-        let queue = SegQueue::<Message>::new();
-        for message in <Message as strum::IntoEnumIterator>::iter() {
-            queue.push(message);
-        }
-
         rt.block_on(async {
             loop {
-                let m = queue.pop().unwrap();
-                queue.push(m);
-                MessageHandler.handle_message(&m, poll_state.clone()).await;
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                // Settings can change while running, so read them every time round
+                let (config, loaded) = {
+                    let data = poll_state.lock().unwrap();
+                    (data.config().clone(), data.is_loaded())
+                };
+                // Wait for the app to restore the last session, which would overwrite
+                // anything fetched before it
+                let message = if loaded {
+                    scheduler.next(&config)
+                } else {
+                    None
+                };
+                match message {
+                    Some(m) => {
+                        MessageHandler.handle_message(&m, poll_state.clone()).await;
+                        tokio::time::sleep(config.message_delay()).await;
+                    }
+                    None => tokio::time::sleep(config.message_delay().max(IDLE_DELAY)).await,
+                }
             }
         })
     });
@@ -58,11 +94,44 @@ fn main() {
 
     let web_options = eframe::WebOptions::default();
 
+    // No config file or environment in the browser, so start from defaults. Saved
+    // settings are picked up from local storage when the app is created
+    let state = std::sync::Arc::new(std::sync::Mutex::new(cyan_fleet_control::AppData::new(
+        Config::default(),
+    )));
+
+    let poll_state = state.clone();
+
+    // Browsers don't have threads to block, so the message loop is a task on the page's
+    // event loop, waiting on a timer between messages
+    wasm_bindgen_futures::spawn_local(async move {
+        let mut scheduler = Scheduler::default();
+        scheduler.requested = poll_state.lock().unwrap().requests();
+        loop {
+            let (config, loaded) = {
+                let data = poll_state.lock().unwrap();
+                (data.config().clone(), data.is_loaded())
+            };
+            let message = if loaded {
+                scheduler.next(&config)
+            } else {
+                None
+            };
+            match message {
+                Some(m) => {
+                    MessageHandler.handle_message(&m, poll_state.clone()).await;
+                    gloo_timers::future::sleep(config.message_delay()).await;
+                }
+                None => gloo_timers::future::sleep(config.message_delay().max(IDLE_DELAY)).await,
+            }
+        }
+    });
+
     wasm_bindgen_futures::spawn_local(async {
         eframe::start_web(
             "the_canvas_id", // hardcode it
             web_options,
-            Box::new(|cc| Box::new(cyan_fleet_control::TemplateApp::new(cc))),
+            Box::new(|cc| Box::new(cyan_fleet_control::AppState::new(cc, state))),
         )
         .await
         .expect("failed to start eframe");